@@ -0,0 +1,189 @@
+use std::{fmt::Write as _, str::FromStr, time::Instant};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    arg_value, headless, Args, Dimension, GfxData, SimData, SimSettings, Solver, ViewSettings,
+};
+
+const SPECIE_N: usize = 6;
+// frames before timing, so creating the pipelines isn't counted
+const WARMUP_FRAME_N: usize = 4;
+
+/// the timing of one combination of settings
+#[derive(serde::Serialize)]
+struct BenchResult {
+    solver: String,
+    particle_n: usize,
+    substep_n: usize,
+    local_radius: f32,
+    frame_n: usize,
+    // includes waiting for the gpu after every frame
+    wall_ms_per_tick: f64,
+    // None if the gpu doesn't support timestamp queries
+    gpu_ms_per_tick: Option<f64>,
+    // counted as particle_n^2 per tick for every solver, from the gpu time if there is one
+    pairs_per_second: f64,
+}
+
+#[derive(serde::Serialize)]
+struct BenchReport {
+    adapter: String,
+    backend: String,
+    dimension: String,
+    workgroup_size: u32,
+    results: Vec<BenchResult>,
+}
+
+/// runs the simulation headless for every combination of the swept settings, and writes the timings
+/// to `{out}.csv` and `{out}.json`, where `out` is `--bench-out` or "bench"
+/// the sweeps are comma separated lists, like `--bench-particle-n 1000,5000,20000`
+pub(crate) fn run(args: &Args) {
+    let particle_ns = list_arg("--bench-particle-n", &[1000, 5000, 20000]);
+    let substep_ns = list_arg("--bench-substep-n", &[1, 8]);
+    let local_radii = list_arg("--bench-local-radius", &[0.05, 0.1]);
+    let solvers = match args.dimension {
+        Dimension::Two => list_arg("--bench-solver", &Solver::ALL),
+        // the other solvers are only implemented in 2d
+        Dimension::Three => vec![Solver::Pairwise],
+    };
+    let frame_n: usize = arg_value("--bench-frames")
+        .map(|frame_n| frame_n.parse().expect("--bench-frames should be a number"))
+        .unwrap_or(32);
+    let out = arg_value("--bench-out").unwrap_or_else(|| "bench".to_owned());
+
+    let render_state = headless::create_render_state();
+    let adapter_info = render_state.adapter.get_info();
+    let mut results = Vec::new();
+    for &particle_n in &particle_ns {
+        for &solver in &solvers {
+            for &substep_n in &substep_ns {
+                for &local_radius in &local_radii {
+                    // the same attractions and starting positions every run, so runs can be compared
+                    let mut rng = StdRng::seed_from_u64(0);
+                    let view_settings = ViewSettings::new(args.dimension, SPECIE_N, particle_n);
+                    let sim_settings = SimSettings {
+                        solver,
+                        substep_n,
+                        local_radius,
+                        workgroup_size: args.workgroup_size,
+                        attractions: (0..SPECIE_N)
+                            .map(|_| (0..SPECIE_N).map(|_| rng.gen_range(-1.0..=1.0)).collect())
+                            .collect(),
+                        dt: 1.0 / 60.0,
+                        ..SimSettings::new(args.dimension, SPECIE_N, particle_n)
+                    };
+                    let mut gfx_data = match args.dimension {
+                        Dimension::Two => GfxData::new(
+                            &render_state,
+                            &view_settings,
+                            &sim_settings,
+                            &SimData::from_rng(&mut rng, SPECIE_N, particle_n),
+                        ),
+                        Dimension::Three => GfxData::new(
+                            &render_state,
+                            &view_settings,
+                            &sim_settings,
+                            &SimData::from_rng_3d(&mut rng, SPECIE_N, particle_n),
+                        ),
+                    };
+
+                    // the sorter is made on the first sort, so warm up past that too
+                    let warmup_frame_n = WARMUP_FRAME_N
+                        .max((sim_settings.sort_interval as usize).div_ceil(substep_n) + 1);
+                    for _ in 0..warmup_frame_n {
+                        gfx_data.step(&view_settings, &sim_settings);
+                        gfx_data.wait();
+                        gfx_data.take_gpu_times();
+                    }
+                    let start = Instant::now();
+                    let mut gpu_ms = 0.0;
+                    for _ in 0..frame_n {
+                        gfx_data.step(&view_settings, &sim_settings);
+                        gfx_data.wait();
+                        if let Some(gpu_times) = gfx_data.take_gpu_times() {
+                            gpu_ms += gpu_times.compute_ms as f64;
+                        }
+                    }
+                    let tick_n = (frame_n * substep_n) as f64;
+                    let wall_ms_per_tick = start.elapsed().as_secs_f64() * 1000.0 / tick_n;
+                    let gpu_ms_per_tick = gfx_data.has_gpu_timer().then_some(gpu_ms / tick_n);
+
+                    let result = BenchResult {
+                        solver: format!("{solver:?}"),
+                        particle_n,
+                        substep_n,
+                        local_radius,
+                        frame_n,
+                        wall_ms_per_tick,
+                        gpu_ms_per_tick,
+                        pairs_per_second: particle_n as f64 * particle_n as f64 * 1000.0
+                            / gpu_ms_per_tick.unwrap_or(wall_ms_per_tick),
+                    };
+                    println!(
+                        "{:>14} particle_n: {:>7} substep_n: {:>2} local_radius: {:.3}  wall: {:.4} ms/tick  gpu: {} ms/tick  pairs/s: {:.3e}",
+                        result.solver,
+                        result.particle_n,
+                        result.substep_n,
+                        result.local_radius,
+                        result.wall_ms_per_tick,
+                        result
+                            .gpu_ms_per_tick
+                            .map_or("n/a".to_owned(), |gpu_ms| format!("{gpu_ms:.4}")),
+                        result.pairs_per_second,
+                    );
+                    results.push(result);
+                }
+            }
+        }
+    }
+
+    let mut csv = "solver,particle_n,substep_n,local_radius,frame_n,wall_ms_per_tick,gpu_ms_per_tick,pairs_per_second\n".to_owned();
+    for result in &results {
+        writeln!(
+            csv,
+            "{},{},{},{},{},{},{},{}",
+            result.solver,
+            result.particle_n,
+            result.substep_n,
+            result.local_radius,
+            result.frame_n,
+            result.wall_ms_per_tick,
+            result
+                .gpu_ms_per_tick
+                .map_or(String::new(), |gpu_ms| gpu_ms.to_string()),
+            result.pairs_per_second,
+        )
+        .unwrap();
+    }
+    std::fs::write(format!("{out}.csv"), csv).expect("should be able to write the csv");
+
+    let report = BenchReport {
+        adapter: adapter_info.name,
+        backend: format!("{:?}", adapter_info.backend),
+        dimension: format!("{:?}", args.dimension),
+        workgroup_size: args.workgroup_size,
+        results,
+    };
+    std::fs::write(
+        format!("{out}.json"),
+        serde_json::to_string_pretty(&report).unwrap(),
+    )
+    .expect("should be able to write the json");
+    println!("wrote {out}.csv and {out}.json");
+}
+
+/// a comma separated list after `name`, or `default`
+pub(crate) fn list_arg<T: FromStr + Clone>(name: &str, default: &[T]) -> Vec<T> {
+    match arg_value(name) {
+        Some(list) => list
+            .split(',')
+            .map(|item| {
+                item.trim().parse().unwrap_or_else(|_| {
+                    panic!("{name} should be a comma separated list, couldn't parse {item:?}")
+                })
+            })
+            .collect(),
+        None => default.to_vec(),
+    }
+}
@@ -0,0 +1,239 @@
+use eframe::wgpu;
+
+use crate::gfx::read_buffer;
+
+// how many particles get their force compared by `BarnesHut::measure_error`
+const PROBE_N: usize = 1024;
+
+/// gpu state for the barnes-hut solver, see bh.wgsl
+pub(crate) struct BarnesHut {
+    pub(crate) depth: u32,
+    leaf_buffer: wgpu::Buffer,
+    // only used on the gpu, but the bind group needs it to stay alive
+    _node_buffer: wgpu::Buffer,
+    probe_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    particles_per_group: usize,
+    deposit_pipeline: wgpu::ComputePipeline,
+    build_pipeline: wgpu::ComputePipeline,
+    integrate_pipeline: wgpu::ComputePipeline,
+    probe_pipeline: wgpu::ComputePipeline,
+}
+impl BarnesHut {
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        shader_module: &wgpu::ShaderModule,
+        compute_bind_group_layout: &wgpu::BindGroupLayout,
+        particles_per_group: usize,
+        specie_n: usize,
+        depth: u32,
+    ) -> Self {
+        let leaf_n = 1_u64 << (2 * depth);
+        let leaf_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("bh_leaf_buffer"),
+            size: leaf_n * specie_n as u64 * 3 * size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let node_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("bh_node_buffer"),
+            size: node_n(depth) as u64 * specie_n as u64 * 4 * size_of::<f32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let probe_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("bh_probe_buffer"),
+            size: (PROBE_N * 4 * size_of::<f32>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let storage_entry = |binding, size| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: wgpu::BufferSize::new(size),
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("bh_bind_group_layout"),
+            entries: &[
+                storage_entry(0, leaf_buffer.size()),
+                storage_entry(1, node_buffer.size()),
+                storage_entry(2, probe_buffer.size()),
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bh_bind_group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: leaf_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: node_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: probe_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("bh_pipeline_layout"),
+            bind_group_layouts: &[compute_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: shader_module,
+                entry_point,
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+
+        Self {
+            depth,
+            deposit_pipeline: create_pipeline("bh_deposit"),
+            build_pipeline: create_pipeline("bh_build"),
+            integrate_pipeline: create_pipeline("bh_integrate"),
+            probe_pipeline: create_pipeline("bh_probe"),
+            leaf_buffer,
+            _node_buffer: node_buffer,
+            probe_buffer,
+            bind_group,
+            particles_per_group,
+        }
+    }
+
+    // clears the leaves and returns a compute pass that has built the tree
+    fn build<'a>(
+        &'a self,
+        command_encoder: &'a mut wgpu::CommandEncoder,
+        compute_bind_group: &'a wgpu::BindGroup,
+        specie_n: usize,
+        particle_n: usize,
+    ) -> wgpu::ComputePass<'a> {
+        command_encoder.clear_buffer(&self.leaf_buffer, 0, None);
+        let mut compute_pass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("bh_compute_pass"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_bind_group(0, compute_bind_group, &[]);
+        compute_pass.set_bind_group(1, &self.bind_group, &[]);
+        compute_pass.set_pipeline(&self.deposit_pipeline);
+        compute_pass.dispatch_workgroups(
+            particle_n.div_ceil(self.particles_per_group) as u32,
+            1,
+            1,
+        );
+        compute_pass.set_pipeline(&self.build_pipeline);
+        compute_pass.dispatch_workgroups(
+            (node_n(self.depth) * specie_n).div_ceil(self.particles_per_group) as u32,
+            1,
+            1,
+        );
+        compute_pass
+    }
+
+    /// does one tick, reading from and writing to the buffers in `compute_bind_group`
+    pub(crate) fn dispatch(
+        &self,
+        command_encoder: &mut wgpu::CommandEncoder,
+        compute_bind_group: &wgpu::BindGroup,
+        specie_n: usize,
+        particle_n: usize,
+    ) {
+        let mut compute_pass =
+            self.build(command_encoder, compute_bind_group, specie_n, particle_n);
+        compute_pass.set_pipeline(&self.integrate_pipeline);
+        compute_pass.dispatch_workgroups(
+            particle_n.div_ceil(self.particles_per_group) as u32,
+            1,
+            1,
+        );
+    }
+
+    /// compares the barnes-hut force against the exact pairwise force for the first `PROBE_N` particles
+    /// blocks the ui thread until the gpu is done, and the exact forces are PROBE_N * particle_n pairs,
+    /// so this is only run when "measure error" is clicked and never every frame
+    pub(crate) fn measure_error(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        compute_bind_group: &wgpu::BindGroup,
+        specie_n: usize,
+        particle_n: usize,
+    ) -> ErrorReport {
+        let mut command_encoder = device.create_command_encoder(&Default::default());
+        {
+            let mut compute_pass = self.build(
+                &mut command_encoder,
+                compute_bind_group,
+                specie_n,
+                particle_n,
+            );
+            compute_pass.set_pipeline(&self.probe_pipeline);
+            compute_pass.dispatch_workgroups(
+                PROBE_N.div_ceil(self.particles_per_group) as u32,
+                1,
+                1,
+            );
+        }
+        queue.submit([command_encoder.finish()]);
+
+        let probes: Vec<[f32; 4]> = read_buffer(device, queue, &self.probe_buffer);
+        ErrorReport::new(&probes[..PROBE_N.min(particle_n)])
+    }
+}
+
+fn node_n(depth: u32) -> usize {
+    ((1 << (2 * (depth + 1))) - 1) / 3
+}
+
+/// how far the barnes-hut forces are from the exact ones
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ErrorReport {
+    pub(crate) particle_n: usize,
+    /// sqrt(sum |bh - exact|^2 / sum |exact|^2), or 0 if every exact force is 0, where max_error still shows the error
+    pub(crate) relative_rms_error: f32,
+    pub(crate) max_error: f32,
+    pub(crate) mean_exact_force: f32,
+}
+impl ErrorReport {
+    // each probe is (exact.x, exact.y, bh.x, bh.y)
+    fn new(probes: &[[f32; 4]]) -> Self {
+        let mut error2_sum = 0.0;
+        let mut exact2_sum = 0.0;
+        let mut exact_sum = 0.0;
+        let mut max_error = 0.0_f32;
+        for [exact_x, exact_y, bh_x, bh_y] in probes.iter().copied() {
+            let error2 = (bh_x - exact_x).powi(2) + (bh_y - exact_y).powi(2);
+            let exact2 = exact_x.powi(2) + exact_y.powi(2);
+            error2_sum += error2;
+            exact2_sum += exact2;
+            exact_sum += exact2.sqrt();
+            max_error = max_error.max(error2.sqrt());
+        }
+        Self {
+            particle_n: probes.len(),
+            relative_rms_error: if exact2_sum > 0.0 {
+                (error2_sum / exact2_sum).sqrt()
+            } else {
+                0.0
+            },
+            max_error,
+            // 0 with no particles
+            mean_exact_force: exact_sum / probes.len().max(1) as f32,
+        }
+    }
+}
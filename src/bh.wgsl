@@ -0,0 +1,166 @@
+// barnes-hut style solver, appended to common.wgsl and shader.wgsl
+// the quadtree is complete with bh_depth levels below the root, so it's built by depositing particles
+// into the 2^bh_depth x 2^bh_depth leaves and then summing leaves for every node.
+// each node stores the count and center of mass of each specie (a monopole per specie).
+// the traversal skips nodes further than local_radius and uses the monopoles of nodes with
+// node_size < bh_theta * distance, so bh_theta = 0 opens everything down to the leaves
+
+// atomics don't work on floats, so offsets within a leaf are stored as fixed point
+const OFFSET_SCALE: f32 = 1024.0;
+// the stack needs 3 * bh_depth + 1 slots
+const BH_STACK_SIZE: u32 = 32;
+
+// leaves[((y * leaf_side + x) * specie_n + specie) * 3 + i] where i is 0 for the count and 1, 2 for the sum of the offsets
+@group(1) @binding(0) var<storage, read_write> leaves: array<atomic<u32>>;
+// nodes[(level_offset(level) + y * 2^level + x) * specie_n + specie] is (center_of_mass, count, 0.0)
+@group(1) @binding(1) var<storage, read_write> nodes: array<vec4<f32>>;
+// probes[index] is (pairwise_force(index), bh_force(index)) for the first arrayLength(probes) particles
+@group(1) @binding(2) var<storage, read_write> probes: array<vec4<f32>>;
+
+// the number of nodes above level
+fn level_offset(level: u32) -> u32 {
+    return ((1u << (2u * level)) - 1u) / 3u;
+}
+
+fn node_cell(pos: vec2<f32>, level: u32) -> vec2<u32> {
+    let side = 1u << level;
+    return min(vec2<u32>(pos * f32(side)), vec2(side - 1u));
+}
+
+@compute
+@workgroup_size(PARTICLES_PER_GROUP)
+fn bh_deposit(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let index = global_invocation_id.x;
+    if (index >= params.particle_n) {
+        return;
+    }
+    let pos = pos_src[index];
+    let leaf_side = 1u << params.bh_depth;
+    let leaf = node_cell(pos, params.bh_depth);
+    let offset = pos * f32(leaf_side) - vec2<f32>(leaf);
+    let base = ((leaf.y * leaf_side + leaf.x) * params.specie_n + species[index]) * 3u;
+    atomicAdd(&leaves[base], 1u);
+    atomicAdd(&leaves[base + 1u], u32(offset.x * OFFSET_SCALE));
+    atomicAdd(&leaves[base + 2u], u32(offset.y * OFFSET_SCALE));
+}
+
+// one invocation per (node, specie) on every level, each sums all the leaves under its node
+// the root reads every leaf, but the total work is only (bh_depth + 1) * 4^bh_depth * specie_n
+@compute
+@workgroup_size(PARTICLES_PER_GROUP)
+fn bh_build(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let index = global_invocation_id.x;
+    if (index >= level_offset(params.bh_depth + 1u) * params.specie_n) {
+        return;
+    }
+    let specie = index % params.specie_n;
+    let node = index / params.specie_n;
+    var level = 0u;
+    while (level_offset(level + 1u) <= node) {
+        level++;
+    }
+    let side = 1u << level;
+    let node_xy = vec2((node - level_offset(level)) % side, (node - level_offset(level)) / side);
+    let leaf_side = 1u << params.bh_depth;
+    let span = 1u << (params.bh_depth - level);
+
+    var count = 0u;
+    // in units of leaves
+    var pos_sum = vec2(0.0, 0.0);
+    for (var dy = 0u; dy < span; dy++) {
+        for (var dx = 0u; dx < span; dx++) {
+            let leaf = node_xy * span + vec2(dx, dy);
+            let base = ((leaf.y * leaf_side + leaf.x) * params.specie_n + specie) * 3u;
+            let leaf_count = atomicLoad(&leaves[base]);
+            count += leaf_count;
+            pos_sum += vec2<f32>(leaf) * f32(leaf_count)
+                + vec2(f32(atomicLoad(&leaves[base + 1u])), f32(atomicLoad(&leaves[base + 2u]))) / OFFSET_SCALE;
+        }
+    }
+    var center_of_mass = vec2(0.0, 0.0);
+    if (count > 0u) {
+        center_of_mass = pos_sum / (f32(count) * f32(leaf_side));
+    }
+    nodes[index] = vec4(center_of_mass, f32(count), 0.0);
+}
+
+fn bh_force(index: u32) -> vec2<f32> {
+    let pos = pos_src[index];
+    let specie = species[index];
+    var force = vec2(0.0, 0.0);
+
+    // nodes are packed as (level << 24) | (y * 2^level + x)
+    var stack: array<u32, BH_STACK_SIZE>;
+    var stack_n = 1u;
+    stack[0] = 0u;
+    while (stack_n > 0u) {
+        stack_n--;
+        let level = stack[stack_n] >> 24u;
+        let local_node = stack[stack_n] & 0xffffffu;
+        let side = 1u << level;
+        let node_xy = vec2(local_node % side, local_node / side);
+        let node_size = 1.0 / f32(side);
+
+        var to_center = (vec2<f32>(node_xy) + vec2(0.5, 0.5)) * node_size - pos;
+        to_center -= step(vec2(0.5, 0.5), to_center);
+        to_center += step(to_center, vec2(-0.5, -0.5));
+        // distance from the particle to the nearest point of the node
+        let gap = max(abs(to_center) - vec2(0.5 * node_size), vec2(0.0, 0.0));
+        if (dot(gap, gap) > params.local_radius2) {
+            continue;
+        }
+
+        let contains = all(node_cell(pos, level) == node_xy);
+        if (level < params.bh_depth && (contains || node_size >= params.bh_theta * length(to_center))) {
+            for (var child_i = 0u; child_i < 4u; child_i++) {
+                let child_xy = node_xy * 2u + vec2(child_i & 1u, child_i >> 1u);
+                stack[stack_n] = ((level + 1u) << 24u) | (child_xy.y * side * 2u + child_xy.x);
+                stack_n++;
+            }
+            continue;
+        }
+
+        // use the node's monopole for each specie
+        let node = level_offset(level) + local_node;
+        for (var neighbor_specie = 0u; neighbor_specie < params.specie_n; neighbor_specie++) {
+            let node_data = nodes[node * params.specie_n + neighbor_specie];
+            var count = node_data.z;
+            var center_of_mass = node_data.xy;
+            if (contains && neighbor_specie == specie) {
+                // take this particle out of its own node
+                count -= 1.0;
+                if (count < 0.5) {
+                    continue;
+                }
+                center_of_mass = (node_data.xy * node_data.z - pos) / count;
+            }
+            if (count < 0.5) {
+                continue;
+            }
+
+            force += count
+                * pair_force(pos, center_of_mass, attractions[specie * params.specie_n + neighbor_specie]);
+        }
+    }
+    return force;
+}
+
+@compute
+@workgroup_size(PARTICLES_PER_GROUP)
+fn bh_integrate(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let index = global_invocation_id.x;
+    if (index >= params.particle_n) {
+        return;
+    }
+    integrate(index, pos_src[index], bh_force(index));
+}
+
+@compute
+@workgroup_size(PARTICLES_PER_GROUP)
+fn bh_probe(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let index = global_invocation_id.x;
+    if (index >= min(params.particle_n, arrayLength(&probes))) {
+        return;
+    }
+    probes[index] = vec4(pairwise_force(index), bh_force(index));
+}
@@ -0,0 +1,327 @@
+use eframe::wgpu;
+
+use crate::ViewSettings;
+
+/// what the particles are rendered to when bloom is on
+pub(crate) const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// gpu state for the bloom post processing, see bloom.wgsl
+/// the particles are rendered to hdr_texture, then the bright parts are blurred at half resolution and
+/// added back while tonemapping into the texture egui shows
+pub(crate) struct Bloom {
+    pub(crate) size: wgpu::Extent3d,
+    hdr_texture: wgpu::Texture,
+    // the bright pass is rendered to blur_texture0, blurred horizontally into blur_texture1,
+    // then vertically back into blur_texture0
+    blur_texture0: wgpu::Texture,
+    blur_texture1: wgpu::Texture,
+    // one per pass, since the blur direction is in the params
+    bright_params_buffer: wgpu::Buffer,
+    blur_h_params_buffer: wgpu::Buffer,
+    blur_v_params_buffer: wgpu::Buffer,
+    bright_bind_group: wgpu::BindGroup,
+    blur_h_bind_group: wgpu::BindGroup,
+    blur_v_bind_group: wgpu::BindGroup,
+    tonemap_bind_group: wgpu::BindGroup,
+    bright_pipeline: wgpu::RenderPipeline,
+    blur_pipeline: wgpu::RenderPipeline,
+    tonemap_pipeline: wgpu::RenderPipeline,
+}
+impl Bloom {
+    /// `output_format` is the format of the texture that dispatch() tonemaps into
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        size: wgpu::Extent3d,
+        output_format: wgpu::TextureFormat,
+    ) -> Self {
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("bloom_shader_module"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("bloom.wgsl").into()),
+        });
+
+        let create_hdr_texture = |label, size| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: HDR_FORMAT,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            })
+        };
+        let half_size = wgpu::Extent3d {
+            width: size.width.div_ceil(2),
+            height: size.height.div_ceil(2),
+            depth_or_array_layers: 1,
+        };
+        let hdr_texture = create_hdr_texture("bloom_hdr_texture", size);
+        let blur_texture0 = create_hdr_texture("bloom_blur_texture0", half_size);
+        let blur_texture1 = create_hdr_texture("bloom_blur_texture1", half_size);
+
+        let create_params_buffer = |label| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: size_of::<BloomParams>() as _,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        };
+        let bright_params_buffer = create_params_buffer("bloom_bright_params_buffer");
+        let blur_h_params_buffer = create_params_buffer("bloom_blur_h_params_buffer");
+        let blur_v_params_buffer = create_params_buffer("bloom_blur_v_params_buffer");
+
+        // clamped so the blur doesn't wrap around the edges of the texture
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("bloom_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("bloom_bind_group_layout"),
+            entries: &[
+                // params
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(size_of::<BloomParams>() as _),
+                    },
+                    count: None,
+                },
+                // sampler
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                // src_texture
+                texture_entry(2),
+                // bloom_texture
+                texture_entry(3),
+            ],
+        });
+        let create_bind_group =
+            |label, params_buffer: &wgpu::Buffer, src: &wgpu::Texture, bloom: &wgpu::Texture| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some(label),
+                    layout: &bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: params_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&sampler),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: wgpu::BindingResource::TextureView(
+                                &src.create_view(&Default::default()),
+                            ),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: wgpu::BindingResource::TextureView(
+                                &bloom.create_view(&Default::default()),
+                            ),
+                        },
+                    ],
+                })
+            };
+        // the passes that don't use bloom_texture still need something there
+        let bright_bind_group = create_bind_group(
+            "bloom_bright_bind_group",
+            &bright_params_buffer,
+            &hdr_texture,
+            &blur_texture1,
+        );
+        let blur_h_bind_group = create_bind_group(
+            "bloom_blur_h_bind_group",
+            &blur_h_params_buffer,
+            &blur_texture0,
+            &hdr_texture,
+        );
+        let blur_v_bind_group = create_bind_group(
+            "bloom_blur_v_bind_group",
+            &blur_v_params_buffer,
+            &blur_texture1,
+            &hdr_texture,
+        );
+        // the tonemap only needs the intensity, which is the same in every params buffer
+        let tonemap_bind_group = create_bind_group(
+            "bloom_tonemap_bind_group",
+            &bright_params_buffer,
+            &hdr_texture,
+            &blur_texture0,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("bloom_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |label, entry_point, format: wgpu::TextureFormat| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader_module,
+                    entry_point: "fullscreen_vs",
+                    compilation_options: Default::default(),
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader_module,
+                    entry_point,
+                    compilation_options: Default::default(),
+                    targets: &[Some(format.into())],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+                cache: None,
+            })
+        };
+
+        Self {
+            size,
+            bright_pipeline: create_pipeline("bloom_bright_pipeline", "bright_fs", HDR_FORMAT),
+            blur_pipeline: create_pipeline("bloom_blur_pipeline", "blur_fs", HDR_FORMAT),
+            tonemap_pipeline: create_pipeline(
+                "bloom_tonemap_pipeline",
+                "tonemap_fs",
+                output_format,
+            ),
+            hdr_texture,
+            blur_texture0,
+            blur_texture1,
+            bright_params_buffer,
+            blur_h_params_buffer,
+            blur_v_params_buffer,
+            bright_bind_group,
+            blur_h_bind_group,
+            blur_v_bind_group,
+            tonemap_bind_group,
+        }
+    }
+
+    /// what to render the particles to instead of the output texture
+    pub(crate) fn hdr_view(&self) -> wgpu::TextureView {
+        self.hdr_texture.create_view(&Default::default())
+    }
+
+    /// records the bright pass, the blur and the tonemap into `output_view`
+    pub(crate) fn dispatch(
+        &self,
+        queue: &wgpu::Queue,
+        command_encoder: &mut wgpu::CommandEncoder,
+        output_view: &wgpu::TextureView,
+        view_settings: &ViewSettings,
+    ) {
+        for (params_buffer, direction) in [
+            (&self.bright_params_buffer, [0.0, 0.0]),
+            (&self.blur_h_params_buffer, [1.0, 0.0]),
+            (&self.blur_v_params_buffer, [0.0, 1.0]),
+        ] {
+            queue.write_buffer(
+                params_buffer,
+                0,
+                bytemuck::bytes_of(&BloomParams::new(view_settings, direction)),
+            );
+        }
+
+        command_encoder.push_debug_group("bloom");
+        let blur_view0 = self.blur_texture0.create_view(&Default::default());
+        let blur_view1 = self.blur_texture1.create_view(&Default::default());
+        for (label, pipeline, bind_group, target) in [
+            (
+                "bloom_bright_pass",
+                &self.bright_pipeline,
+                &self.bright_bind_group,
+                &blur_view0,
+            ),
+            (
+                "bloom_blur_h_pass",
+                &self.blur_pipeline,
+                &self.blur_h_bind_group,
+                &blur_view1,
+            ),
+            (
+                "bloom_blur_v_pass",
+                &self.blur_pipeline,
+                &self.blur_v_bind_group,
+                &blur_view0,
+            ),
+            (
+                "bloom_tonemap_pass",
+                &self.tonemap_pipeline,
+                &self.tonemap_bind_group,
+                output_view,
+            ),
+        ] {
+            let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(label),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        // every pixel gets overwritten
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        command_encoder.pop_debug_group();
+    }
+}
+
+#[derive(Clone, Copy, bytemuck::NoUninit)]
+#[repr(C)]
+struct BloomParams {
+    threshold: f32,
+    intensity: f32,
+    sigma: f32,
+    _padding: f32,
+    direction: [f32; 2],
+    _padding2: [f32; 2],
+}
+impl BloomParams {
+    fn new(view_settings: &ViewSettings, direction: [f32; 2]) -> Self {
+        Self {
+            threshold: view_settings.bloom_threshold,
+            intensity: view_settings.bloom_intensity,
+            // bloom_radius is a fraction of the texture, and the blur is at half resolution
+            sigma: view_settings.bloom_radius * view_settings.texture_size as f32 / 2.0,
+            _padding: 0.0,
+            direction,
+            _padding2: [0.0; 2],
+        }
+    }
+}
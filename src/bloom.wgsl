@@ -0,0 +1,80 @@
+// bloom post processing, its own shader module, see bloom.rs
+// bright_fs keeps the bright parts of the hdr texture at half resolution, blur_fs is one direction of a
+// separable gaussian blur of that, and tonemap_fs adds it back to the hdr texture and maps it to [0, 1]
+
+struct BloomParams {
+    threshold: f32,
+    intensity: f32,
+    // standard deviation of the blur, in half resolution pixels
+    sigma: f32,
+    _padding: f32,
+    // (1, 0) or (0, 1) for blur_fs
+    direction: vec2<f32>,
+    _padding2: vec2<f32>,
+}
+
+@group(0) @binding(0) var<uniform> bloom_params: BloomParams;
+@group(0) @binding(1) var linear_sampler: sampler;
+@group(0) @binding(2) var src_texture: texture_2d<f32>;
+// only used by tonemap_fs
+@group(0) @binding(3) var bloom_texture: texture_2d<f32>;
+
+struct FullscreenOutput {
+    @builtin(position) clip_pos: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn fullscreen_vs(@builtin(vertex_index) vertex_index: u32) -> FullscreenOutput {
+    let uv = vec2(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    // uv is y down but clip space is y up
+    return FullscreenOutput(vec4(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0), uv);
+}
+
+@fragment
+fn bright_fs(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    // the average of 4 pixels of the full resolution texture, since this is half resolution
+    let texel = 1.0 / vec2<f32>(textureDimensions(src_texture));
+    let color = 0.25 * (
+        textureSample(src_texture, linear_sampler, in.uv + vec2(-0.5, -0.5) * texel)
+        + textureSample(src_texture, linear_sampler, in.uv + vec2(0.5, -0.5) * texel)
+        + textureSample(src_texture, linear_sampler, in.uv + vec2(-0.5, 0.5) * texel)
+        + textureSample(src_texture, linear_sampler, in.uv + vec2(0.5, 0.5) * texel)
+    );
+    let brightness = max(color.r, max(color.g, color.b));
+    // keeps the part of the color above the threshold, with the same hue
+    let kept = max(brightness - bloom_params.threshold, 0.0) / max(brightness, 1e-4);
+    return vec4(color.rgb * kept, 1.0);
+}
+
+// the blur is cut off at 3 sigma, and this many pixels on each side
+const MAX_BLUR_RADIUS: i32 = 32;
+
+@fragment
+fn blur_fs(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let texel = bloom_params.direction / vec2<f32>(textureDimensions(src_texture));
+    let sigma = max(bloom_params.sigma, 1e-3);
+    let radius = min(i32(ceil(3.0 * sigma)), MAX_BLUR_RADIUS);
+    var sum = vec3(0.0, 0.0, 0.0);
+    var weight_sum = 0.0;
+    for (var i = -radius; i <= radius; i++) {
+        let weight = exp(-f32(i * i) / (2.0 * sigma * sigma));
+        sum += weight * textureSample(src_texture, linear_sampler, in.uv + f32(i) * texel).rgb;
+        weight_sum += weight;
+    }
+    return vec4(sum / weight_sum, 1.0);
+}
+
+// https://knarkowicz.wordpress.com/2016/01/06/aces-filmic-tone-mapping-curve/
+fn aces(x: vec3<f32>) -> vec3<f32> {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), vec3(0.0), vec3(1.0));
+}
+
+@fragment
+fn tonemap_fs(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(src_texture, linear_sampler, in.uv);
+    let bloom = textureSample(bloom_texture, linear_sampler, in.uv).rgb * bloom_params.intensity;
+    // the glow is visible over the background, so it makes the texture more opaque
+    let alpha = clamp(color.a + max(bloom.r, max(bloom.g, bloom.b)), 0.0, 1.0);
+    return vec4(aces(color.rgb + bloom), alpha);
+}
@@ -0,0 +1,94 @@
+// matrices are column major like in wgsl, so m[col][row]
+pub(crate) type Mat4 = [[f32; 4]; 4];
+
+/// camera that orbits around the center of the [0, 1]^3 simulation box
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct OrbitCamera {
+    pub(crate) yaw: f32,
+    pub(crate) pitch: f32,
+    pub(crate) distance: f32,
+    pub(crate) fov_y: f32,
+}
+impl OrbitCamera {
+    pub(crate) fn new() -> Self {
+        Self {
+            yaw: 0.6,
+            pitch: 0.4,
+            distance: 2.0,
+            fov_y: std::f32::consts::FRAC_PI_4,
+        }
+    }
+
+    pub(crate) fn target(&self) -> [f32; 3] {
+        [0.5, 0.5, 0.5]
+    }
+
+    pub(crate) fn eye(&self) -> [f32; 3] {
+        let [tx, ty, tz] = self.target();
+        [
+            tx + self.distance * self.pitch.cos() * self.yaw.sin(),
+            ty + self.distance * self.pitch.sin(),
+            tz + self.distance * self.pitch.cos() * self.yaw.cos(),
+        ]
+    }
+
+    /// rotate by a drag of `delta` radians, keeping the camera from flipping over the poles
+    pub(crate) fn orbit(&mut self, delta_yaw: f32, delta_pitch: f32) {
+        const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+        self.yaw = (self.yaw + delta_yaw).rem_euclid(std::f32::consts::TAU);
+        self.pitch = (self.pitch + delta_pitch).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    pub(crate) fn view_matrix(&self) -> Mat4 {
+        look_at(self.eye(), self.target(), [0.0, 1.0, 0.0])
+    }
+
+    pub(crate) fn proj_matrix(&self, aspect: f32) -> Mat4 {
+        perspective(self.fov_y, aspect, 0.01, 100.0)
+    }
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(a: [f32; 3]) -> [f32; 3] {
+    let len = dot(a, a).sqrt();
+    [a[0] / len, a[1] / len, a[2] / len]
+}
+
+// right handed, camera looks down -z
+fn look_at(eye: [f32; 3], target: [f32; 3], up: [f32; 3]) -> Mat4 {
+    let f = normalize(sub(target, eye));
+    let s = normalize(cross(f, up));
+    let u = cross(s, f);
+    [
+        [s[0], u[0], -f[0], 0.0],
+        [s[1], u[1], -f[1], 0.0],
+        [s[2], u[2], -f[2], 0.0],
+        [-dot(s, eye), -dot(u, eye), dot(f, eye), 1.0],
+    ]
+}
+
+// maps view space depth to wgpu's [0, 1] clip space depth
+fn perspective(fov_y: f32, aspect: f32, near: f32, far: f32) -> Mat4 {
+    let f = 1.0 / (fov_y / 2.0).tan();
+    [
+        [f / aspect, 0.0, 0.0, 0.0],
+        [0.0, f, 0.0, 0.0],
+        [0.0, 0.0, far / (near - far), -1.0],
+        [0.0, 0.0, near * far / (near - far), 0.0],
+    ]
+}
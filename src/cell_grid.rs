@@ -0,0 +1,181 @@
+/// particles sorted into a grid of cells at least `reach` wide that wraps around like the sim,
+/// so the pairs closer than `reach` can be found without checking every pair
+pub(crate) struct CellGrid {
+    dimension: usize,
+    reach2: f32,
+    // cells across each axis
+    cell_n: usize,
+    // the particles in cell i are cell_particles[cell_starts[i]..cell_starts[i + 1]]
+    cell_starts: Vec<usize>,
+    cell_particles: Vec<usize>,
+}
+impl CellGrid {
+    /// `dimension` is 2 or 3, and z is ignored in 2d
+    pub(crate) fn new(poses: &[[f32; 3]], dimension: usize, reach: f32) -> Self {
+        // more cells than this would mostly be empty
+        let max_cell_n = if dimension == 2 { 1024 } else { 128 };
+        let mut grid = Self {
+            dimension,
+            reach2: reach * reach,
+            cell_n: ((1.0 / reach) as usize).clamp(1, max_cell_n),
+            cell_starts: Vec::new(),
+            cell_particles: vec![0; poses.len()],
+        };
+        let cell_count = grid.cell_n.pow(dimension as u32);
+        grid.cell_starts = vec![0; cell_count + 1];
+        for pos in poses {
+            let cell_i = grid.cell_index(grid.cell_of(pos));
+            grid.cell_starts[cell_i + 1] += 1;
+        }
+        for i in 0..cell_count {
+            grid.cell_starts[i + 1] += grid.cell_starts[i];
+        }
+        let mut fill = grid.cell_starts.clone();
+        for (particle_i, pos) in poses.iter().enumerate() {
+            let cell_i = grid.cell_index(grid.cell_of(pos));
+            grid.cell_particles[fill[cell_i]] = particle_i;
+            fill[cell_i] += 1;
+        }
+        grid
+    }
+
+    fn cell_of(&self, pos: &[f32; 3]) -> [usize; 3] {
+        std::array::from_fn(|axis| {
+            if axis < self.dimension {
+                ((pos[axis] * self.cell_n as f32) as usize).min(self.cell_n - 1)
+            } else {
+                0
+            }
+        })
+    }
+
+    fn cell_index(&self, cell: [usize; 3]) -> usize {
+        (cell[2] * self.cell_n + cell[1]) * self.cell_n + cell[0]
+    }
+
+    /// calls `f(i, j, distance2)` once for each pair of particles with i < j that are within reach,
+    /// where distance2 is the squared distance the short way around the walls
+    /// `poses` has to be the same as in new()
+    pub(crate) fn for_each_pair(&self, poses: &[[f32; 3]], mut f: impl FnMut(usize, usize, f32)) {
+        let dimension = self.dimension;
+        let offsets = (0..3usize.pow(dimension as u32))
+            .map(|i| {
+                std::array::from_fn::<isize, 3, _>(|axis| {
+                    (i / 3usize.pow(axis as u32)) as isize % 3 - 1
+                })
+            })
+            .collect::<Vec<_>>();
+        for (particle_i, pos) in poses.iter().enumerate() {
+            let cell = self.cell_of(pos);
+            let mut neighbor_cells = offsets
+                .iter()
+                .map(|offset| {
+                    self.cell_index(std::array::from_fn(|axis| {
+                        if axis < dimension {
+                            (cell[axis] as isize + offset[axis]).rem_euclid(self.cell_n as isize)
+                                as usize
+                        } else {
+                            0
+                        }
+                    }))
+                })
+                .collect::<Vec<_>>();
+            // with fewer than 3 cells across, the offsets wrap around to the same cells
+            neighbor_cells.sort_unstable();
+            neighbor_cells.dedup();
+            for cell_i in neighbor_cells {
+                let particles =
+                    &self.cell_particles[self.cell_starts[cell_i]..self.cell_starts[cell_i + 1]];
+                for &other_i in particles {
+                    if other_i <= particle_i {
+                        continue;
+                    }
+                    let other_pos = &poses[other_i];
+                    let distance2 = (0..dimension)
+                        .map(|axis| {
+                            let delta = other_pos[axis] - pos[axis];
+                            (delta - delta.round()).powi(2)
+                        })
+                        .sum::<f32>();
+                    if distance2 <= self.reach2 {
+                        f(particle_i, other_i, distance2);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::CellGrid;
+
+    // every pair within reach the slow way, as (i, j) with i < j
+    fn brute_force_pairs(poses: &[[f32; 3]], dimension: usize, reach: f32) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for i in 0..poses.len() {
+            for j in i + 1..poses.len() {
+                let distance2 = (0..dimension)
+                    .map(|axis| {
+                        let delta = poses[j][axis] - poses[i][axis];
+                        (delta - delta.round()).powi(2)
+                    })
+                    .sum::<f32>();
+                if distance2 <= reach * reach {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        pairs
+    }
+
+    #[test]
+    fn test_pairs_match_brute_force() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for dimension in [2, 3] {
+            let poses = (0..300)
+                .map(|_| {
+                    std::array::from_fn(|axis| {
+                        if axis < dimension {
+                            rng.gen_range(0.0..1.0)
+                        } else {
+                            0.0
+                        }
+                    })
+                })
+                .collect::<Vec<[f32; 3]>>();
+            // including reaches with 1 and 2 cells across, where the neighboring cells wrap around to the same ones
+            for reach in [0.0, 0.03, 0.1, 0.34, 0.5, 0.7, 1.0, 2.0] {
+                let grid = CellGrid::new(&poses, dimension, reach);
+                let mut pairs = Vec::new();
+                grid.for_each_pair(&poses, |i, j, _| pairs.push((i, j)));
+                pairs.sort_unstable();
+                assert_eq!(
+                    pairs,
+                    brute_force_pairs(&poses, dimension, reach),
+                    "dimension {dimension} reach {reach}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_pairs_across_the_walls() {
+        let poses = [
+            [0.01, 0.5, 0.0],
+            [0.99, 0.5, 0.0],
+            [0.5, 0.995, 0.0],
+            [0.5, 0.005, 0.0],
+        ];
+        let grid = CellGrid::new(&poses, 2, 0.05);
+        let mut pairs = Vec::new();
+        grid.for_each_pair(&poses, |i, j, distance2| pairs.push((i, j, distance2)));
+        assert_eq!(pairs.len(), 2);
+        assert_eq!((pairs[0].0, pairs[0].1), (0, 1));
+        assert!((pairs[0].2 - 0.02_f32.powi(2)).abs() < 1e-6);
+        assert_eq!((pairs[1].0, pairs[1].1), (2, 3));
+        assert!((pairs[1].2 - 0.01_f32.powi(2)).abs() < 1e-6);
+    }
+}
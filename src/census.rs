@@ -0,0 +1,343 @@
+use eframe::egui;
+
+use crate::{cell_grid::CellGrid, SimData};
+
+/// a group of particles connected by links shorter than link_distance
+#[derive(Clone, Debug)]
+pub(crate) struct Cluster {
+    pub(crate) size: usize,
+    // how many of its particles are each specie
+    pub(crate) specie_counts: Vec<usize>,
+    // the mean position going around the torus, so clusters on a wall aren't put in the middle of the box
+    pub(crate) centroid: [f32; 3],
+    // the mean velocity
+    pub(crate) velocity: [f32; 3],
+}
+
+/// the connected components of the graph of particles within link_distance of each other,
+/// from a snapshot of the particles on the cpu
+pub(crate) struct Census {
+    // the sim time of the snapshot
+    pub(crate) time: f64,
+    // the ones with at least min_cluster_size particles, biggest first
+    pub(crate) clusters: Vec<Cluster>,
+    // particles in smaller clusters, including ones on their own
+    pub(crate) loose_n: usize,
+    // size_histogram[i] is how many clusters of any size have from 2^i to 2^(i + 1) - 1 particles
+    pub(crate) size_histogram: Vec<usize>,
+    // the index in clusters of each particle in stable id order, or -1.0, the color values for ColorMode::Cluster
+    pub(crate) labels: Vec<f32>,
+    // how many components of the centroids and velocities to show
+    dimension: usize,
+}
+impl Census {
+    /// `dimension` is 2 or 3, and `sim_data` is from GfxData::read_sim_data()
+    pub(crate) fn take(
+        sim_data: &SimData<[f32; 3]>,
+        specie_n: usize,
+        dimension: usize,
+        link_distance: f32,
+        min_cluster_size: usize,
+        time: f64,
+    ) -> Self {
+        let particle_n = sim_data.poses.len();
+        let mut components = UnionFind::new(particle_n);
+        CellGrid::new(&sim_data.poses, dimension, link_distance)
+            .for_each_pair(&sim_data.poses, |particle_i, other_i, _| {
+                components.union(particle_i, other_i)
+            });
+
+        // sums for every component, indexed by their root
+        struct Sums {
+            size: usize,
+            specie_counts: Vec<usize>,
+            cos_sin: [[f32; 2]; 3],
+            velocity: [f32; 3],
+        }
+        let mut sums = std::collections::HashMap::<usize, Sums>::new();
+        for particle_i in 0..particle_n {
+            let root = components.find(particle_i);
+            let entry = sums.entry(root).or_insert_with(|| Sums {
+                size: 0,
+                specie_counts: vec![0; specie_n],
+                cos_sin: [[0.0; 2]; 3],
+                velocity: [0.0; 3],
+            });
+            entry.size += 1;
+            entry.specie_counts[sim_data.species[particle_i] as usize] += 1;
+            for axis in 0..3 {
+                let angle = sim_data.poses[particle_i][axis] * std::f32::consts::TAU;
+                entry.cos_sin[axis][0] += angle.cos();
+                entry.cos_sin[axis][1] += angle.sin();
+                entry.velocity[axis] += sim_data.vels[particle_i][axis];
+            }
+        }
+
+        let mut size_histogram = Vec::new();
+        for component in sums.values() {
+            let bin = component.size.ilog2() as usize;
+            if size_histogram.len() <= bin {
+                size_histogram.resize(bin + 1, 0);
+            }
+            size_histogram[bin] += 1;
+        }
+        let mut roots = sums
+            .iter()
+            .filter(|(_, component)| component.size >= min_cluster_size)
+            .map(|(&root, component)| (root, component.size))
+            .collect::<Vec<_>>();
+        // the root breaks ties so the order doesn't depend on the hash map
+        roots.sort_unstable_by_key(|&(root, size)| (std::cmp::Reverse(size), root));
+        let clusters = roots
+            .iter()
+            .map(|(root, _)| {
+                let component = &sums[root];
+                let size = component.size as f32;
+                Cluster {
+                    size: component.size,
+                    specie_counts: component.specie_counts.clone(),
+                    centroid: std::array::from_fn(|axis| {
+                        let [cos, sin] = component.cos_sin[axis];
+                        (sin.atan2(cos) / std::f32::consts::TAU).rem_euclid(1.0)
+                    }),
+                    velocity: component.velocity.map(|sum| sum / size),
+                }
+            })
+            .collect::<Vec<Cluster>>();
+        let cluster_indices = roots
+            .iter()
+            .enumerate()
+            .map(|(cluster_i, &(root, _))| (root, cluster_i as f32))
+            .collect::<std::collections::HashMap<_, _>>();
+        let labels = (0..particle_n)
+            .map(|particle_i| {
+                cluster_indices
+                    .get(&components.find(particle_i))
+                    .copied()
+                    .unwrap_or(-1.0)
+            })
+            .collect();
+
+        Self {
+            time,
+            loose_n: particle_n - clusters.iter().map(|cluster| cluster.size).sum::<usize>(),
+            clusters,
+            size_histogram,
+            labels,
+            dimension,
+        }
+    }
+
+    /// the summary, the size histogram and a table of the biggest clusters
+    pub(crate) fn ui(&self, ui: &mut egui::Ui, specie_colors: &[egui::Rgba]) {
+        ui.label(format!(
+            "{} clusters, {} particles in smaller ones, at t = {:.2} s",
+            self.clusters.len(),
+            self.loose_n,
+            self.time,
+        ));
+
+        ui.label("sizes (clusters of any size)");
+        let max_count = self.size_histogram.iter().copied().max().unwrap_or(1);
+        egui::Grid::new("size_histogram").show(ui, |ui| {
+            for (bin, &count) in self.size_histogram.iter().enumerate() {
+                let (min, max) = (1usize << bin, (1usize << (bin + 1)) - 1);
+                ui.label(if min == max {
+                    format!("{min}")
+                } else {
+                    format!("{min}-{max}")
+                });
+                let (rect, _) =
+                    ui.allocate_exact_size(egui::Vec2::new(160.0, 12.0), egui::Sense::hover());
+                let width = rect.width() * count as f32 / max_count as f32;
+                ui.painter().rect_filled(
+                    egui::Rect::from_min_size(rect.min, egui::Vec2::new(width, rect.height())),
+                    0.0,
+                    egui::Color32::GRAY,
+                );
+                ui.label(count.to_string());
+                ui.end_row();
+            }
+        });
+
+        ui.separator();
+        egui::ScrollArea::vertical()
+            .max_height(300.0)
+            .show(ui, |ui| {
+                egui::Grid::new("clusters").striped(true).show(ui, |ui| {
+                    for header in ["", "size", "species", "centroid", "velocity"] {
+                        ui.label(header);
+                    }
+                    ui.end_row();
+                    for (cluster_i, cluster) in self.clusters.iter().enumerate() {
+                        let (rect, _) = ui.allocate_exact_size(
+                            egui::Vec2::splat(ui.spacing().interact_size.y),
+                            egui::Sense::hover(),
+                        );
+                        ui.painter()
+                            .rect_filled(rect.shrink(2.0), 2.0, cluster_color(cluster_i));
+                        ui.label(cluster.size.to_string());
+                        // a bar split into each specie's share
+                        let (rect, response) = ui.allocate_exact_size(
+                            egui::Vec2::new(100.0, 12.0),
+                            egui::Sense::hover(),
+                        );
+                        let mut left = rect.left();
+                        for (&count, &specie_color) in
+                            cluster.specie_counts.iter().zip(specie_colors)
+                        {
+                            let width = rect.width() * count as f32 / cluster.size as f32;
+                            ui.painter().rect_filled(
+                                egui::Rect::from_x_y_ranges(left..=left + width, rect.y_range()),
+                                0.0,
+                                specie_color,
+                            );
+                            left += width;
+                        }
+                        response.on_hover_text(format!("{:?}", cluster.specie_counts));
+                        ui.label(format_vector(&cluster.centroid[..self.dimension]));
+                        ui.label(format_vector(&cluster.velocity[..self.dimension]));
+                        ui.end_row();
+                    }
+                });
+            });
+    }
+}
+
+/// the same as COLOR_MODE_CLUSTER in common.wgsl, so neighboring indices get far apart hues
+pub(crate) fn cluster_color(cluster_i: usize) -> egui::Color32 {
+    let hue = (cluster_i as f32 * 0.618034).fract();
+    egui::ecolor::Hsva::new(hue, 1.0, 1.0, 1.0).into()
+}
+
+fn format_vector(vector: &[f32]) -> String {
+    vector
+        .iter()
+        .map(|component| format!("{component:.3}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// disjoint sets of particle indices, with path halving and union by size
+struct UnionFind {
+    parents: Vec<usize>,
+    sizes: Vec<usize>,
+}
+impl UnionFind {
+    fn new(len: usize) -> Self {
+        Self {
+            parents: (0..len).collect(),
+            sizes: vec![1; len],
+        }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parents[i] != i {
+            self.parents[i] = self.parents[self.parents[i]];
+            i = self.parents[i];
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        if self.sizes[a] < self.sizes[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parents[b] = a;
+        self.sizes[a] += self.sizes[b];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Census, UnionFind};
+    use crate::SimData;
+
+    fn sim_data(poses: &[[f32; 3]]) -> SimData<[f32; 3]> {
+        SimData {
+            poses: poses.to_vec(),
+            vels: vec![[0.0; 3]; poses.len()],
+            species: vec![0; poses.len()],
+        }
+    }
+
+    #[test]
+    fn test_union_find() {
+        let mut components = UnionFind::new(6);
+        components.union(0, 1);
+        components.union(2, 3);
+        components.union(1, 3);
+        assert_eq!(components.find(0), components.find(2));
+        assert_ne!(components.find(0), components.find(4));
+        assert_ne!(components.find(4), components.find(5));
+        // again, and with itself
+        components.union(0, 2);
+        components.union(5, 5);
+        let root = components.find(3);
+        assert_eq!(components.sizes[root], 4);
+    }
+
+    #[test]
+    fn test_pair_across_the_wall() {
+        let mut sim_data = sim_data(&[[0.02, 0.5, 0.0], [0.98, 0.5, 0.0], [0.5, 0.5, 0.0]]);
+        sim_data.vels = vec![[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 0.0]];
+        let census = Census::take(&sim_data, 1, 2, 0.05, 2, 0.0);
+        assert_eq!(census.clusters.len(), 1);
+        assert_eq!(census.loose_n, 1);
+        assert_eq!(census.labels, [0.0, 0.0, -1.0]);
+        let cluster = &census.clusters[0];
+        assert_eq!(cluster.size, 2);
+        // on the wall, not in the middle of the box where the plain mean would be
+        let [x, y, _] = cluster.centroid;
+        assert!(x.min(1.0 - x) < 1e-4, "centroid x {x}");
+        assert!((y - 0.5).abs() < 1e-4, "centroid y {y}");
+        assert_eq!(cluster.velocity, [0.5, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn test_size_histogram() {
+        // clusters of 1 to 5 particles in a row along y, each at its own x
+        let poses = (1..=5)
+            .flat_map(|size| {
+                (0..size).map(move |i| [0.1 + 0.15 * size as f32, 0.1 + 0.01 * i as f32, 0.0])
+            })
+            .collect::<Vec<_>>();
+        let census = Census::take(&sim_data(&poses), 1, 2, 0.015, 3, 0.0);
+        // 1, then 2 and 3, then 4 and 5
+        assert_eq!(census.size_histogram, [1, 2, 2]);
+        assert_eq!(
+            census
+                .clusters
+                .iter()
+                .map(|cluster| cluster.size)
+                .collect::<Vec<_>>(),
+            [5, 4, 3]
+        );
+        assert_eq!(census.loose_n, 3);
+    }
+
+    #[test]
+    fn test_zero_link_distance() {
+        // only particles in exactly the same place are linked
+        let census = Census::take(
+            &sim_data(&[[0.3, 0.3, 0.0], [0.3, 0.3, 0.0], [0.3001, 0.3, 0.0]]),
+            1,
+            2,
+            0.0,
+            1,
+            0.0,
+        );
+        assert_eq!(
+            census
+                .clusters
+                .iter()
+                .map(|cluster| cluster.size)
+                .collect::<Vec<_>>(),
+            [2, 1]
+        );
+    }
+}
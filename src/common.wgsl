@@ -0,0 +1,136 @@
+// shared by shader.wgsl and shader3d.wgsl, which get appended to this in gfx.rs
+
+struct Params {
+    specie_n: u32,
+    particle_n: u32,
+    local_radius: f32,
+    local_radius2: f32,
+    friction: f32,
+    dt: f32,
+    force_multiplier: f32,
+    particle_radius: f32,
+    particle_radius2: f32,
+    texture_size: u32,
+    zoom_scale: f32,
+    // zoom_center: vec2<f32>,
+    zoom_center_x: f32,
+    zoom_center_y: f32,
+    pm_grid_size: u32,
+    bh_depth: u32,
+    bh_theta: f32,
+    // particles smaller than this are drawn at this size and faded out, in the same units as particle_radius
+    min_particle_radius: f32,
+    // the width of the smooth edge in the same units, 0.0 for hard edges
+    antialias_width: f32,
+    // one of the COLOR_MODE_ consts, and the values at the ends of its colormap
+    color_mode: u32,
+    color_min: f32,
+    color_max: f32,
+    // see overlay.wgsl
+    overlay_mode: u32,
+    overlay_specie: u32,
+    overlay_scale: f32,
+    // one of the GLYPH_ consts in shader.wgsl, and the seconds of velocity its tail is long
+    glyph: u32,
+    glyph_length: f32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(5) var<storage, read> species: array<u32>;
+@group(0) @binding(6) var<storage, read> attractions: array<f32>;
+@group(0) @binding(7) var<storage, read> specie_colors: array<vec4<f32>>;
+
+// the same as ColorMode in main.rs
+const COLOR_MODE_SPECIES: u32 = 0u;
+const COLOR_MODE_SPEED: u32 = 1u;
+const COLOR_MODE_HEADING: u32 = 2u;
+const COLOR_MODE_NEIGHBOR_COUNT: u32 = 3u;
+const COLOR_MODE_FORCE: u32 = 4u;
+const COLOR_MODE_CLUSTER: u32 = 5u;
+
+// color_value is from color_value_cs, only for the modes it calculates
+fn particle_color(specie: u32, vel: vec3<f32>, color_value: f32) -> vec4<f32> {
+    switch params.color_mode {
+        case COLOR_MODE_SPEED: {
+            return turbo(length(vel), params.color_min, params.color_max);
+        }
+        case COLOR_MODE_HEADING: {
+            let heading = degrees(atan2(vel.y, vel.x));
+            return hue(fract((heading - params.color_min) / (params.color_max - params.color_min)));
+        }
+        case COLOR_MODE_NEIGHBOR_COUNT, COLOR_MODE_FORCE: {
+            return turbo(color_value, params.color_min, params.color_max);
+        }
+        case COLOR_MODE_CLUSTER: {
+            // color_value is the index of the particle's cluster in the census, or -1.0 if it's too small to count
+            // the same as cluster_color() in census.rs
+            if (color_value < 0.0) {
+                return vec4(0.15, 0.15, 0.15, 1.0);
+            }
+            return hue(fract(color_value * 0.618034));
+        }
+        default: {
+            return specie_colors[specie];
+        }
+    }
+}
+
+// fully saturated, for hue in [0, 1]
+fn hue(hue: f32) -> vec4<f32> {
+    let rgb = clamp(abs(fract(hue + vec3(1.0, 2.0 / 3.0, 1.0 / 3.0)) * 6.0 - 3.0) - 1.0, vec3(0.0), vec3(1.0));
+    return vec4(rgb, 1.0);
+}
+
+// the corners of the two triangles of the quad [-1, 1]x[-1, 1], for vertex_index % 6
+fn quad_corner(vertex_index: u32) -> vec2<f32> {
+    var corners = array(
+        vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(1.0, 1.0),
+        vec2(-1.0, -1.0), vec2(1.0, 1.0), vec2(-1.0, 1.0),
+    );
+    return corners[vertex_index % 6u];
+}
+
+// a triangle that covers the whole screen, for the trails fade in gfx.rs and overlay_fs
+@vertex
+fn fade_vs(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4(uv * 2.0 - vec2(1.0, 1.0), 0.0, 1.0);
+}
+
+// subtracted after multiplying by the decay, about the smallest step of an 8 bit srgb texture near black
+const TRAIL_FLOOR: f32 = 1.0 / 2048.0;
+
+@fragment
+fn fade_fs() -> @location(0) vec4<f32> {
+    return vec4(TRAIL_FLOOR);
+}
+
+// TODO: this but without distance normalized by local_radius so i can do a convolution
+const BETA: f32 = 0.3;
+fn get_attraction_force(distance: f32, attraction: f32) -> f32 {
+    if (distance < BETA) {
+        return distance * (1.0 / BETA) - 1.0;
+    } else {
+        return attraction * (1.0 - abs(2.0 * distance - (1.0 + BETA)) / (1.0 - BETA));
+    }
+}
+
+// this used to have a white band around 0.5 for debugging, which would look like part of the data in the color modes
+fn turbo(value: f32, min: f32, max: f32) -> vec4<f32> {
+    let kRedVec4: vec4<f32> = vec4(0.13572138, 4.61539260, -42.66032258, 132.13108234);
+    let kGreenVec4: vec4<f32> = vec4(0.09140261, 2.19418839, 4.84296658, -14.18503333);
+    let kBlueVec4: vec4<f32> = vec4(0.10667330, 12.64194608, -60.58204836, 110.36276771);
+    let kRedVec2: vec2<f32> = vec2(-152.94239396, 59.28637943);
+    let kGreenVec2: vec2<f32> = vec2(4.27729857, 2.82956604);
+    let kBlueVec2: vec2<f32> = vec2(-89.90310912, 27.34824973);
+
+    let x = saturate((value - min) / (max - min));
+    let v4: vec4<f32> = vec4( 1.0, x, x * x, x * x * x);
+    let v2: vec2<f32> = v4.zw * v4.z;
+    return vec4(
+        dot(v4, kRedVec4)   + dot(v2, kRedVec2),
+        dot(v4, kGreenVec4) + dot(v2, kGreenVec2),
+        dot(v4, kBlueVec4)  + dot(v2, kBlueVec2),
+        1.0,
+    );
+}
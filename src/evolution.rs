@@ -0,0 +1,418 @@
+use std::io::Write as _;
+
+use eframe::{egui, egui_wgpu};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::{
+    export,
+    search::{Scores, Trial, THUMBNAIL_SIZE},
+    EvolutionSettings, SimSettings, ViewSettings,
+};
+
+/// what the evolution selects for, from the same scores as the search
+/// to select for something else, add a variant and score it in of()
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Fitness {
+    // the equally weighted geometric mean of the others
+    Total,
+    Clusters,
+    Motion,
+    Stability,
+}
+impl Fitness {
+    pub(crate) const ALL: [Self; 4] = [Self::Total, Self::Clusters, Self::Motion, Self::Stability];
+
+    fn of(self, scores: &Scores) -> f32 {
+        match self {
+            Self::Total => scores.total,
+            Self::Clusters => scores.clusters,
+            Self::Motion => scores.motion,
+            Self::Stability => scores.stability,
+        }
+    }
+}
+
+/// one candidate, which is scored by running a Trial of it
+struct Individual {
+    // unique across generations, for the lineage
+    id: usize,
+    generation: usize,
+    // the ids of the one or two it was bred from, empty for the starting attractions
+    parents: Vec<usize>,
+    attractions: Vec<Vec<f32>>,
+    local_radius: f32,
+    friction_half_life: f32,
+    // its trial's starting positions come from StdRng::seed_from_u64(seed), so its fitness can be reproduced
+    seed: u64,
+    // None until its trial is done
+    scores: Option<Scores>,
+    fitness: Option<f32>,
+    thumbnail: Option<egui::TextureHandle>,
+    // picked to breed the next generation
+    survives: bool,
+}
+
+/// a genetic algorithm over the attractions, and optionally local_radius and friction_half_life
+/// each generation is scored one trial at a time over a few frames of the app, then the survivors,
+/// which start as the fittest but can be picked by hand, are kept and bred into the next one
+/// every individual and every choice of survivors is logged to lineage_1700000000.jsonl
+pub(crate) struct Evolution {
+    render_state: egui_wgpu::RenderState,
+    view_settings: ViewSettings,
+    // the settings every trial uses, except for what's evolved
+    base_settings: SimSettings,
+    generation: usize,
+    population: Vec<Individual>,
+    // the index in population of the one being run
+    trial: Option<(usize, Trial)>,
+    next_id: usize,
+    lineage_path: std::path::PathBuf,
+    lineage: std::io::BufWriter<std::fs::File>,
+    // why the lineage stopped being written
+    lineage_error: Option<String>,
+}
+impl Evolution {
+    /// the first generation is the attractions in `sim_settings` and mutations of them
+    pub(crate) fn start(
+        render_state: &egui_wgpu::RenderState,
+        view_settings: &ViewSettings,
+        sim_settings: &SimSettings,
+        settings: &EvolutionSettings,
+    ) -> std::io::Result<Self> {
+        let lineage_path = export::timestamped_path("lineage", "jsonl");
+        let lineage = std::io::BufWriter::new(std::fs::File::create(&lineage_path)?);
+        let mut evolution = Self {
+            render_state: render_state.clone(),
+            view_settings: view_settings.clone(),
+            base_settings: SimSettings {
+                particle_n: settings.particle_n,
+                // tick_n is in ticks, so a fixed dt makes every individual run for the same sim time
+                dt: 1.0 / 60.0,
+                ..sim_settings.clone()
+            },
+            generation: 0,
+            population: Vec::new(),
+            trial: None,
+            next_id: 0,
+            lineage_path,
+            lineage,
+            lineage_error: None,
+        };
+        let original = evolution.individual(
+            Vec::new(),
+            sim_settings.attractions.clone(),
+            sim_settings.local_radius,
+            sim_settings.friction_half_life,
+        );
+        let mut rng = rand::thread_rng();
+        let mutants = (1..settings.population_n)
+            .map(|_| {
+                let mut mutant = evolution.individual(
+                    vec![original.id],
+                    original.attractions.clone(),
+                    original.local_radius,
+                    original.friction_half_life,
+                );
+                mutant.mutate(&mut rng, settings);
+                mutant
+            })
+            .collect::<Vec<_>>();
+        evolution.population.push(original);
+        evolution.population.extend(mutants);
+        Ok(evolution)
+    }
+
+    fn individual(
+        &mut self,
+        parents: Vec<usize>,
+        attractions: Vec<Vec<f32>>,
+        local_radius: f32,
+        friction_half_life: f32,
+    ) -> Individual {
+        self.next_id += 1;
+        Individual {
+            id: self.next_id - 1,
+            generation: self.generation,
+            parents,
+            attractions,
+            local_radius,
+            friction_half_life,
+            seed: rand::thread_rng().gen(),
+            scores: None,
+            fitness: None,
+            thumbnail: None,
+            survives: false,
+        }
+    }
+
+    fn is_generation_done(&self) -> bool {
+        self.population
+            .iter()
+            .all(|individual| individual.fitness.is_some())
+    }
+
+    /// runs the current trial for settings.steps_per_frame steps, or starts the next one
+    /// when the generation is done, picks the fittest as survivors, and breeds them if settings.auto
+    pub(crate) fn update(&mut self, ctx: &egui::Context, settings: &EvolutionSettings) {
+        if self.trial.is_none() {
+            let Some(individual_i) = self
+                .population
+                .iter()
+                .position(|individual| individual.fitness.is_none())
+            else {
+                if settings.auto && self.population.iter().any(|individual| individual.survives) {
+                    self.breed(settings);
+                }
+                return;
+            };
+            let individual = &self.population[individual_i];
+            let sim_settings = SimSettings {
+                attractions: individual.attractions.clone(),
+                local_radius: individual.local_radius,
+                friction_half_life: individual.friction_half_life,
+                ..self.base_settings.clone()
+            };
+            let frame_n = settings.tick_n.div_ceil(sim_settings.substep_n);
+            self.trial = Some((
+                individual_i,
+                Trial::new(
+                    &self.render_state,
+                    &self.view_settings,
+                    sim_settings,
+                    frame_n,
+                    &mut StdRng::seed_from_u64(individual.seed),
+                ),
+            ));
+        }
+
+        let (_, trial) = self.trial.as_mut().unwrap();
+        for _ in 0..settings.steps_per_frame {
+            if trial.is_done() {
+                break;
+            }
+            trial.step();
+        }
+        if !trial.is_done() {
+            return;
+        }
+        let (individual_i, mut trial) = self.trial.take().unwrap();
+        let individual = &mut self.population[individual_i];
+        let scores = trial.scores([1.0; 3]);
+        individual.fitness = Some(settings.fitness.of(&scores));
+        individual.thumbnail = Some(ctx.load_texture(
+            format!("evolution_{}", individual.id),
+            egui::ColorImage::from_rgba_unmultiplied(
+                [THUMBNAIL_SIZE as usize; 2],
+                &trial.thumbnail(),
+            ),
+            egui::TextureOptions::LINEAR,
+        ));
+        let line = serde_json::json!({
+            "id": individual.id,
+            "generation": individual.generation,
+            "parents": individual.parents,
+            "attractions": individual.attractions,
+            "local_radius": individual.local_radius,
+            "friction_half_life": individual.friction_half_life,
+            "seed": individual.seed,
+            "fitness": individual.fitness,
+            "scores": scores,
+            "cluster_n": trial.cluster_n(),
+            "mean_speed": trial.mean_speed(),
+        });
+        individual.scores = Some(scores);
+        self.log(&line);
+
+        if self.is_generation_done() {
+            let mut ranked = (0..self.population.len()).collect::<Vec<_>>();
+            let fitness = |individual_i: usize| {
+                self.population[individual_i]
+                    .fitness
+                    .unwrap_or(f32::NEG_INFINITY)
+            };
+            ranked.sort_by(|&a, &b| fitness(b).total_cmp(&fitness(a)));
+            for &individual_i in ranked.iter().take(settings.survivor_n) {
+                self.population[individual_i].survives = true;
+            }
+        }
+    }
+
+    fn log(&mut self, line: &serde_json::Value) {
+        if self.lineage_error.is_some() {
+            return;
+        }
+        if let Err(error) = writeln!(self.lineage, "{line}").and_then(|()| self.lineage.flush()) {
+            self.lineage_error = Some(format!(
+                "couldn't write {}: {error}",
+                self.lineage_path.display()
+            ));
+        }
+    }
+
+    /// keeps the survivors, and fills the rest of the next generation with their children,
+    /// each a crossover of two survivors (or a copy of one) that's then mutated
+    fn breed(&mut self, settings: &EvolutionSettings) {
+        let survivors = std::mem::take(&mut self.population)
+            .into_iter()
+            .filter(|individual| individual.survives)
+            .collect::<Vec<_>>();
+        self.log(&serde_json::json!({
+            "generation": self.generation,
+            "survivors": survivors.iter().map(|survivor| survivor.id).collect::<Vec<_>>(),
+        }));
+        self.generation += 1;
+        let mut rng = rand::thread_rng();
+        let children = (survivors.len()..settings.population_n.max(survivors.len()))
+            .map(|_| {
+                let a = survivors.choose(&mut rng).unwrap();
+                let b = survivors.choose(&mut rng).unwrap();
+                // each specie's row of attractions comes from one parent, so it keeps how it reacts to the others
+                let attractions = a
+                    .attractions
+                    .iter()
+                    .zip(&b.attractions)
+                    .map(|(row_a, row_b)| if rng.gen() { row_a } else { row_b }.clone())
+                    .collect();
+                let parents = if a.id == b.id {
+                    vec![a.id]
+                } else {
+                    vec![a.id, b.id]
+                };
+                let local_radius = if rng.gen() {
+                    a.local_radius
+                } else {
+                    b.local_radius
+                };
+                let friction_half_life = if rng.gen() {
+                    a.friction_half_life
+                } else {
+                    b.friction_half_life
+                };
+                let mut child =
+                    self.individual(parents, attractions, local_radius, friction_half_life);
+                child.mutate(&mut rng, settings);
+                child
+            })
+            .collect::<Vec<_>>();
+        // the survivors aren't run again, so they keep their scores and thumbnails
+        self.population = survivors;
+        for survivor in &mut self.population {
+            survivor.survives = false;
+        }
+        self.population.extend(children);
+    }
+
+    /// clicking a thumbnail toggles whether it survives, and "load" puts it in sim_settings
+    pub(crate) fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        settings: &EvolutionSettings,
+        sim_settings: &mut SimSettings,
+    ) {
+        let evaluated_n = self
+            .population
+            .iter()
+            .filter(|individual| individual.fitness.is_some())
+            .count();
+        ui.label(format!(
+            "generation {}, {evaluated_n} of {} scored",
+            self.generation,
+            self.population.len()
+        ));
+        if let Some((_, trial)) = &self.trial {
+            ui.add(egui::ProgressBar::new(trial.progress()));
+        }
+        ui.horizontal(|ui| {
+            let survivor_n = self
+                .population
+                .iter()
+                .filter(|individual| individual.survives)
+                .count();
+            if ui
+                .add_enabled(
+                    self.is_generation_done() && survivor_n > 0,
+                    egui::Button::new("next generation"),
+                )
+                .on_hover_text("breeds the highlighted ones")
+                .clicked()
+            {
+                self.breed(settings);
+            }
+            ui.label(format!("{survivor_n} survivors"));
+        });
+        ui.label(format!("logging to {}", self.lineage_path.display()));
+        if let Some(lineage_error) = &self.lineage_error {
+            ui.label(lineage_error);
+        }
+
+        let fits = self.base_settings.dimension == sim_settings.dimension
+            && self.base_settings.specie_n == sim_settings.specie_n;
+        egui::ScrollArea::vertical()
+            .max_height(400.0)
+            .show(ui, |ui| {
+                egui::Grid::new("evolution").show(ui, |ui| {
+                    for (individual_i, individual) in self.population.iter_mut().enumerate() {
+                        ui.vertical(|ui| {
+                            let size = egui::Vec2::splat(THUMBNAIL_SIZE as f32);
+                            let button = match &individual.thumbnail {
+                                Some(thumbnail) => egui::Button::image(
+                                    egui::load::SizedTexture::new(thumbnail.id(), size),
+                                ),
+                                None => egui::Button::new("...").min_size(size),
+                            }
+                            .selected(individual.survives);
+                            let mut hover_text = format!(
+                                "id {}, generation {}, parents {:?}",
+                                individual.id, individual.generation, individual.parents
+                            );
+                            if let Some(scores) = &individual.scores {
+                                hover_text += &format!(
+                                    "\nclusters {:.3}\nmotion {:.3}\nstability {:.3}\ntotal {:.3}",
+                                    scores.clusters, scores.motion, scores.stability, scores.total
+                                );
+                            }
+                            if ui
+                                .add_enabled(individual.fitness.is_some(), button)
+                                .on_hover_text(hover_text)
+                                .clicked()
+                            {
+                                individual.survives = !individual.survives;
+                            }
+                            ui.horizontal(|ui| {
+                                if let Some(fitness) = individual.fitness {
+                                    ui.label(format!("{fitness:.3}"));
+                                }
+                                if ui.add_enabled(fits, egui::Button::new("load")).clicked() {
+                                    sim_settings.attractions = individual.attractions.clone();
+                                    sim_settings.local_radius = individual.local_radius;
+                                    sim_settings.friction_half_life = individual.friction_half_life;
+                                }
+                            });
+                        });
+                        if individual_i % 4 == 3 {
+                            ui.end_row();
+                        }
+                    }
+                });
+            });
+    }
+}
+
+impl Individual {
+    /// nudges every attraction by up to settings.mutation_size, and scales local_radius and
+    /// friction_half_life by up to e^mutation_size if they're evolved
+    fn mutate(&mut self, rng: &mut impl Rng, settings: &EvolutionSettings) {
+        let size = settings.mutation_size;
+        for attraction in self.attractions.iter_mut().flatten() {
+            *attraction = (*attraction + rng.gen_range(-size..=size)).clamp(-1.0, 1.0);
+        }
+        if settings.mutate_local_radius {
+            self.local_radius =
+                (self.local_radius * rng.gen_range(-size..=size).exp()).clamp(0.01, 0.5);
+        }
+        if settings.mutate_friction {
+            self.friction_half_life =
+                (self.friction_half_life * rng.gen_range(-size..=size).exp()).clamp(0.001, 1.0);
+        }
+    }
+}
@@ -0,0 +1,330 @@
+use eframe::egui::{self, ecolor, Color32};
+
+/// what the parts of an exported image with no particles look like
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Background {
+    Transparent,
+    Color(Color32),
+}
+
+/// turns what GfxData::screenshot() returns into straight alpha, or puts it over `background`
+/// the colors are premultiplied in linear space, since that's where the gpu blends them
+pub(crate) fn apply_background(premultiplied: &[u8], background: Background) -> Vec<u8> {
+    let to_linear: [f32; 256] =
+        std::array::from_fn(|value| ecolor::linear_f32_from_gamma_u8(value as u8));
+    premultiplied
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            let alpha = pixel[3] as f32 / 255.0;
+            let rgb = [0, 1, 2].map(|i| to_linear[pixel[i] as usize]);
+            match background {
+                Background::Transparent if alpha > 0.0 => {
+                    let [r, g, b] = rgb.map(|c| ecolor::gamma_u8_from_linear_f32(c / alpha));
+                    [r, g, b, pixel[3]]
+                }
+                Background::Transparent => [0; 4],
+                Background::Color(color) => {
+                    let background_rgb =
+                        [color.r(), color.g(), color.b()].map(|c| to_linear[c as usize]);
+                    let [r, g, b] = [0, 1, 2].map(|i| {
+                        ecolor::gamma_u8_from_linear_f32(rgb[i] + background_rgb[i] * (1.0 - alpha))
+                    });
+                    [r, g, b, 255]
+                }
+            }
+        })
+        .collect()
+}
+
+/// writes `rgba`, which is `size` by `size` with straight alpha, to a png at `path`
+pub(crate) fn write_png(
+    path: &std::path::Path,
+    size: u32,
+    rgba: &[u8],
+) -> Result<(), png::EncodingError> {
+    let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let mut encoder = png::Encoder::new(file, size, size);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+    encoder.write_header()?.write_image_data(rgba)
+}
+
+/// a file name in the working directory with the current time in it, like screenshot_1700000000.png
+pub(crate) fn timestamped_path(prefix: &str, extension: &str) -> std::path::PathBuf {
+    format!("{}.{extension}", timestamped_name(prefix)).into()
+}
+
+/// like screenshot_1700000000
+pub(crate) fn timestamped_name(prefix: &str) -> String {
+    let seconds = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    format!("{prefix}_{seconds}")
+}
+
+/// where the frames of a recording go
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RecordingOutput {
+    /// numbered pngs in a new directory
+    PngSequence,
+    /// raw rgba frames piped to the stdin of a command, like ffmpeg
+    Encoder,
+}
+
+enum Sink {
+    PngSequence(std::path::PathBuf),
+    Encoder(std::process::Child),
+    Gif {
+        encoder: gif::Encoder<std::io::BufWriter<std::fs::File>>,
+        path: std::path::PathBuf,
+        palette: Vec<[u8; 3]>,
+        // palette[0] is transparent instead of the background
+        transparent: bool,
+    },
+}
+
+/// frames from GfxData::screenshot() on their way to a png sequence, an encoder or a gif
+pub(crate) struct Recording {
+    // what to pass to GfxData::screenshot(), which is twice size for gifs so they can be downsampled
+    pub(crate) render_size: u32,
+    size: u32,
+    // the sim goes 1 / fps seconds every frame while recording
+    pub(crate) fps: f32,
+    pub(crate) frame_n: usize,
+    // gifs stop by themselves after this many frames
+    frame_limit: Option<usize>,
+    background: Background,
+    sink: Sink,
+}
+impl Recording {
+    /// `encoder_command` is split on whitespace, after replacing {size}, {fps} and {name} in it
+    /// where {name} is like recording_1700000000, so the output doesn't overwrite the last one
+    pub(crate) fn start(
+        output: RecordingOutput,
+        size: u32,
+        fps: f32,
+        background: Background,
+        encoder_command: &str,
+    ) -> std::io::Result<Self> {
+        let name = timestamped_name("recording");
+        let sink = match output {
+            RecordingOutput::PngSequence => {
+                std::fs::create_dir(&name)?;
+                Sink::PngSequence(name.into())
+            }
+            RecordingOutput::Encoder => {
+                let command = encoder_command
+                    .replace("{size}", &size.to_string())
+                    .replace("{fps}", &fps.to_string())
+                    .replace("{name}", &name);
+                let mut words = command.split_whitespace();
+                let program = words.next().ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        "encoder_command is empty",
+                    )
+                })?;
+                let child = std::process::Command::new(program)
+                    .args(words)
+                    .stdin(std::process::Stdio::piped())
+                    .spawn()?;
+                Sink::Encoder(child)
+            }
+        };
+        Ok(Self {
+            render_size: size,
+            size,
+            fps,
+            frame_n: 0,
+            frame_limit: None,
+            background,
+            sink,
+        })
+    }
+
+    /// a gif that's `seconds` long, like gif_1700000000.gif in the working directory
+    /// the colors are snapped to shades of `specie_colors` between the background and the full color,
+    /// so the other ColorModes don't look right in it
+    pub(crate) fn start_gif(
+        size: u32,
+        fps: f32,
+        seconds: f32,
+        background: Background,
+        specie_colors: &[egui::Rgba],
+    ) -> std::io::Result<Self> {
+        const SHADE_N: usize = 4;
+        let (background_color, transparent) = match background {
+            Background::Transparent => (Color32::BLACK, true),
+            Background::Color(color) => (color, false),
+        };
+        let background_rgba = egui::Rgba::from(background_color);
+        let palette = std::iter::once(background_color)
+            .chain(specie_colors.iter().flat_map(|&specie_color| {
+                (1..=SHADE_N).map(move |shade_i| {
+                    let t = shade_i as f32 / SHADE_N as f32;
+                    Color32::from(background_rgba * (1.0 - t) + specie_color * t)
+                })
+            }))
+            .map(|color| [color.r(), color.g(), color.b()])
+            .collect::<Vec<_>>();
+
+        let path = timestamped_path("gif", "gif");
+        let file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        let mut encoder = gif::Encoder::new(file, size as u16, size as u16, palette.as_flattened())
+            .map_err(std::io::Error::other)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(std::io::Error::other)?;
+        Ok(Self {
+            render_size: 2 * size,
+            size,
+            fps,
+            frame_n: 0,
+            frame_limit: Some((seconds * fps).round().max(1.0) as usize),
+            background,
+            sink: Sink::Gif {
+                encoder,
+                path,
+                palette,
+                transparent,
+            },
+        })
+    }
+
+    /// if it's a gif that has all its frames, and should be finished
+    pub(crate) fn is_done(&self) -> bool {
+        self.frame_limit
+            .is_some_and(|frame_limit| self.frame_n >= frame_limit)
+    }
+
+    /// `premultiplied` is what GfxData::screenshot() returns, at self.render_size
+    pub(crate) fn add_frame(&mut self, premultiplied: &[u8]) -> std::io::Result<()> {
+        let rgba = if self.render_size == self.size {
+            apply_background(premultiplied, self.background)
+        } else {
+            apply_background(
+                &downsample(premultiplied, self.render_size),
+                self.background,
+            )
+        };
+        match &mut self.sink {
+            Sink::PngSequence(directory) => {
+                let path = directory.join(format!("frame_{:05}.png", self.frame_n));
+                write_png(&path, self.size, &rgba)?;
+            }
+            Sink::Encoder(child) => {
+                std::io::Write::write_all(child.stdin.as_mut().unwrap(), &rgba)?;
+            }
+            Sink::Gif {
+                encoder,
+                palette,
+                transparent,
+                ..
+            } => {
+                let indices = rgba
+                    .chunks_exact(4)
+                    .map(|pixel| {
+                        if *transparent && pixel[3] < 128 {
+                            return 0;
+                        }
+                        // the nearest color, leaving out the background if it's transparent
+                        let first = *transparent as usize;
+                        (first..palette.len())
+                            .min_by_key(|&i| {
+                                (0..3)
+                                    .map(|c| (pixel[c] as i32 - palette[i][c] as i32).pow(2))
+                                    .sum::<i32>()
+                            })
+                            .unwrap() as u8
+                    })
+                    .collect::<Vec<_>>();
+                encoder
+                    .write_frame(&gif::Frame {
+                        width: self.size as u16,
+                        height: self.size as u16,
+                        buffer: indices.into(),
+                        // in hundredths of a second
+                        delay: (100.0 / self.fps).round() as u16,
+                        transparent: transparent.then_some(0),
+                        dispose: gif::DisposalMethod::Background,
+                        ..Default::default()
+                    })
+                    .map_err(std::io::Error::other)?;
+            }
+        }
+        self.frame_n += 1;
+        Ok(())
+    }
+
+    /// closes the encoder's stdin and waits for it to finish writing
+    /// returns where the recording went, for export_status
+    pub(crate) fn finish(self) -> std::io::Result<String> {
+        match self.sink {
+            Sink::PngSequence(directory) => Ok(format!(
+                "saved {} frames to {}",
+                self.frame_n,
+                directory.display()
+            )),
+            Sink::Encoder(mut child) => {
+                drop(child.stdin.take());
+                let status = child.wait()?;
+                Ok(format!(
+                    "piped {} frames to the encoder, which exited with {status}",
+                    self.frame_n
+                ))
+            }
+            // the encoder writes the end of the gif when it's dropped
+            Sink::Gif { path, .. } => Ok(format!(
+                "saved {} frames to {}",
+                self.frame_n,
+                path.display()
+            )),
+        }
+    }
+}
+
+/// halves the width and height of a square image by averaging each 2 by 2 block
+/// the colors are averaged in linear space like in apply_background(), since averaging the srgb bytes darkens edges
+fn downsample(premultiplied: &[u8], size: u32) -> Vec<u8> {
+    let to_linear: [f32; 256] =
+        std::array::from_fn(|value| ecolor::linear_f32_from_gamma_u8(value as u8));
+    let size = size as usize;
+    let half_size = size / 2;
+    (0..half_size * half_size)
+        .flat_map(|half_i| {
+            let (x, y) = (2 * (half_i % half_size), 2 * (half_i / half_size));
+            std::array::from_fn::<u8, 4, _>(|c| {
+                let block = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                    .map(|(dx, dy)| premultiplied[4 * ((y + dy) * size + x + dx) + c]);
+                if c == 3 {
+                    // alpha isn't gamma encoded
+                    let sum = block.iter().map(|&alpha| alpha as u32).sum::<u32>();
+                    ((sum + 2) / 4) as u8
+                } else {
+                    let sum = block
+                        .iter()
+                        .map(|&value| to_linear[value as usize])
+                        .sum::<f32>();
+                    ecolor::gamma_u8_from_linear_f32(sum / 4.0)
+                }
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::downsample;
+
+    #[test]
+    fn test_downsample_averages_linear_light() {
+        // half white and half transparent, like the edge of a particle
+        let white = [255, 255, 255, 255];
+        let clear = [0; 4];
+        let block = [white, clear, clear, white].concat();
+        let half = downsample(&block, 2);
+        // half of the light is 188 in srgb, not the 128 from averaging the bytes
+        assert_eq!(half, [188, 188, 188, 128]);
+    }
+}
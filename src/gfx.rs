@@ -1,16 +1,45 @@
 use std::sync::Arc;
 
-use eframe::wgpu::{self, util::DeviceExt};
+use eframe::{
+    egui::Vec2,
+    wgpu::{self, util::DeviceExt},
+};
 
-use crate::{SimData, SimSettings, ViewSettings};
+use crate::{
+    bh::{self, BarnesHut},
+    bloom::{self, Bloom},
+    camera,
+    guides::SelectedParticle,
+    overlay::Overlay,
+    pm::ParticleMesh,
+    sort::ParticleSorter,
+    stats::{Stats, StatsReducer},
+    timing::{GpuTimer, GpuTimes},
+    Dimension, OverlayMode, SimData, SimSettings, Solver, ViewSettings,
+};
 
-const PARTICLES_PER_GROUP: usize = 64;
+pub(crate) const COMMON_WGSL: &str = include_str!("common.wgsl");
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+pub(crate) const TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+// 4 is the only count other than 1 that every gpu supports
+const MSAA_SAMPLE_COUNT: u32 = 4;
 
 pub(crate) struct GfxData {
     device: Arc<wgpu::Device>,
     queue: Arc<wgpu::Queue>,
     renderer: Arc<eframe::egui::mutex::RwLock<eframe::egui_wgpu::Renderer>>,
     texture: wgpu::Texture,
+    // only used in 3d
+    depth_texture: Option<wgpu::Texture>,
+    // what gets rendered to and resolved into texture when view_settings.msaa
+    msaa_texture: Option<wgpu::Texture>,
+    sample_count: u32,
+    // what the particles are rendered to, TEXTURE_FORMAT or bloom::HDR_FORMAT
+    color_format: wgpu::TextureFormat,
+    // Some when view_settings.bloom
+    bloom: Option<Bloom>,
+    // made by the first screenshot(), and remade when the size, sample_count or color_format changes
+    screenshot_targets: Option<ScreenshotTargets>,
     pub(crate) texture_id: eframe::egui::TextureId,
     shader_params_buffer: wgpu::Buffer,
     pos_buffer0: wgpu::Buffer,
@@ -18,24 +47,71 @@ pub(crate) struct GfxData {
     pos_buffer1: wgpu::Buffer,
     vel_buffer1: wgpu::Buffer,
     specie_buffer: wgpu::Buffer,
+    // id_buffer[slot] is the stable id of the particle in that slot of the other buffers
+    id_buffer: wgpu::Buffer,
+    // slot_buffer[id] is the slot of the particle with that stable id
+    slot_buffer: wgpu::Buffer,
     attraction_buffer: wgpu::Buffer,
     specie_color_buffer: wgpu::Buffer,
-    vertex_buffer: wgpu::Buffer,
+    // the neighbor count or force of the particle in each slot, for the color modes that need it
+    color_value_buffer: wgpu::Buffer,
+    // the last census::Census::labels, by stable id, for ColorMode::Cluster
+    cluster_label_buffer: wgpu::Buffer,
+    color_value_bind_group: wgpu::BindGroup,
+    color_value_pipeline: wgpu::ComputePipeline,
+    camera_buffer: wgpu::Buffer,
+    shader_module: wgpu::ShaderModule,
+    compute_bind_group_layout: wgpu::BindGroupLayout,
     compute_bind_groups: [wgpu::BindGroup; 2],
     compute_pipeline: wgpu::ComputePipeline,
+    // only in 2d
+    compute_pipeline_tiled: Option<wgpu::ComputePipeline>,
+    // the workgroup size of every compute shader, which is compiled into them as PARTICLES_PER_GROUP
+    particles_per_group: usize,
+    // made when they're first used
+    particle_mesh: Option<ParticleMesh>,
+    barnes_hut: Option<BarnesHut>,
+    sorter: Option<ParticleSorter>,
+    // made when view_settings.show_stats is first on
+    stats_reducer: Option<StatsReducer>,
+    // ticks since the start
+    tick_count: u64,
+    // the sum of the dt of those ticks
+    sim_time: f64,
+    // None if the gpu doesn't support timestamp queries
+    gpu_timer: Option<GpuTimer>,
+    render_bind_group_layout: wgpu::BindGroupLayout,
     render_bind_group: wgpu::BindGroup,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    // Some when view_settings.overlay_mode isn't off, only in 2d
+    overlay: Option<Overlay>,
+    // the particle clicked on for the radius circles, only in 2d
+    selected_particle: Option<SelectedParticle>,
+    // remade when sample_count or color_format changes
     render_pipeline: wgpu::RenderPipeline,
+    // the second pass in 3d, for the fragments that are too transparent to write depth, see main_fs in shader3d.wgsl
+    translucent_render_pipeline: Option<wgpu::RenderPipeline>,
+    // darkens the last frame for trails, also remade when sample_count or color_format changes
+    fade_pipeline: wgpu::RenderPipeline,
     swap_parity: bool,
+    dimension: Dimension,
 }
 impl GfxData {
-    pub(crate) fn new(
-        cc: &eframe::CreationContext<'_>,
+    /// `V` is the type of a position or velocity on the gpu, which depends on `sim_settings.dimension`
+    /// `render_state` is `cc.wgpu_render_state` in the app, or from `headless::create_render_state()`
+    pub(crate) fn new<V: bytemuck::NoUninit>(
+        render_state: &eframe::egui_wgpu::RenderState,
         view_settings: &ViewSettings,
         sim_settings: &SimSettings,
-        sim_data: &SimData,
+        sim_data: &SimData<V>,
     ) -> Self {
-        let render_state = cc.wgpu_render_state.as_ref().unwrap();
         let device = render_state.device.clone();
+        let particles_per_group = sim_settings.workgroup_size;
+        assert!(
+            particles_per_group <= device.limits().max_compute_invocations_per_workgroup
+                && particles_per_group <= device.limits().max_compute_workgroup_size_x,
+            "workgroup_size is bigger than this gpu supports"
+        );
         let queue = render_state.queue.clone();
         let renderer = render_state.renderer.clone();
 
@@ -52,7 +128,7 @@ impl GfxData {
             contents: bytemuck::cast_slice(&sim_data.poses),
             usage: wgpu::BufferUsages::VERTEX
                 | wgpu::BufferUsages::STORAGE
-                // | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_SRC
                 | wgpu::BufferUsages::COPY_DST,
         });
         let vel_buffer0 = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -60,7 +136,7 @@ impl GfxData {
             contents: bytemuck::cast_slice(&sim_data.vels),
             usage: wgpu::BufferUsages::VERTEX
                 | wgpu::BufferUsages::STORAGE
-                // | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_SRC
                 | wgpu::BufferUsages::COPY_DST,
         });
         let pos_buffer1 = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -68,7 +144,7 @@ impl GfxData {
             contents: bytemuck::cast_slice(&sim_data.poses),
             usage: wgpu::BufferUsages::VERTEX
                 | wgpu::BufferUsages::STORAGE
-                // | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_SRC
                 | wgpu::BufferUsages::COPY_DST,
         });
         let vel_buffer1 = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -76,7 +152,7 @@ impl GfxData {
             contents: bytemuck::cast_slice(&sim_data.vels),
             usage: wgpu::BufferUsages::VERTEX
                 | wgpu::BufferUsages::STORAGE
-                // | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_SRC
                 | wgpu::BufferUsages::COPY_DST,
         });
         let specie_buffer: wgpu::Buffer =
@@ -85,8 +161,25 @@ impl GfxData {
                 contents: bytemuck::cast_slice(&sim_data.species),
                 usage: wgpu::BufferUsages::VERTEX
                     | wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_SRC
                     | wgpu::BufferUsages::COPY_DST,
             });
+        // particles get moved around in the buffers when they're sorted, so this is where each one is
+        let identity = (0..sim_settings.particle_n as u32).collect::<Vec<_>>();
+        let id_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("id_buffer"),
+            contents: bytemuck::cast_slice(&identity),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+        });
+        let slot_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("slot_buffer"),
+            contents: bytemuck::cast_slice(&identity),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+        });
         let attraction_buffer: wgpu::Buffer =
             device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("attraction_buffer"),
@@ -106,15 +199,62 @@ impl GfxData {
                 contents: bytemuck::cast_slice(&view_settings.specie_colors),
                 usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
             });
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("vertex_buffer"),
-            contents: bytemuck::bytes_of(&get_triangle(view_settings.particle_radius)),
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        let color_value_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("color_value_buffer"),
+            size: (sim_settings.particle_n * size_of::<f32>()) as _,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        // -1.0 is no cluster, until there's a census
+        let cluster_label_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("cluster_label_buffer"),
+            contents: bytemuck::cast_slice(&vec![-1.0_f32; sim_settings.particle_n]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("camera_buffer"),
+            contents: bytemuck::bytes_of(&CameraParams::new(view_settings)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
         // TODO: make compute_bind_group_layout after the buffers so i can use stuff like specie_buffer.size();
 
         // create compute bind layout group and compute pipeline layout and compute pipeline
-        let shader_module = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shader_module"),
+            source: wgpu::ShaderSource::Wgsl(
+                [
+                    // a pipeline override would be the way to set this, but naga 22 rejects override expressions
+                    // both in @workgroup_size and as the length of a var<workgroup> array like tile_poses and
+                    // stats_scratch, so it's prepended as a const and each workgroup size gets its own shader module
+                    format!("const PARTICLES_PER_GROUP: u32 = {particles_per_group}u;\n").as_str(),
+                    COMMON_WGSL,
+                    match sim_settings.dimension {
+                        Dimension::Two => include_str!("shader.wgsl"),
+                        Dimension::Three => include_str!("shader3d.wgsl"),
+                    },
+                    // the other solvers are only implemented in 2d
+                    match sim_settings.dimension {
+                        Dimension::Two => include_str!("pm.wgsl"),
+                        Dimension::Three => "",
+                    },
+                    match sim_settings.dimension {
+                        Dimension::Two => include_str!("bh.wgsl"),
+                        Dimension::Three => "",
+                    },
+                    match sim_settings.dimension {
+                        Dimension::Two => include_str!("sort.wgsl"),
+                        Dimension::Three => "",
+                    },
+                    match sim_settings.dimension {
+                        Dimension::Two => include_str!("overlay.wgsl"),
+                        Dimension::Three => "",
+                    },
+                    include_str!("stats.wgsl"),
+                ]
+                .concat()
+                .into(),
+            ),
+        });
         let compute_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: Some("compute_bind_group_layout"),
@@ -232,6 +372,91 @@ impl GfxData {
             compilation_options: Default::default(),
             cache: None,
         });
+        let compute_pipeline_tiled = (sim_settings.dimension == Dimension::Two).then(|| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("compute_pipeline_tiled"),
+                layout: Some(&compute_pipeline_layout),
+                module: &shader_module,
+                entry_point: "main_cs_tiled",
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        });
+
+        let color_value_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("color_value_bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(color_value_buffer.size()),
+                        },
+                        count: None,
+                    },
+                    // cluster_label_buffer
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(cluster_label_buffer.size()),
+                        },
+                        count: None,
+                    },
+                    // id_buffer
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(id_buffer.size()),
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let color_value_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("color_value_bind_group"),
+            layout: &color_value_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: color_value_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: cluster_label_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: id_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let color_value_pipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("color_value_pipeline"),
+                layout: Some(
+                    &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                        label: Some("color_value_pipeline_layout"),
+                        bind_group_layouts: &[
+                            &compute_bind_group_layout,
+                            &color_value_bind_group_layout,
+                        ],
+                        push_constant_ranges: &[],
+                    }),
+                ),
+                module: &shader_module,
+                entry_point: "color_value_cs",
+                compilation_options: Default::default(),
+                cache: None,
+            });
 
         // create two bind groups, one for each buffer as the src
         // where the alternate buffer is used as the dst
@@ -294,6 +519,8 @@ impl GfxData {
                 depth_or_array_layers: 1,
             },
         );
+        let depth_texture = (sim_settings.dimension == Dimension::Three)
+            .then(|| create_depth_texture(&device, texture.size(), 1));
         let texture_id = renderer.write().register_native_texture(
             &device,
             &texture.create_view(&wgpu::TextureViewDescriptor::default()),
@@ -329,6 +556,18 @@ impl GfxData {
                         },
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 8,
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(
+                                (size_of::<CameraParams>()) as _,
+                            ),
+                        },
+                        count: None,
+                    },
                 ],
             });
         let render_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -343,6 +582,10 @@ impl GfxData {
                     binding: 7,
                     resource: specie_color_buffer.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: camera_buffer.as_entire_binding(),
+                },
             ],
         });
         let render_pipeline_layout =
@@ -352,63 +595,47 @@ impl GfxData {
                 push_constant_ranges: &[],
             });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("render_pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader_module,
-                entry_point: "main_vs",
-                compilation_options: Default::default(),
-                buffers: &[
-                    // @location(0) vertex_pos: vec2<f32>,
-                    wgpu::VertexBufferLayout {
-                        array_stride: 4 * 2,
-                        step_mode: wgpu::VertexStepMode::Vertex,
-                        attributes: &wgpu::vertex_attr_array![0 => Float32x2],
-                    },
-                    // @location(1) particle_pos: vec2<f32>,
-                    wgpu::VertexBufferLayout {
-                        array_stride: 4 * 2,
-                        step_mode: wgpu::VertexStepMode::Instance,
-                        attributes: &wgpu::vertex_attr_array![1 => Float32x2],
-                    },
-                    // @location(2) particle_vel: vec2<f32>,
-                    wgpu::VertexBufferLayout {
-                        array_stride: 4 * 2,
-                        step_mode: wgpu::VertexStepMode::Instance,
-                        attributes: &wgpu::vertex_attr_array![2 => Float32x2],
-                    },
-                    // @location(3) particle_species: u32,
-                    wgpu::VertexBufferLayout {
-                        array_stride: 4,
-                        step_mode: wgpu::VertexStepMode::Instance,
-                        attributes: &wgpu::vertex_attr_array![3 => Uint32],
-                    },
-                ],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader_module,
-                entry_point: "main_fs",
-                compilation_options: Default::default(),
-                // targets: &[Some(config.view_formats[0].into())],
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: texture.format(),
-                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                    write_mask: wgpu::ColorWrites::all(),
-                })],
-            }),
-            primitive: wgpu::PrimitiveState::default(),
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None,
-            cache: None,
+        let render_pipeline = create_render_pipeline(
+            &device,
+            &render_pipeline_layout,
+            &shader_module,
+            sim_settings.dimension,
+            TEXTURE_FORMAT,
+            1,
+            false,
+        );
+        let translucent_render_pipeline = (sim_settings.dimension == Dimension::Three).then(|| {
+            create_render_pipeline(
+                &device,
+                &render_pipeline_layout,
+                &shader_module,
+                sim_settings.dimension,
+                TEXTURE_FORMAT,
+                1,
+                true,
+            )
         });
+        let fade_pipeline = create_fade_pipeline(
+            &device,
+            &shader_module,
+            sim_settings.dimension,
+            TEXTURE_FORMAT,
+            1,
+        );
+
+        let gpu_timer = GpuTimer::new(&device, &queue);
 
         Self {
             device,
             queue,
             renderer,
             texture,
+            depth_texture,
+            msaa_texture: None,
+            sample_count: 1,
+            color_format: TEXTURE_FORMAT,
+            bloom: None,
+            screenshot_targets: None,
             texture_id,
             shader_params_buffer,
             pos_buffer0,
@@ -416,14 +643,38 @@ impl GfxData {
             pos_buffer1,
             vel_buffer1,
             specie_buffer,
+            id_buffer,
+            slot_buffer,
             attraction_buffer,
             specie_color_buffer,
+            color_value_buffer,
+            cluster_label_buffer,
+            color_value_bind_group,
+            color_value_pipeline,
+            camera_buffer,
+            shader_module,
+            compute_bind_group_layout,
             compute_bind_groups,
             compute_pipeline,
-            vertex_buffer,
+            compute_pipeline_tiled,
+            particles_per_group: particles_per_group as _,
+            particle_mesh: None,
+            barnes_hut: None,
+            sorter: None,
+            stats_reducer: None,
+            tick_count: 0,
+            sim_time: 0.0,
+            gpu_timer,
+            render_bind_group_layout,
             render_bind_group,
+            render_pipeline_layout,
+            overlay: None,
+            selected_particle: None,
             render_pipeline,
+            translucent_render_pipeline,
+            fade_pipeline,
             swap_parity: false,
+            dimension: sim_settings.dimension,
         }
     }
 
@@ -434,46 +685,47 @@ impl GfxData {
                     label: Some("command_encoder"),
                 });
 
-        self.queue.write_buffer(
-            &self.shader_params_buffer,
-            0,
-            bytemuck::bytes_of(&ShaderParams::new(view_settings, sim_settings)),
-        );
-
-        // compute pass
-        command_encoder.push_debug_group("compute_pass");
+        self.record_compute(&mut command_encoder, view_settings, sim_settings);
+        if view_settings
+            .shown_color_mode(sim_settings)
+            .needs_color_values()
         {
-            self.queue.write_buffer(
-                &self.attraction_buffer,
+            let mut compute_pass =
+                command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("color_value_pass"),
+                    timestamp_writes: None,
+                });
+            compute_pass.set_pipeline(&self.color_value_pipeline);
+            compute_pass.set_bind_group(
                 0,
-                bytemuck::cast_slice(
-                    &sim_settings
-                        .attractions
-                        .clone()
-                        .into_iter()
-                        .flatten()
-                        .collect::<Vec<f32>>(),
-                ),
+                &self.compute_bind_groups[self.swap_parity as usize],
+                &[],
+            );
+            compute_pass.set_bind_group(1, &self.color_value_bind_group, &[]);
+            compute_pass.dispatch_workgroups(
+                sim_settings.particle_n.div_ceil(self.particles_per_group) as _,
+                1,
+                1,
+            );
+        }
+        if view_settings.show_stats {
+            let stats_reducer = self.stats_reducer.get_or_insert_with(|| {
+                StatsReducer::new(
+                    &self.device,
+                    &self.shader_module,
+                    &self.compute_bind_group_layout,
+                    self.particles_per_group,
+                    sim_settings.specie_n,
+                    sim_settings.particle_n,
+                )
+            });
+            stats_reducer.dispatch(
+                &mut command_encoder,
+                &self.compute_bind_groups[self.swap_parity as usize],
+                sim_settings.particle_n,
+                self.sim_time,
             );
-            for _ in 0..sim_settings.substep_n {
-                let mut compute_pass =
-                    command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                        label: Some("compute_pass"),
-                        timestamp_writes: None,
-                    });
-                compute_pass.set_pipeline(&self.compute_pipeline);
-                compute_pass.set_bind_group(
-                    0,
-                    &self.compute_bind_groups[self.swap_parity as usize],
-                    &[],
-                );
-                let work_group_count =
-                    ((sim_settings.particle_n as f32) / (PARTICLES_PER_GROUP as f32)).ceil() as u32;
-                compute_pass.dispatch_workgroups(work_group_count, 1, 1);
-                self.swap_parity = !self.swap_parity;
-            }
         }
-        command_encoder.pop_debug_group();
 
         // let cpu_readable_buffer;
         // {
@@ -519,25 +771,85 @@ impl GfxData {
                 height: view_settings.texture_size,
                 depth_or_array_layers: 1,
             };
+            let sample_count = if view_settings.msaa {
+                MSAA_SAMPLE_COUNT
+            } else {
+                1
+            };
+            let color_format = if view_settings.bloom {
+                bloom::HDR_FORMAT
+            } else {
+                TEXTURE_FORMAT
+            };
             // dbg!(self.texture.size());
-            if self.texture.size() != new_size {
-                println!("self.texture.size() != new_size");
-                self.texture = create_texture(&self.device, new_size);
-                self.renderer.write().update_egui_texture_from_wgpu_texture(
-                    &self.device,
-                    &self
-                        .texture
-                        .create_view(&wgpu::TextureViewDescriptor::default()),
-                    eframe::wgpu::FilterMode::Nearest,
-                    self.texture_id,
-                );
+            if self.texture.size() != new_size
+                || self.sample_count != sample_count
+                || self.color_format != color_format
+            {
+                if self.texture.size() != new_size {
+                    println!("self.texture.size() != new_size");
+                    self.texture = create_texture(&self.device, new_size);
+                    self.renderer.write().update_egui_texture_from_wgpu_texture(
+                        &self.device,
+                        &self
+                            .texture
+                            .create_view(&wgpu::TextureViewDescriptor::default()),
+                        eframe::wgpu::FilterMode::Nearest,
+                        self.texture_id,
+                    );
+                }
+                if self.sample_count != sample_count || self.color_format != color_format {
+                    self.render_pipeline = create_render_pipeline(
+                        &self.device,
+                        &self.render_pipeline_layout,
+                        &self.shader_module,
+                        self.dimension,
+                        color_format,
+                        sample_count,
+                        false,
+                    );
+                    if self.translucent_render_pipeline.is_some() {
+                        self.translucent_render_pipeline = Some(create_render_pipeline(
+                            &self.device,
+                            &self.render_pipeline_layout,
+                            &self.shader_module,
+                            self.dimension,
+                            color_format,
+                            sample_count,
+                            true,
+                        ));
+                    }
+                    self.fade_pipeline = create_fade_pipeline(
+                        &self.device,
+                        &self.shader_module,
+                        self.dimension,
+                        color_format,
+                        sample_count,
+                    );
+                    self.sample_count = sample_count;
+                    self.color_format = color_format;
+                }
+                if self.depth_texture.is_some() {
+                    self.depth_texture =
+                        Some(create_depth_texture(&self.device, new_size, sample_count));
+                }
+                self.msaa_texture = (sample_count > 1).then(|| {
+                    create_msaa_texture(&self.device, new_size, color_format, sample_count)
+                });
+            }
+            if !view_settings.bloom {
+                self.bloom = None;
+            } else if self
+                .bloom
+                .as_ref()
+                .is_none_or(|bloom| bloom.size != new_size)
+            {
+                self.bloom = Some(Bloom::new(&self.device, new_size, TEXTURE_FORMAT));
             }
             self.queue.write_buffer(
-                &self.vertex_buffer,
+                &self.camera_buffer,
                 0,
-                bytemuck::bytes_of(&get_triangle(
-                    view_settings.particle_radius * view_settings.zoom_scale,
-                )),
+                bytemuck::bytes_of(&CameraParams::new(view_settings)),
             );
             self.queue.write_buffer(
                 &self.specie_color_buffer,
@@ -548,6 +860,11 @@ impl GfxData {
             let texture_view = self
                 .texture
                 .create_view(&wgpu::TextureViewDescriptor::default());
+            // with bloom the particles go to the hdr texture, which gets tonemapped into texture after
+            let color_view = match &self.bloom {
+                Some(bloom) => bloom.hdr_view(),
+                None => self.texture.create_view(&Default::default()),
+            };
             // let color_attachments = [Some(wgpu::RenderPassColorAttachment {
             //     view: &texture_view,
             //     resolve_target: None,
@@ -558,43 +875,651 @@ impl GfxData {
             //         store: wgpu::StoreOp::Store,
             //     },
             // })];
-            let color_attachments = [Some(wgpu::RenderPassColorAttachment {
-                view: &texture_view,
-                resolve_target: None,
-                ops: wgpu::Operations::default(),
-            })];
-            let render_pass_descriptor = wgpu::RenderPassDescriptor {
-                label: Some("render_pass_descriptor"),
-                color_attachments: &color_attachments,
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            };
-            let mut render_pass = command_encoder.begin_render_pass(&render_pass_descriptor);
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.render_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_vertex_buffer(
-                1,
-                [&self.pos_buffer0, &self.pos_buffer1][self.swap_parity as usize].slice(..),
-            );
-            render_pass.set_vertex_buffer(
-                2,
-                [&self.vel_buffer0, &self.vel_buffer1][self.swap_parity as usize].slice(..),
+            let msaa_view = self
+                .msaa_texture
+                .as_ref()
+                .map(|msaa_texture| msaa_texture.create_view(&Default::default()));
+            let depth_view = self
+                .depth_texture
+                .as_ref()
+                .map(|depth_texture| depth_texture.create_view(&Default::default()));
+            // trail_length is the half life, and sim_settings.dt is the time since the last frame
+            let trail_decay = (view_settings.trail_length > 0.0)
+                .then(|| 0.5_f64.powf((sim_settings.dt / view_settings.trail_length) as f64));
+            self.record_particles(
+                &mut command_encoder,
+                &color_view,
+                msaa_view.as_ref(),
+                depth_view.as_ref(),
+                trail_decay,
+                self.gpu_timer
+                    .as_ref()
+                    .map(|gpu_timer| gpu_timer.render_timestamp_writes()),
+                sim_settings.particle_n,
             );
-            render_pass.set_vertex_buffer(3, self.specie_buffer.slice(..));
-            render_pass.draw(0..3, 0..sim_settings.particle_n as _);
+
+            if let Some(bloom) = &self.bloom {
+                bloom.dispatch(
+                    &self.queue,
+                    &mut command_encoder,
+                    &texture_view,
+                    view_settings,
+                );
+            }
         }
         command_encoder.pop_debug_group();
 
+        if view_settings.overlay_mode != OverlayMode::Off && self.dimension == Dimension::Two {
+            self.render_overlay(&mut command_encoder, sim_settings);
+        } else if let Some(overlay) = self.overlay.take() {
+            self.renderer.write().free_texture(&overlay.texture_id);
+        }
+
+        if let Some(selected_particle) = self
+            .selected_particle
+            .as_mut()
+            .filter(|_| view_settings.show_radius)
+        {
+            selected_particle.copy(
+                &mut command_encoder,
+                &self.id_buffer,
+                &self.slot_buffer,
+                [&self.pos_buffer0, &self.pos_buffer1][self.swap_parity as usize],
+            );
+        }
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.resolve(&mut command_encoder);
+        }
         self.queue.submit([command_encoder.finish()]);
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.start_readback();
+        }
+        if let Some(stats_reducer) = &mut self.stats_reducer {
+            stats_reducer.start_readback();
+        }
+        if let Some(selected_particle) = &mut self.selected_particle {
+            selected_particle.start_readback();
+        }
         // dbg!(cpu_readable_buffer);
     }
+
+    /// fills the particle mesh grids from the current positions and draws them to the overlay texture
+    fn render_overlay(
+        &mut self,
+        command_encoder: &mut wgpu::CommandEncoder,
+        sim_settings: &SimSettings,
+    ) {
+        self.update_particle_mesh(sim_settings);
+        let particle_mesh = self.particle_mesh.as_ref().unwrap();
+        particle_mesh.dispatch_field(
+            command_encoder,
+            &self.compute_bind_groups[self.swap_parity as usize],
+            sim_settings.specie_n,
+            sim_settings.particle_n,
+        );
+        if self.overlay.as_ref().is_none_or(|overlay| {
+            overlay.size != self.texture.size() || overlay.grid_size != particle_mesh.grid_size
+        }) {
+            let mut renderer = self.renderer.write();
+            if let Some(overlay) = self.overlay.take() {
+                renderer.free_texture(&overlay.texture_id);
+            }
+            self.overlay = Some(Overlay::new(
+                &self.device,
+                &mut renderer,
+                &self.shader_module,
+                &self.render_bind_group_layout,
+                particle_mesh,
+                self.texture.size(),
+            ));
+        }
+        self.overlay
+            .as_ref()
+            .unwrap()
+            .dispatch(command_encoder, &self.render_bind_group);
+    }
+
+    /// the texture to paint over the particles, if view_settings.overlay_mode isn't off
+    pub(crate) fn overlay_texture_id(&self) -> Option<eframe::egui::TextureId> {
+        self.overlay.as_ref().map(|overlay| overlay.texture_id)
+    }
+
+    /// draws the particles to `color_view`, through `msaa_view` and with `depth_view` if the pipelines need them
+    /// with `trail_decay` the last frame is multiplied by it instead of cleared
+    #[allow(clippy::too_many_arguments)]
+    fn record_particles(
+        &self,
+        command_encoder: &mut wgpu::CommandEncoder,
+        color_view: &wgpu::TextureView,
+        msaa_view: Option<&wgpu::TextureView>,
+        depth_view: Option<&wgpu::TextureView>,
+        trail_decay: Option<f64>,
+        timestamp_writes: Option<wgpu::RenderPassTimestampWrites>,
+        particle_n: usize,
+    ) {
+        let load = if trail_decay.is_some() {
+            wgpu::LoadOp::Load
+        } else {
+            wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT)
+        };
+        let color_attachments = [Some(match msaa_view {
+            Some(msaa_view) => wgpu::RenderPassColorAttachment {
+                view: msaa_view,
+                resolve_target: Some(color_view),
+                ops: wgpu::Operations {
+                    load,
+                    // only the resolved texture is needed, unless the next frame loads this one
+                    store: if trail_decay.is_some() {
+                        wgpu::StoreOp::Store
+                    } else {
+                        wgpu::StoreOp::Discard
+                    },
+                },
+            },
+            None => wgpu::RenderPassColorAttachment {
+                view: color_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load,
+                    store: wgpu::StoreOp::Store,
+                },
+            },
+        })];
+        let render_pass_descriptor = wgpu::RenderPassDescriptor {
+            label: Some("render_pass_descriptor"),
+            color_attachments: &color_attachments,
+            depth_stencil_attachment: depth_view.map(|depth_view| {
+                wgpu::RenderPassDepthStencilAttachment {
+                    view: depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Discard,
+                    }),
+                    stencil_ops: None,
+                }
+            }),
+            timestamp_writes,
+            occlusion_query_set: None,
+        };
+        let mut render_pass = command_encoder.begin_render_pass(&render_pass_descriptor);
+        if let Some(decay) = trail_decay {
+            render_pass.set_pipeline(&self.fade_pipeline);
+            render_pass.set_blend_constant(wgpu::Color {
+                r: decay,
+                g: decay,
+                b: decay,
+                a: decay,
+            });
+            render_pass.draw(0..3, 0..1);
+        }
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.render_bind_group, &[]);
+        render_pass.set_vertex_buffer(
+            0,
+            [&self.pos_buffer0, &self.pos_buffer1][self.swap_parity as usize].slice(..),
+        );
+        render_pass.set_vertex_buffer(
+            1,
+            [&self.vel_buffer0, &self.vel_buffer1][self.swap_parity as usize].slice(..),
+        );
+        render_pass.set_vertex_buffer(2, self.specie_buffer.slice(..));
+        render_pass.set_vertex_buffer(3, self.color_value_buffer.slice(..));
+        // the quads are made from the vertex index in main_vs, which draws 4 copies of them in 2d
+        let vertex_n = match self.dimension {
+            Dimension::Two => 4 * 6,
+            Dimension::Three => 6,
+        };
+        render_pass.draw(0..vertex_n, 0..particle_n as _);
+        if let Some(translucent_render_pipeline) = &self.translucent_render_pipeline {
+            render_pass.set_pipeline(translucent_render_pipeline);
+            render_pass.draw(0..vertex_n, 0..particle_n as _);
+        }
+    }
+
+    /// draws the particles again to a `size` by `size` texture and copies it to the cpu,
+    /// as rgba with the colors premultiplied by alpha and the background transparent
+    /// it uses the msaa and bloom of the last render(), but not the trails or the overlay
+    /// blocks until the gpu is done
+    pub(crate) fn screenshot(
+        &mut self,
+        view_settings: &ViewSettings,
+        sim_settings: &SimSettings,
+        size: u32,
+    ) -> Vec<u8> {
+        let size = wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        };
+        // the antialiasing and min_particle_pixels are in pixels, so they depend on the texture size
+        let view_settings = ViewSettings {
+            texture_size: size.width,
+            ..view_settings.clone()
+        };
+        self.queue.write_buffer(
+            &self.shader_params_buffer,
+            0,
+            bytemuck::bytes_of(&ShaderParams::new(&view_settings, sim_settings)),
+        );
+        // the render pipelines were made for the sample count and format of the last render()
+        if self.screenshot_targets.as_ref().is_none_or(|targets| {
+            targets.texture.size() != size
+                || targets.sample_count != self.sample_count
+                || targets.color_format != self.color_format
+        }) {
+            self.screenshot_targets = Some(ScreenshotTargets::new(
+                &self.device,
+                size,
+                self.sample_count,
+                self.color_format,
+                self.depth_texture.is_some(),
+            ));
+        }
+        let targets = self.screenshot_targets.as_ref().unwrap();
+        let texture_view = targets.texture.create_view(&Default::default());
+        let color_view = match &targets.bloom {
+            Some(bloom) => bloom.hdr_view(),
+            None => targets.texture.create_view(&Default::default()),
+        };
+        let msaa_view = targets
+            .msaa_texture
+            .as_ref()
+            .map(|msaa_texture| msaa_texture.create_view(&Default::default()));
+        let depth_view = targets
+            .depth_texture
+            .as_ref()
+            .map(|depth_texture| depth_texture.create_view(&Default::default()));
+
+        let mut command_encoder = self.device.create_command_encoder(&Default::default());
+        self.record_particles(
+            &mut command_encoder,
+            &color_view,
+            msaa_view.as_ref(),
+            depth_view.as_ref(),
+            None,
+            None,
+            sim_settings.particle_n,
+        );
+        if let Some(bloom) = &targets.bloom {
+            bloom.dispatch(
+                &self.queue,
+                &mut command_encoder,
+                &texture_view,
+                &view_settings,
+            );
+        }
+        self.queue.submit([command_encoder.finish()]);
+        read_texture(
+            &self.device,
+            &self.queue,
+            &targets.texture,
+            &targets.staging_buffer,
+        )
+    }
+
+    /// does sim_settings.substep_n ticks without rendering
+    /// doesn't wait for the gpu, see wait()
+    pub(crate) fn step(&mut self, view_settings: &ViewSettings, sim_settings: &SimSettings) {
+        let mut command_encoder =
+            self.device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("command_encoder"),
+                });
+        self.record_compute(&mut command_encoder, view_settings, sim_settings);
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.resolve(&mut command_encoder);
+        }
+        self.queue.submit([command_encoder.finish()]);
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.start_readback();
+        }
+    }
+
+    /// blocks until the gpu has finished everything submitted so far
+    pub(crate) fn wait(&self) {
+        self.device.poll(wgpu::Maintain::Wait);
+    }
+
+    /// the biggest screenshot() the gpu can do
+    pub(crate) fn max_texture_size(&self) -> u32 {
+        self.device.limits().max_texture_dimension_2d
+    }
+
+    pub(crate) fn has_gpu_timer(&self) -> bool {
+        self.gpu_timer.is_some()
+    }
+
+    /// uploads the settings and records sim_settings.substep_n ticks
+    fn record_compute(
+        &mut self,
+        command_encoder: &mut wgpu::CommandEncoder,
+        view_settings: &ViewSettings,
+        sim_settings: &SimSettings,
+    ) {
+        self.queue.write_buffer(
+            &self.shader_params_buffer,
+            0,
+            bytemuck::bytes_of(&ShaderParams::new(view_settings, sim_settings)),
+        );
+
+        command_encoder.push_debug_group("compute_pass");
+        {
+            self.queue.write_buffer(
+                &self.attraction_buffer,
+                0,
+                bytemuck::cast_slice(
+                    &sim_settings
+                        .attractions
+                        .clone()
+                        .into_iter()
+                        .flatten()
+                        .collect::<Vec<f32>>(),
+                ),
+            );
+            if let Some(gpu_timer) = &self.gpu_timer {
+                gpu_timer.mark_compute_start(command_encoder);
+            }
+            for _ in 0..sim_settings.substep_n {
+                self.dispatch_tick(command_encoder, sim_settings);
+            }
+            if let Some(gpu_timer) = &self.gpu_timer {
+                gpu_timer.mark_compute_end(command_encoder);
+            }
+        }
+        command_encoder.pop_debug_group();
+    }
+
+    /// records one simulation tick with `sim_settings.solver` and swaps the buffers
+    fn dispatch_tick(
+        &mut self,
+        command_encoder: &mut wgpu::CommandEncoder,
+        sim_settings: &SimSettings,
+    ) {
+        let compute_bind_group = &self.compute_bind_groups[self.swap_parity as usize];
+        match sim_settings.solver {
+            Solver::Pairwise | Solver::PairwiseTiled => {
+                let mut compute_pass =
+                    command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                        label: Some("compute_pass"),
+                        timestamp_writes: None,
+                    });
+                compute_pass.set_pipeline(match sim_settings.solver {
+                    Solver::PairwiseTiled => self.compute_pipeline_tiled.as_ref().unwrap(),
+                    _ => &self.compute_pipeline,
+                });
+                compute_pass.set_bind_group(0, compute_bind_group, &[]);
+                let work_group_count = ((sim_settings.particle_n as f32)
+                    / (self.particles_per_group as f32))
+                    .ceil() as u32;
+                compute_pass.dispatch_workgroups(work_group_count, 1, 1);
+            }
+            Solver::ParticleMesh => {
+                self.update_particle_mesh(sim_settings);
+                self.particle_mesh.as_ref().unwrap().dispatch(
+                    command_encoder,
+                    &self.compute_bind_groups[self.swap_parity as usize],
+                    sim_settings.specie_n,
+                    sim_settings.particle_n,
+                );
+            }
+            Solver::BarnesHut => {
+                self.update_barnes_hut(sim_settings);
+                self.barnes_hut.as_ref().unwrap().dispatch(
+                    command_encoder,
+                    &self.compute_bind_groups[self.swap_parity as usize],
+                    sim_settings.specie_n,
+                    sim_settings.particle_n,
+                );
+            }
+        }
+        self.swap_parity = !self.swap_parity;
+        self.tick_count += 1;
+        self.sim_time += sim_settings.tick_dt() as f64;
+
+        if self.dimension == Dimension::Two
+            && sim_settings.sort_interval != 0
+            && self
+                .tick_count
+                .is_multiple_of(sim_settings.sort_interval as u64)
+        {
+            let sorter = self.sorter.get_or_insert_with(|| {
+                ParticleSorter::new(
+                    &self.device,
+                    &self.shader_module,
+                    &self.compute_bind_group_layout,
+                    self.particles_per_group,
+                    sim_settings.particle_n,
+                    &self.id_buffer,
+                    &self.slot_buffer,
+                )
+            });
+            sorter.dispatch(
+                command_encoder,
+                &self.compute_bind_groups[self.swap_parity as usize],
+                sim_settings.particle_n,
+                &self.specie_buffer,
+                &self.id_buffer,
+            );
+            self.swap_parity = !self.swap_parity;
+        }
+    }
+
+    /// how long the last timed frame took on the gpu, if it's finished since the last call
+    /// always None if the gpu doesn't support timestamp queries
+    pub(crate) fn take_gpu_times(&mut self) -> Option<GpuTimes> {
+        self.gpu_timer.as_mut()?.take_times(&self.device)
+    }
+
+    /// the statistics from the last render() with view_settings.show_stats that's finished reading back,
+    /// if one has since the last call
+    pub(crate) fn take_stats(&mut self, particle_n: usize) -> Option<Stats> {
+        self.stats_reducer
+            .as_mut()?
+            .take_stats(&self.device, particle_n)
+    }
+
+    /// what ColorMode::Cluster shows, census::Census::labels
+    pub(crate) fn set_cluster_labels(&self, labels: &[f32]) {
+        self.queue
+            .write_buffer(&self.cluster_label_buffer, 0, bytemuck::cast_slice(labels));
+    }
+
+    // (re)makes self.particle_mesh if it doesn't match sim_settings
+    fn update_particle_mesh(&mut self, sim_settings: &SimSettings) {
+        if self
+            .particle_mesh
+            .as_ref()
+            .is_none_or(|particle_mesh| particle_mesh.grid_size != sim_settings.pm_grid_size)
+        {
+            self.particle_mesh = Some(ParticleMesh::new(
+                &self.device,
+                &self.shader_module,
+                &self.compute_bind_group_layout,
+                self.particles_per_group,
+                sim_settings.specie_n,
+                sim_settings.pm_grid_size,
+            ));
+        }
+    }
+
+    // (re)makes self.barnes_hut if it doesn't match sim_settings
+    fn update_barnes_hut(&mut self, sim_settings: &SimSettings) {
+        if self
+            .barnes_hut
+            .as_ref()
+            .is_none_or(|barnes_hut| barnes_hut.depth != sim_settings.bh_depth)
+        {
+            self.barnes_hut = Some(BarnesHut::new(
+                &self.device,
+                &self.shader_module,
+                &self.compute_bind_group_layout,
+                self.particles_per_group,
+                sim_settings.specie_n,
+                sim_settings.bh_depth,
+            ));
+        }
+    }
+
+    pub(crate) fn tick_count(&self) -> u64 {
+        self.tick_count
+    }
+
+    /// seconds of sim time since the start
+    pub(crate) fn sim_time(&self) -> f64 {
+        self.sim_time
+    }
+
+    /// every particle's position, velocity and specie in stable id order, undoing the sorting
+    /// z is 0.0 in 2d, and it blocks until the gpu is done
+    pub(crate) fn read_sim_data(&self) -> SimData<[f32; 3]> {
+        // the 3d buffers are padded to vec4s
+        let stride = match self.dimension {
+            Dimension::Two => 2,
+            Dimension::Three => 4,
+        };
+        let poses: Vec<f32> = read_buffer(
+            &self.device,
+            &self.queue,
+            [&self.pos_buffer0, &self.pos_buffer1][self.swap_parity as usize],
+        );
+        let vels: Vec<f32> = read_buffer(
+            &self.device,
+            &self.queue,
+            [&self.vel_buffer0, &self.vel_buffer1][self.swap_parity as usize],
+        );
+        let species: Vec<u32> = read_buffer(&self.device, &self.queue, &self.specie_buffer);
+        let slots: Vec<u32> = read_buffer(&self.device, &self.queue, &self.slot_buffer);
+        let by_id = |values: &[f32]| {
+            slots
+                .iter()
+                .map(|&slot| {
+                    let value = &values[stride * slot as usize..];
+                    [value[0], value[1], if stride > 2 { value[2] } else { 0.0 }]
+                })
+                .collect()
+        };
+        SimData {
+            poses: by_id(&poses),
+            vels: by_id(&vels),
+            species: slots.iter().map(|&slot| species[slot as usize]).collect(),
+        }
+    }
+
+    /// selects the particle nearest to `pos` for the radius circles to follow, if it's within `max_distance`
+    /// across the wrapping walls, and deselects otherwise
+    /// only in 2d, and blocks until the gpu is done, but only when it's clicked
+    pub(crate) fn select_particle(&mut self, pos: Vec2, max_distance: f32) {
+        let poses: Vec<Vec2> = read_buffer(
+            &self.device,
+            &self.queue,
+            [&self.pos_buffer0, &self.pos_buffer1][self.swap_parity as usize],
+        );
+        self.selected_particle = poses
+            .iter()
+            .map(|&particle_pos| {
+                let offset = particle_pos - pos;
+                (offset - offset.round()).length()
+            })
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .filter(|&(_, distance)| distance <= max_distance)
+            .map(|(slot, _)| {
+                let id: Vec<u32> = read_buffer_range(
+                    &self.device,
+                    &self.queue,
+                    &self.id_buffer,
+                    4 * slot as u64..4 * (slot as u64 + 1),
+                );
+                SelectedParticle::new(&self.device, id[0], slot as u32, poses[slot])
+            });
+    }
+
+    /// where the selected particle was a frame or two ago, see SelectedParticle
+    pub(crate) fn selected_particle_pos(&mut self) -> Option<Vec2> {
+        Some(self.selected_particle.as_mut()?.pos(&self.device))
+    }
+
+    /// compares the barnes-hut forces with sim_settings.bh_theta to the exact pairwise ones on the current positions
+    /// blocks until the gpu is done, see BarnesHut::measure_error
+    pub(crate) fn measure_bh_error(
+        &mut self,
+        view_settings: &ViewSettings,
+        sim_settings: &SimSettings,
+    ) -> bh::ErrorReport {
+        self.queue.write_buffer(
+            &self.shader_params_buffer,
+            0,
+            bytemuck::bytes_of(&ShaderParams::new(view_settings, sim_settings)),
+        );
+        self.update_barnes_hut(sim_settings);
+        self.barnes_hut.as_ref().unwrap().measure_error(
+            &self.device,
+            &self.queue,
+            &self.compute_bind_groups[self.swap_parity as usize],
+            sim_settings.specie_n,
+            sim_settings.particle_n,
+        )
+    }
+}
+// the renderer is shared with the app, so the textures would stay registered in it otherwise,
+// like when the evolution window makes one of these for each candidate
+impl Drop for GfxData {
+    fn drop(&mut self) {
+        let mut renderer = self.renderer.write();
+        renderer.free_texture(&self.texture_id);
+        if let Some(overlay) = self.overlay.take() {
+            renderer.free_texture(&overlay.texture_id);
+        }
+    }
+}
+
+/// what GfxData::screenshot() renders to, kept between screenshots so a recording doesn't remake them every frame
+struct ScreenshotTargets {
+    texture: wgpu::Texture,
+    sample_count: u32,
+    color_format: wgpu::TextureFormat,
+    // Some when color_format is bloom::HDR_FORMAT
+    bloom: Option<Bloom>,
+    msaa_texture: Option<wgpu::Texture>,
+    // only in 3d
+    depth_texture: Option<wgpu::Texture>,
+    // what texture is copied to for reading back
+    staging_buffer: wgpu::Buffer,
+}
+impl ScreenshotTargets {
+    fn new(
+        device: &wgpu::Device,
+        size: wgpu::Extent3d,
+        sample_count: u32,
+        color_format: wgpu::TextureFormat,
+        has_depth: bool,
+    ) -> Self {
+        Self {
+            texture: device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("screenshot_texture"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: TEXTURE_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            }),
+            sample_count,
+            color_format,
+            bloom: (color_format == bloom::HDR_FORMAT)
+                .then(|| Bloom::new(device, size, TEXTURE_FORMAT)),
+            msaa_texture: (sample_count > 1)
+                .then(|| create_msaa_texture(device, size, color_format, sample_count)),
+            depth_texture: has_depth.then(|| create_depth_texture(device, size, sample_count)),
+            staging_buffer: create_texture_staging_buffer(device, size),
+        }
+    }
 }
 
 #[derive(Clone, Copy, bytemuck::NoUninit)]
 #[repr(C)]
-struct ShaderParams {
+pub(crate) struct ShaderParams {
     specie_n: u32,
     particle_n: u32,
     local_radius: f32,
@@ -610,12 +1535,25 @@ struct ShaderParams {
     // zoom_center: eframe::egui::Vec2,
     zoom_center_x: f32,
     zoom_center_y: f32,
+    pm_grid_size: u32,
+    bh_depth: u32,
+    bh_theta: f32,
+    min_particle_radius: f32,
+    antialias_width: f32,
+    color_mode: u32,
+    color_min: f32,
+    color_max: f32,
+    overlay_mode: u32,
+    overlay_specie: u32,
+    overlay_scale: f32,
+    glyph: u32,
+    glyph_length: f32,
 }
 impl ShaderParams {
-    fn new(view_settings: &ViewSettings, sim_settings: &SimSettings) -> Self {
-        let dt = (sim_settings.time_scale * sim_settings.dt / sim_settings.substep_n as f32)
-            .min(1.0 / 30.0);
+    pub(crate) fn new(view_settings: &ViewSettings, sim_settings: &SimSettings) -> Self {
+        let dt = sim_settings.tick_dt();
         let particle_radius = view_settings.particle_radius * view_settings.zoom_scale;
+        let color_mode = view_settings.shown_color_mode(sim_settings);
         Self {
             specie_n: sim_settings.specie_n as _,
             particle_n: sim_settings.particle_n as _,
@@ -632,18 +1570,299 @@ impl ShaderParams {
             // zoom_center: view_settings.zoom_center,
             zoom_center_x: view_settings.zoom_center.x,
             zoom_center_y: view_settings.zoom_center.y,
+            pm_grid_size: sim_settings.pm_grid_size,
+            bh_depth: sim_settings.bh_depth,
+            bh_theta: sim_settings.bh_theta,
+            min_particle_radius: view_settings.min_particle_pixels
+                / view_settings.texture_size as f32,
+            // one pixel, since the texture is [0, 1]
+            antialias_width: if view_settings.antialiasing {
+                1.0 / view_settings.texture_size as f32
+            } else {
+                0.0
+            },
+            color_mode: color_mode as _,
+            color_min: view_settings.color_ranges[color_mode as usize][0],
+            color_max: view_settings.color_ranges[color_mode as usize][1],
+            overlay_mode: view_settings.overlay_mode as _,
+            overlay_specie: view_settings.overlay_specie as _,
+            overlay_scale: view_settings.overlay_scale,
+            glyph: view_settings.glyph as _,
+            // in the same units as particle_radius
+            glyph_length: view_settings.glyph_length * view_settings.zoom_scale,
         }
     }
 }
 
-fn create_texture(device: &wgpu::Device, size: wgpu::Extent3d) -> wgpu::Texture {
+#[derive(Clone, Copy, bytemuck::NoUninit)]
+#[repr(C)]
+struct CameraParams {
+    view: camera::Mat4,
+    proj: camera::Mat4,
+}
+impl CameraParams {
+    fn new(view_settings: &ViewSettings) -> Self {
+        Self {
+            view: view_settings.camera.view_matrix(),
+            // the texture is always square
+            proj: view_settings.camera.proj_matrix(1.0),
+        }
+    }
+}
+
+/// copies `buffer` to the cpu, which needs `buffer` to have `wgpu::BufferUsages::COPY_SRC`
+/// blocks until the gpu is done, so don't do it every frame
+pub(crate) fn read_buffer<T: bytemuck::AnyBitPattern>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+) -> Vec<T> {
+    read_buffer_range(device, queue, buffer, 0..buffer.size())
+}
+
+/// read_buffer() but only the bytes in `range`, which has to be a multiple of 4
+pub(crate) fn read_buffer_range<T: bytemuck::AnyBitPattern>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+    range: std::ops::Range<u64>,
+) -> Vec<T> {
+    let size = range.end - range.start;
+    let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("staging_buffer"),
+        size,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut command_encoder = device.create_command_encoder(&Default::default());
+    command_encoder.copy_buffer_to_buffer(buffer, range.start, &staging_buffer, 0, size);
+    queue.submit([command_encoder.finish()]);
+
+    let slice = staging_buffer.slice(..);
+    slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+    device.poll(wgpu::Maintain::Wait);
+    let data = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+    staging_buffer.unmap();
+    data
+}
+
+/// copies `texture` to the cpu, which needs `texture` to have `wgpu::TextureUsages::COPY_SRC` and 4 bytes per pixel
+/// the rows are packed together, without the padding the copy needs
+/// `staging_buffer` is from create_texture_staging_buffer() with the same size, so it can be reused
+/// blocks until the gpu is done
+pub(crate) fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    staging_buffer: &wgpu::Buffer,
+) -> Vec<u8> {
+    let size = texture.size();
+    let row_bytes = 4 * size.width;
+    let padded_row_bytes = row_bytes.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+    let mut command_encoder = device.create_command_encoder(&Default::default());
+    command_encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: staging_buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_row_bytes),
+                rows_per_image: None,
+            },
+        },
+        size,
+    );
+    queue.submit([command_encoder.finish()]);
+
+    let slice = staging_buffer.slice(..);
+    slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+    device.poll(wgpu::Maintain::Wait);
+    let data = slice
+        .get_mapped_range()
+        .chunks(padded_row_bytes as _)
+        .flat_map(|row| &row[..row_bytes as _])
+        .copied()
+        .collect();
+    staging_buffer.unmap();
+    data
+}
+
+/// a buffer read_texture() can copy a 4 bytes per pixel texture of `size` to, with the rows padded like the copy needs
+fn create_texture_staging_buffer(
+    device: &wgpu::Device,
+    size: wgpu::Extent3d,
+) -> wgpu::Buffer {
+    let padded_row_bytes = (4 * size.width).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("texture_staging_buffer"),
+        size: (padded_row_bytes * size.height) as _,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    render_pipeline_layout: &wgpu::PipelineLayout,
+    shader_module: &wgpu::ShaderModule,
+    dimension: Dimension,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    // for the second pass in 3d, which only draws the fragments that aren't opaque and doesn't write depth
+    translucent: bool,
+) -> wgpu::RenderPipeline {
+    let (vec_format, vec_size) = match dimension {
+        Dimension::Two => (wgpu::VertexFormat::Float32x2, size_of::<[f32; 2]>()),
+        Dimension::Three => (wgpu::VertexFormat::Float32x4, size_of::<[f32; 4]>()),
+    };
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(if translucent {
+            "translucent_render_pipeline"
+        } else {
+            "render_pipeline"
+        }),
+        layout: Some(render_pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: shader_module,
+            entry_point: "main_vs",
+            compilation_options: Default::default(),
+            buffers: &[
+                // @location(1) particle_pos: vec2<f32> or vec4<f32>,
+                wgpu::VertexBufferLayout {
+                    array_stride: vec_size as _,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &[wgpu::VertexAttribute {
+                        format: vec_format,
+                        offset: 0,
+                        shader_location: 1,
+                    }],
+                },
+                // @location(2) particle_vel: vec2<f32> or vec4<f32>,
+                wgpu::VertexBufferLayout {
+                    array_stride: vec_size as _,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &[wgpu::VertexAttribute {
+                        format: vec_format,
+                        offset: 0,
+                        shader_location: 2,
+                    }],
+                },
+                // @location(3) particle_species: u32,
+                wgpu::VertexBufferLayout {
+                    array_stride: 4,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![3 => Uint32],
+                },
+                // @location(4) color_value: f32,
+                wgpu::VertexBufferLayout {
+                    array_stride: 4,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![4 => Float32],
+                },
+            ],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader_module,
+            entry_point: if translucent {
+                "main_fs_translucent"
+            } else {
+                "main_fs"
+            },
+            compilation_options: Default::default(),
+            // targets: &[Some(config.view_formats[0].into())],
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                // with msaa, alpha to coverage does the edges instead, which doesn't depend on the draw order
+                blend: (sample_count == 1).then_some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::all(),
+            })],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: (dimension == Dimension::Three).then_some(wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: !translucent,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: sample_count > 1,
+        },
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// a full screen triangle that multiplies what's already in the texture by the blend constant
+fn create_fade_pipeline(
+    device: &wgpu::Device,
+    shader_module: &wgpu::ShaderModule,
+    dimension: Dimension,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    // dst * constant - src, where fade_fs outputs a tiny src so faint trails don't get stuck
+    // when the multiplication rounds back up in the 8 bit texture
+    let fade = wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::Constant,
+        operation: wgpu::BlendOperation::ReverseSubtract,
+    };
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("fade_pipeline_layout"),
+        bind_group_layouts: &[],
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("fade_pipeline"),
+        layout: Some(&layout),
+        vertex: wgpu::VertexState {
+            module: shader_module,
+            entry_point: "fade_vs",
+            compilation_options: Default::default(),
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader_module,
+            entry_point: "fade_fs",
+            compilation_options: Default::default(),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState {
+                    color: fade,
+                    alpha: fade,
+                }),
+                write_mask: wgpu::ColorWrites::all(),
+            })],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        // the render pass has a depth texture in 3d, but the fade ignores it
+        depth_stencil: (dimension == Dimension::Three).then_some(wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Always,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            ..Default::default()
+        },
+        multiview: None,
+        cache: None,
+    })
+}
+
+pub(crate) fn create_texture(device: &wgpu::Device, size: wgpu::Extent3d) -> wgpu::Texture {
     device.create_texture(&wgpu::TextureDescriptor {
         label: Some("texture"),
         size,
         mip_level_count: 1,
         sample_count: 1,
         dimension: wgpu::TextureDimension::D2,
-        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        format: TEXTURE_FORMAT,
         usage: wgpu::TextureUsages::TEXTURE_BINDING
             | wgpu::TextureUsages::RENDER_ATTACHMENT
             | wgpu::TextureUsages::COPY_DST,
@@ -651,24 +1870,37 @@ fn create_texture(device: &wgpu::Device, size: wgpu::Extent3d) -> wgpu::Texture
     })
 }
 
-fn get_triangle(radius: f32) -> [f32; 6] {
-    // returns a small triangle that contains the circle centered at the origin with the given radius
-    // desmos.com/calculator/ksbrvgwqfp
-    // TODO: why do i need to scale it up?
-    // [
-    //     -3.0 * radius,
-    //     -radius,
-    //     radius,
-    //     -radius,
-    //     radius,
-    //     3.0 * radius,
-    // ]
-    [
-        -6.0 * radius,
-        -2.0 * radius,
-        2.0 * radius,
-        -2.0 * radius,
-        2.0 * radius,
-        6.0 * radius,
-    ]
+fn create_msaa_texture(
+    device: &wgpu::Device,
+    size: wgpu::Extent3d,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("msaa_texture"),
+        size,
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    })
+}
+
+fn create_depth_texture(
+    device: &wgpu::Device,
+    size: wgpu::Extent3d,
+    sample_count: u32,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("depth_texture"),
+        size,
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format: DEPTH_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    })
 }
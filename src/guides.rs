@@ -0,0 +1,206 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use eframe::{
+    egui::{self, Pos2, Rect, Vec2},
+    wgpu,
+};
+
+use crate::ViewSettings;
+
+// the same as in common.wgsl, where particles closer than BETA * local_radius push each other away
+pub(crate) const BETA: f32 = 0.3;
+
+/// the position in the sim of a point in `rect`, the inverse of main_vs in 2d
+pub(crate) fn screen_to_sim(rect: Rect, view_settings: &ViewSettings, point: Pos2) -> Vec2 {
+    let screen = Vec2::new(point.x - rect.left(), rect.bottom() - point.y) / rect.width();
+    let pos = screen / view_settings.zoom_scale + view_settings.zoom_center;
+    Vec2::new(pos.x.rem_euclid(1.0), pos.y.rem_euclid(1.0))
+}
+
+/// where `pos` is drawn in `rect`, the copy nearest the bottom left if the view is zoomed out
+fn sim_to_screen(rect: Rect, view_settings: &ViewSettings, pos: Vec2) -> Pos2 {
+    let translated = pos - view_settings.zoom_center;
+    let screen = Vec2::new(translated.x.rem_euclid(1.0), translated.y.rem_euclid(1.0))
+        * view_settings.zoom_scale;
+    rect.left_bottom() + Vec2::new(screen.x, -screen.y) * rect.width()
+}
+
+/// lines local_radius apart, starting from the walls, so each cell is about the reach of a particle
+pub(crate) fn paint_grid(
+    painter: &egui::Painter,
+    rect: Rect,
+    view_settings: &ViewSettings,
+    local_radius: f32,
+) {
+    // the sim is this many points across
+    let period = view_settings.zoom_scale * rect.width();
+    // denser than this is just a gray
+    if local_radius * period < 4.0 {
+        return;
+    }
+    let stroke = egui::Stroke::new(1.0, egui::Color32::from_white_alpha(40));
+    let line_n = (1.0 / local_radius).ceil() as usize;
+    // copies of the sim to fill the view when zoom_scale < 1
+    let copy_n = (1.0 / view_settings.zoom_scale).ceil() as usize;
+    for line_i in 0..line_n {
+        let origin = sim_to_screen(
+            rect,
+            view_settings,
+            Vec2::splat(line_i as f32 * local_radius),
+        );
+        for copy_i in 0..copy_n {
+            let x = origin.x + copy_i as f32 * period;
+            painter.vline(x, rect.y_range(), stroke);
+            let y = origin.y - copy_i as f32 * period;
+            painter.hline(rect.x_range(), y, stroke);
+        }
+    }
+}
+
+/// circles at local_radius and the BETA * local_radius repulsion zone around `pos`
+pub(crate) fn paint_radius(
+    painter: &egui::Painter,
+    rect: Rect,
+    view_settings: &ViewSettings,
+    pos: Vec2,
+    local_radius: f32,
+) {
+    let period = view_settings.zoom_scale * rect.width();
+    let radius = local_radius * period;
+    let center = sim_to_screen(rect, view_settings, pos);
+    // the circle wraps around the walls like the particles do
+    for x in -1..=1 {
+        for y in -1..=1 {
+            let center = center + Vec2::new(x as f32, y as f32) * period;
+            painter.circle(
+                center,
+                BETA * radius,
+                egui::Color32::from_rgba_unmultiplied(255, 64, 64, 24),
+                egui::Stroke::new(1.0, egui::Color32::from_rgb(255, 96, 96)),
+            );
+            painter.circle_stroke(center, radius, egui::Stroke::new(1.0, egui::Color32::WHITE));
+        }
+    }
+}
+
+/// the particle the radius circles follow, whose position is read back from the gpu without waiting for it,
+/// like GpuTimer and StatsReducer, so the circles are drawn where it was a frame or two ago
+pub(crate) struct SelectedParticle {
+    // the stable id
+    id: u32,
+    // the slot it was in at the last readback, which is a frame or two behind when the particles are sorted
+    slot: u32,
+    // where it was at the last readback that found it
+    pos: Vec2,
+    // slot_buffer[id], 4 bytes of padding, then the ids and positions of the WINDOW_N slots around self.slot,
+    // which it's still in after a sort unless it moved far
+    readback_buffer: wgpu::Buffer,
+    // whether copy() copied into readback_buffer this frame, so start_readback() should map it
+    copied: bool,
+    // true from mapping readback_buffer until it's unmapped, since it can't be copied to while mapped
+    readback_busy: bool,
+    readback_ready: Arc<AtomicBool>,
+}
+impl SelectedParticle {
+    const WINDOW_N: u64 = 128;
+
+    /// `slot` and `pos` are where the particle with stable id `id` is now
+    pub(crate) fn new(device: &wgpu::Device, id: u32, slot: u32, pos: Vec2) -> Self {
+        Self {
+            id,
+            slot,
+            pos,
+            readback_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("selected_particle_readback_buffer"),
+                size: 8 + Self::WINDOW_N * (4 + 8),
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            copied: false,
+            readback_busy: false,
+            readback_ready: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// call after the compute passes, with the 2d positions they wrote to
+    pub(crate) fn copy(
+        &mut self,
+        command_encoder: &mut wgpu::CommandEncoder,
+        id_buffer: &wgpu::Buffer,
+        slot_buffer: &wgpu::Buffer,
+        pos_buffer: &wgpu::Buffer,
+    ) {
+        self.copied = !self.readback_busy;
+        if !self.copied {
+            return;
+        }
+        let particle_n = slot_buffer.size() / 4;
+        let window_n = Self::WINDOW_N.min(particle_n);
+        let window_start = (self.slot as u64)
+            .saturating_sub(window_n / 2)
+            .min(particle_n - window_n);
+        command_encoder.copy_buffer_to_buffer(
+            slot_buffer,
+            4 * self.id as u64,
+            &self.readback_buffer,
+            0,
+            4,
+        );
+        command_encoder.copy_buffer_to_buffer(
+            id_buffer,
+            4 * window_start,
+            &self.readback_buffer,
+            8,
+            4 * window_n,
+        );
+        command_encoder.copy_buffer_to_buffer(
+            pos_buffer,
+            8 * window_start,
+            &self.readback_buffer,
+            8 + 4 * Self::WINDOW_N,
+            8 * window_n,
+        );
+    }
+
+    /// call after submitting the command buffer that copy() was recorded into
+    pub(crate) fn start_readback(&mut self) {
+        if !self.copied {
+            return;
+        }
+        self.copied = false;
+        self.readback_busy = true;
+        let readback_ready = self.readback_ready.clone();
+        self.readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                result.unwrap();
+                readback_ready.store(true, Ordering::Release);
+            });
+    }
+
+    /// where it was at the last readback that's finished
+    pub(crate) fn pos(&mut self, device: &wgpu::Device) -> Vec2 {
+        device.poll(wgpu::Maintain::Poll);
+        if self.readback_ready.swap(false, Ordering::Acquire) {
+            {
+                let mapped = self.readback_buffer.slice(..).get_mapped_range();
+                let words: &[u32] = bytemuck::cast_slice(&mapped);
+                let window_n = Self::WINDOW_N as usize;
+                let (ids, poses) = words[2..].split_at(window_n);
+                let poses: &[Vec2] = bytemuck::cast_slice(poses);
+                // with fewer than WINDOW_N particles the window has all of them,
+                // so it's found before the unused ids at the end, which are 0
+                if let Some(window_i) = ids.iter().position(|&id| id == self.id) {
+                    self.pos = poses[window_i];
+                }
+                self.slot = words[0];
+            }
+            self.readback_buffer.unmap();
+            self.readback_busy = false;
+        }
+        self.pos
+    }
+}
@@ -0,0 +1,44 @@
+use std::sync::Arc;
+
+use eframe::{egui_wgpu, wgpu};
+
+/// the same gpu state eframe makes for the app, but without a window, for the benchmarks and other batch jobs
+pub(crate) fn create_render_state() -> egui_wgpu::RenderState {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::util::backend_bits_from_env()
+            .unwrap_or(wgpu::Backends::PRIMARY | wgpu::Backends::GL),
+        ..Default::default()
+    });
+    let adapter = pollster::block_on(
+        instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::util::power_preference_from_env()
+                .unwrap_or(wgpu::PowerPreference::HighPerformance),
+            compatible_surface: None,
+            force_fallback_adapter: false,
+        }),
+    )
+    .expect("there should be a gpu adapter");
+    println!("using {:?}", adapter.get_info());
+
+    let (device, queue) = pollster::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            // there's no surface to be compatible with, so use everything the adapter has,
+            // since eframe's webgl2 limits for the gl backend don't have storage buffers
+            required_limits: adapter.limits(),
+            ..crate::device_descriptor(&adapter)
+        },
+        None,
+    ))
+    .expect("the gpu device should be created");
+    let renderer =
+        egui_wgpu::Renderer::new(&device, wgpu::TextureFormat::Rgba8UnormSrgb, None, 1, false);
+
+    egui_wgpu::RenderState {
+        adapter: Arc::new(adapter),
+        available_adapters: Arc::new([]),
+        device: Arc::new(device),
+        queue: Arc::new(queue),
+        target_format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        renderer: Arc::new(eframe::egui::mutex::RwLock::new(renderer)),
+    }
+}
@@ -1,41 +1,186 @@
 // mod boids_example_gfx;
 
+mod bench;
+mod bh;
+mod bloom;
+mod camera;
+mod cell_grid;
+mod census;
 mod color_drag_value;
+mod evolution;
+mod export;
 mod gfx;
+mod guides;
+mod headless;
 mod my_utils;
+mod overlay;
+mod pm;
+mod search;
+mod sort;
+mod stats;
+mod structure;
+mod sweep;
+mod timing;
+mod trajectory;
 
+use camera::OrbitCamera;
 use color_drag_value::ColorDragValue;
 use eframe::egui::{self, Vec2};
 use gfx::GfxData;
 use rand::prelude::*;
+use timing::PerfStats;
 
 fn main() -> eframe::Result {
     // std::env::set_var("RUST_BACKTRACE", "1");
     // env_logger::init();
 
-    let native_options = eframe::NativeOptions::default();
+    let native_options = eframe::NativeOptions {
+        wgpu_options: eframe::egui_wgpu::WgpuConfiguration {
+            device_descriptor: std::sync::Arc::new(device_descriptor),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let args = Args::parse();
+    if args.bench {
+        bench::run(&args);
+        return Ok(());
+    }
+    if args.search {
+        search::run(&args);
+        return Ok(());
+    }
 
     eframe::run_native(
         "particle life",
         native_options,
         // Box::new(|cc| Ok(Box::new(App::new(cc, 6, 100)))),
-        Box::new(|cc| Ok(Box::new(App::new(cc, 6, 5000)))),
+        Box::new(move |cc| Ok(Box::new(App::new(cc, &args, 6)))),
     )
 }
 
+/// eframe's default, but with timestamp queries when the gpu has them, for the performance overlay
+fn device_descriptor(adapter: &eframe::wgpu::Adapter) -> eframe::wgpu::DeviceDescriptor<'static> {
+    let device_descriptor =
+        (eframe::egui_wgpu::WgpuConfiguration::default().device_descriptor)(adapter);
+    eframe::wgpu::DeviceDescriptor {
+        required_features: device_descriptor.required_features
+            | (adapter.features() & eframe::wgpu::Features::TIMESTAMP_QUERY),
+        ..device_descriptor
+    }
+}
+
+/// the settings that can only be chosen at startup
+struct Args {
+    // run the benchmarks in bench.rs instead of opening a window
+    bench: bool,
+    // search for interesting attractions with search.rs instead of opening a window
+    search: bool,
+    // a directory from a search to open in the gallery
+    gallery: Option<String>,
+    dimension: Dimension,
+    particle_n: usize,
+    workgroup_size: u32,
+}
+impl Args {
+    fn parse() -> Self {
+        Self {
+            bench: std::env::args().any(|arg| arg == "--bench"),
+            search: std::env::args().any(|arg| arg == "--search"),
+            gallery: arg_value("--gallery"),
+            dimension: if std::env::args().any(|arg| arg == "--3d") {
+                Dimension::Three
+            } else {
+                Dimension::Two
+            },
+            particle_n: arg_value("--particle-n")
+                .map(|particle_n| particle_n.parse().expect("--particle-n should be a number"))
+                .unwrap_or(5000),
+            workgroup_size: arg_value("--workgroup-size")
+                .map(|size| {
+                    size.parse()
+                        .ok()
+                        .filter(|&size| size > 0)
+                        .expect("--workgroup-size should be a positive number")
+                })
+                .unwrap_or(64),
+        }
+    }
+}
+
+/// the argument after `name`, like `--particle-n 100000`
+fn arg_value(name: &str) -> Option<String> {
+    let mut args = std::env::args().skip_while(|arg| arg != name);
+    args.next()?;
+    Some(
+        args.next()
+            .unwrap_or_else(|| panic!("{name} should be followed by a value")),
+    )
+}
+
+/// chosen at startup because the gpu buffers and pipelines depend on it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+enum Dimension {
+    Two,
+    Three,
+}
+
 // TODO: not square simulation window
+#[derive(Clone)]
 struct ViewSettings {
     particle_radius: f32,
     texture_size: u32,
     zoom_scale: f32,
     zoom_center: Vec2,
     specie_colors: Vec<egui::Rgba>,
+    // only used in 3d
+    camera: OrbitCamera,
+    show_perf_overlay: bool,
+    // a window with plots of stats::Stats over time
+    show_stats: bool,
+    // smooth the edges of the particles in the fragment shader
+    antialiasing: bool,
+    // render with 4x msaa, and use alpha to coverage for the smooth edges
+    msaa: bool,
+    // particles smaller than this radius in texture pixels are drawn at this size and faded out
+    min_particle_pixels: f32,
+    glyph: Glyph,
+    // the tail of a glyph reaches back to where the particle was this many seconds ago, if it kept its velocity
+    glyph_length: f32,
+    // the half life in seconds of the trails behind the particles, 0.0 for no trails
+    trail_length: f32,
+    // render to an hdr texture and add a glow around the bright parts
+    bloom: bool,
+    // how bright a color channel has to be to glow
+    bloom_threshold: f32,
+    bloom_intensity: f32,
+    // the standard deviation of the glow as a fraction of the texture
+    bloom_radius: f32,
+    color_mode: ColorMode,
+    // the values at the ends of the colormap, for each ColorMode
+    color_ranges: [[f32; 2]; ColorMode::ALL.len()],
+    // drawn over the particles from the particle mesh grid, only in 2d
+    overlay_mode: OverlayMode,
+    // whose force OverlayMode::Field shows
+    overlay_specie: usize,
+    // the value at full brightness, density relative to the average for the density modes and force for the field
+    overlay_scale: f32,
+    overlay_opacity: f32,
+    // lines local_radius apart, only in 2d
+    show_grid: bool,
+    // circles at local_radius and the repulsion zone around the cursor or the clicked particle, only in 2d
+    show_radius: bool,
 }
 impl ViewSettings {
-    fn new(specie_n: usize, _particle_n: usize) -> Self {
+    fn new(dimension: Dimension, specie_n: usize, particle_n: usize) -> Self {
         const INITIAL_TEXTURE_SIZE: u32 = 100;
         Self {
-            particle_radius: 0.002, // TODO: this should vary with particle n
+            // TODO: this should vary with particle n
+            particle_radius: match dimension {
+                Dimension::Two => 0.002,
+                // the camera is further away than the 2d view
+                Dimension::Three => 0.006,
+            },
             // particle_radius: 0.05,
             texture_size: INITIAL_TEXTURE_SIZE,
             zoom_scale: 1.0,
@@ -62,6 +207,27 @@ impl ViewSettings {
             // specie_colors: (0..specie_n)
             //     .map(|specie_i| color_interpolation::get_color(specie_n, specie_i))
             //     .collect(),
+            camera: OrbitCamera::new(),
+            show_perf_overlay: true,
+            show_stats: false,
+            antialiasing: true,
+            msaa: false,
+            min_particle_pixels: 1.0,
+            glyph: Glyph::Circle,
+            glyph_length: 0.1,
+            trail_length: 0.0,
+            bloom: false,
+            bloom_threshold: 0.5,
+            bloom_intensity: 1.0,
+            bloom_radius: 0.005,
+            color_mode: ColorMode::Species,
+            color_ranges: ColorMode::ALL.map(|color_mode| color_mode.default_range(particle_n)),
+            overlay_mode: OverlayMode::Off,
+            overlay_specie: 0,
+            overlay_scale: 4.0,
+            overlay_opacity: 0.8,
+            show_grid: false,
+            show_radius: false,
         }
     }
 
@@ -70,9 +236,306 @@ impl ViewSettings {
     //     egui::Rgba::from_rgb(x.cos(), (x - 2.0).cos(), (x - 4.0).cos())
     //     egui::ecolor::Hsva::new(specie_i as f32 / specie_n as f32, 1.0, 0.9, 1.0).into()
     // }
+
+    /// color_mode, or Species if it isn't available with these settings
+    fn shown_color_mode(&self, sim_settings: &SimSettings) -> ColorMode {
+        if self.color_mode.is_available(sim_settings) {
+            self.color_mode
+        } else {
+            ColorMode::Species
+        }
+    }
+}
+
+/// for saving images of the sim, see export.rs
+struct ExportSettings {
+    // the width and height of screenshots, independent of view_settings.texture_size
+    screenshot_size: u32,
+    transparent_background: bool,
+    // used when transparent_background is off
+    background_color: egui::Color32,
+    recording_output: export::RecordingOutput,
+    recording_size: u32,
+    // the sim goes 1 / recording_fps seconds every frame while recording, however long the frame takes
+    recording_fps: f32,
+    // for RecordingOutput::Encoder, with {size}, {fps} and {name} replaced
+    encoder_command: String,
+    // gifs are small and short, for sharing in chat
+    gif_size: u32,
+    gif_fps: f32,
+    gif_seconds: f32,
+    trajectory_format: trajectory::TrajectoryFormat,
+    // ticks between samples of the trajectory
+    trajectory_interval: u64,
+}
+impl ExportSettings {
+    fn new() -> Self {
+        Self {
+            screenshot_size: 4096,
+            transparent_background: false,
+            background_color: egui::Color32::BLACK,
+            recording_output: export::RecordingOutput::PngSequence,
+            recording_size: 1024,
+            recording_fps: 60.0,
+            encoder_command: "ffmpeg -y -f rawvideo -pix_fmt rgba -s {size}x{size} -r {fps} -i - -pix_fmt yuv420p {name}.mp4".to_owned(),
+            gif_size: 256,
+            // gif frame delays are in hundredths of a second, so this is exact
+            gif_fps: 25.0,
+            gif_seconds: 4.0,
+            trajectory_format: trajectory::TrajectoryFormat::Csv,
+            trajectory_interval: 64,
+        }
+    }
+
+    fn background(&self) -> export::Background {
+        if self.transparent_background {
+            export::Background::Transparent
+        } else {
+            export::Background::Color(self.background_color)
+        }
+    }
+}
+
+/// for finding clusters, see census.rs
+struct CensusSettings {
+    // particles closer than this are in the same cluster, by default about where they start repelling each other
+    link_distance: f32,
+    // smaller clusters are counted as loose particles
+    min_cluster_size: usize,
+    // take a new census every frame while the census window is open
+    live: bool,
+}
+impl CensusSettings {
+    fn new() -> Self {
+        Self {
+            link_distance: 0.03,
+            min_cluster_size: 8,
+            live: false,
+        }
+    }
+}
+
+/// for the radial distribution functions and structure factors, see structure.rs
+struct StructureSettings {
+    // bins of g(r) between 0 and local_radius
+    bin_n: usize,
+    // S(k) goes up to |k| = 2π k_n
+    k_n: usize,
+    // add a snapshot every frame while the structure window is open
+    live: bool,
+}
+impl StructureSettings {
+    fn new() -> Self {
+        Self {
+            bin_n: 50,
+            k_n: 16,
+            live: false,
+        }
+    }
+}
+
+/// for the genetic algorithm, see evolution.rs
+struct EvolutionSettings {
+    // individuals per generation
+    population_n: usize,
+    // the fittest this many are picked to survive, before any are picked by hand
+    survivor_n: usize,
+    // each individual is run for this many ticks from random positions
+    tick_n: usize,
+    // fewer than the main sim, so the individuals are quicker to run
+    particle_n: usize,
+    fitness: evolution::Fitness,
+    // the most an attraction changes in a mutation
+    mutation_size: f32,
+    mutate_local_radius: bool,
+    mutate_friction: bool,
+    // frames of the trial run every frame of the app, fewer keeps the app more responsive
+    steps_per_frame: usize,
+    // breed the next generation as soon as one is scored
+    auto: bool,
+}
+impl EvolutionSettings {
+    fn new(particle_n: usize) -> Self {
+        Self {
+            population_n: 8,
+            survivor_n: 3,
+            tick_n: 2000,
+            particle_n: particle_n.min(2000),
+            fitness: evolution::Fitness::Total,
+            mutation_size: 0.15,
+            mutate_local_radius: false,
+            mutate_friction: false,
+            steps_per_frame: 4,
+            auto: false,
+        }
+    }
+}
+
+/// for the sweep grid, see sweep.rs
+struct SweepSettings {
+    // varies along the columns
+    x_param: sweep::SweepParam,
+    x_range: [f32; 2],
+    x_n: usize,
+    // varies along the rows, or None for a single row
+    y_param: Option<sweep::SweepParam>,
+    y_range: [f32; 2],
+    y_n: usize,
+    // particles in each sim of the grid
+    instance_particle_n: usize,
+    // in the units of one sim's box, bigger than the main sim's since the boxes are small
+    particle_radius: f32,
+    // the width and height in pixels of one sim's tile
+    tile_size: u32,
+}
+impl SweepSettings {
+    fn new(sim_settings: &SimSettings) -> Self {
+        let x_param = sweep::SweepParam::FrictionHalfLife;
+        let y_param = sweep::SweepParam::LocalRadius;
+        Self {
+            x_param,
+            x_range: x_param.default_range(sim_settings),
+            x_n: 6,
+            y_param: Some(y_param),
+            y_range: y_param.default_range(sim_settings),
+            y_n: 6,
+            instance_particle_n: 400,
+            particle_radius: 0.006,
+            tile_size: 128,
+        }
+    }
+}
+
+/// the shape each particle is drawn as, see main_fs in shader.wgsl
+/// the ones other than Circle point along the velocity and get longer with speed, only in 2d
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Glyph {
+    Circle,
+    /// a triangle head with a thin tail
+    Arrow,
+    /// a circle with a tail that narrows to a point
+    Teardrop,
+}
+impl Glyph {
+    const ALL: [Self; 3] = [Self::Circle, Self::Arrow, Self::Teardrop];
+}
+
+/// what the color of each particle shows
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ColorMode {
+    /// specie_colors
+    Species,
+    /// length of the velocity, with the turbo colormap
+    Speed,
+    /// direction of the velocity in degrees as a hue, the range repeats
+    /// in 3d it's the direction in the xy plane
+    Heading,
+    /// particles within local_radius, with the turbo colormap
+    NeighborCount,
+    /// length of the net force, with the turbo colormap
+    Force,
+    /// a hue for each cluster in the last census, and gray for particles in none
+    Cluster,
+}
+impl ColorMode {
+    const ALL: [Self; 6] = [
+        Self::Species,
+        Self::Speed,
+        Self::Heading,
+        Self::NeighborCount,
+        Self::Force,
+        Self::Cluster,
+    ];
+
+    fn default_range(self, particle_n: usize) -> [f32; 2] {
+        match self {
+            Self::Species | Self::Cluster => [0.0, 1.0],
+            Self::Speed => [0.0, 0.25],
+            Self::Heading => [-180.0, 180.0],
+            // a few times the count if the particles were spread out evenly with the default local_radius
+            Self::NeighborCount => [0.0, 0.1 * particle_n as f32],
+            Self::Force => [0.0, 4.0],
+        }
+    }
+
+    /// whether the value is calculated by a compute pass before rendering, instead of in the vertex shader
+    fn needs_color_values(self) -> bool {
+        matches!(self, Self::NeighborCount | Self::Force | Self::Cluster)
+    }
+
+    // NeighborCount and Force are counted pairwise every frame whatever the solver is, which would undo the speedup
+    // of the approximate solvers, so with those they're only available up to this many particles
+    const PAIRWISE_MAX_PARTICLE_N: usize = 20_000;
+
+    fn is_available(self, sim_settings: &SimSettings) -> bool {
+        !matches!(self, Self::NeighborCount | Self::Force)
+            || matches!(
+                sim_settings.solver,
+                Solver::Pairwise | Solver::PairwiseTiled
+            )
+            || sim_settings.particle_n <= Self::PAIRWISE_MAX_PARTICLE_N
+    }
+}
+
+/// what's drawn over the particles, see overlay.wgsl
+/// the grid is the same as Solver::ParticleMesh's, so its resolution is pm_grid_size
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum OverlayMode {
+    Off,
+    /// the density of each specie as a heatmap in their colors
+    DensityHeatmap,
+    /// contour lines of the density of each specie in their colors
+    DensityContours,
+    /// the force a particle of overlay_specie would feel at each point, with the direction as a hue
+    Field,
+}
+impl OverlayMode {
+    const ALL: [Self; 4] = [
+        Self::Off,
+        Self::DensityHeatmap,
+        Self::DensityContours,
+        Self::Field,
+    ];
+}
+
+/// how the force on each particle is calculated
+/// only pairwise is implemented in 3d
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+enum Solver {
+    /// exact, O(particle_n^2)
+    Pairwise,
+    /// the same as pairwise, but neighbors are loaded in tiles through workgroup memory
+    PairwiseTiled,
+    /// approximate, see pm.wgsl
+    ParticleMesh,
+    /// approximate, see bh.wgsl
+    BarnesHut,
+}
+impl Solver {
+    const ALL: [Self; 4] = [
+        Self::Pairwise,
+        Self::PairwiseTiled,
+        Self::ParticleMesh,
+        Self::BarnesHut,
+    ];
+}
+impl std::str::FromStr for Solver {
+    type Err = String;
+
+    /// the same as the Debug name, ignoring case
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|solver| format!("{solver:?}").eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("{s:?} isn't a solver, expected one of {:?}", Self::ALL))
+    }
 }
 
+// serialized into the header of trajectory exports
+#[derive(Clone, serde::Serialize)]
 struct SimSettings {
+    dimension: Dimension,
+    solver: Solver,
     substep_n: usize,
     specie_n: usize,
     particle_n: usize,
@@ -81,25 +544,52 @@ struct SimSettings {
     time_scale: f32,
     attractions: Vec<Vec<f32>>,
     dt: f32,
+    // cells per side, for Solver::ParticleMesh
+    pm_grid_size: u32,
+    // the quadtree has 4^bh_depth leaves, for Solver::BarnesHut
+    bh_depth: u32,
+    // opening angle, nodes with size < bh_theta * distance are approximated, for Solver::BarnesHut
+    bh_theta: f32,
+    // particles per workgroup in the compute shaders, fixed at startup because it's compiled into them
+    workgroup_size: u32,
+    // ticks between sorting the particles by position so neighbors are near each other in memory, 0 to never sort
+    // only in 2d
+    sort_interval: u32,
 }
 impl SimSettings {
-    fn new(specie_n: usize, particle_n: usize) -> Self {
+    fn new(dimension: Dimension, specie_n: usize, particle_n: usize) -> Self {
         let mut rng = rand::thread_rng();
         Self {
+            dimension,
+            solver: Solver::Pairwise,
             substep_n: 8,
             specie_n,
             particle_n,
             // TODO: vary with particle_n
             // TODO: if i use grid optimization, should be enforced that this is of the form 1/n for some n, it's probably incorrect near the right/bottom if it's not
-            local_radius: 0.1,
+            local_radius: match dimension {
+                Dimension::Two => 0.1,
+                // gives about as many neighbors as in 2d
+                Dimension::Three => 0.2,
+            },
             friction_half_life: 0.04,
             time_scale: 1.0,
             attractions: (0..specie_n)
                 .map(|_| (0..specie_n).map(|_| rng.gen_range(-1.0..=1.0)).collect())
                 .collect(),
             dt: 0.01,
+            pm_grid_size: 128,
+            bh_depth: 6,
+            bh_theta: 0.5,
+            workgroup_size: 64,
+            sort_interval: 16,
         }
     }
+
+    /// the sim time of one tick, which is capped so slow frames don't blow up the sim
+    fn tick_dt(&self) -> f32 {
+        (self.time_scale * self.dt / self.substep_n as f32).min(1.0 / 30.0)
+    }
 }
 
 // stuff that should live on the gpu in the future
@@ -108,14 +598,18 @@ impl SimSettings {
 // TODO: wgsl likes "normalized device coordinates" which are in [-1.0, 1.0] instead of [0, 1.0], but also it's cool how its ambiguous over whether it's y-down
 // stuff that gets sent to the gpu on initialization but never anytime else
 #[derive(Debug)]
-struct SimData {
-    poses: Vec<Vec2>,
-    vels: Vec<Vec2>,
+struct SimData<V = Vec2> {
+    poses: Vec<V>,
+    vels: Vec<V>,
     species: Vec<u32>,
 }
 impl SimData {
     fn new(specie_n: usize, particle_n: usize) -> Self {
-        let mut rng = rand::thread_rng();
+        Self::from_rng(&mut rand::thread_rng(), specie_n, particle_n)
+    }
+
+    /// with a seeded rng for runs that can be repeated
+    fn from_rng(rng: &mut impl Rng, specie_n: usize, particle_n: usize) -> Self {
         Self {
             poses: (0..particle_n)
                 .map(|_| Vec2 {
@@ -136,33 +630,235 @@ impl SimData {
     }
 }
 
+// a vec3 padded to a vec4, because array<vec3<f32>> has the same stride as array<vec4<f32>> in wgsl anyway
+type Vec3Padded = [f32; 4];
+impl SimData<Vec3Padded> {
+    fn new_3d(specie_n: usize, particle_n: usize) -> Self {
+        Self::from_rng_3d(&mut rand::thread_rng(), specie_n, particle_n)
+    }
+
+    fn from_rng_3d(rng: &mut impl Rng, specie_n: usize, particle_n: usize) -> Self {
+        Self {
+            poses: (0..particle_n)
+                .map(|_| {
+                    [
+                        rng.gen_range(0.0..1.0),
+                        rng.gen_range(0.0..1.0),
+                        rng.gen_range(0.0..1.0),
+                        0.0,
+                    ]
+                })
+                .collect(),
+            vels: (0..particle_n)
+                .map(|_| {
+                    [
+                        rng.gen_range(-0.1..=0.1),
+                        rng.gen_range(-0.1..=0.1),
+                        rng.gen_range(-0.1..=0.1),
+                        0.0,
+                    ]
+                })
+                .collect(),
+            species: (0..particle_n)
+                .map(|_| rng.gen_range(0..specie_n as _))
+                .collect(),
+        }
+    }
+}
+
 struct App {
     view_settings: ViewSettings,
     sim_settings: SimSettings,
     gfx_data: GfxData,
+    bh_error_report: Option<bh::ErrorReport>,
+    perf_stats: PerfStats,
+    stats_history: stats::StatsHistory,
+    export_settings: ExportSettings,
+    // where the last export went, or why it failed
+    export_status: Option<String>,
+    // Some while recording, which gets a frame after every render
+    recording: Option<export::Recording>,
+    // Some while writing a trajectory, which gets sampled after every render
+    trajectory: Option<trajectory::TrajectoryWriter>,
+    census_settings: CensusSettings,
+    // the last census, shown in a window until it's closed
+    census: Option<census::Census>,
+    structure_settings: StructureSettings,
+    // the snapshots averaged so far, shown in a window until it's closed
+    structure: Option<structure::Structure>,
+    // the directory in the gallery section's text box
+    gallery_dir: String,
+    // the presets from a search, shown in a window until it's closed
+    gallery: Option<search::Gallery>,
+    // why the last gallery couldn't be opened
+    gallery_error: Option<String>,
+    evolution_settings: EvolutionSettings,
+    // Some while evolving, shown in a window until it's closed
+    evolution: Option<evolution::Evolution>,
+    // why the last evolution couldn't be started
+    evolution_error: Option<String>,
+    sweep_settings: SweepSettings,
+    // Some while the sweep window is open
+    sweep: Option<sweep::Sweep>,
 }
 impl App {
-    fn new(cc: &eframe::CreationContext<'_>, specie_n: usize, particle_n: usize) -> Self {
-        let view_settings = ViewSettings::new(specie_n, particle_n);
-        let sim_settings = SimSettings::new(specie_n, particle_n);
-        let sim_data = SimData::new(specie_n, particle_n);
-        let gfx_data = GfxData::new(cc, &view_settings, &sim_settings, &sim_data);
-        Self {
+    fn new(cc: &eframe::CreationContext<'_>, args: &Args, specie_n: usize) -> Self {
+        let Args {
+            dimension,
+            particle_n,
+            workgroup_size,
+            ..
+        } = *args;
+        let view_settings = ViewSettings::new(dimension, specie_n, particle_n);
+        let sim_settings = SimSettings {
+            workgroup_size,
+            ..SimSettings::new(dimension, specie_n, particle_n)
+        };
+        let gfx_data = match dimension {
+            Dimension::Two => GfxData::new(
+                cc.wgpu_render_state.as_ref().unwrap(),
+                &view_settings,
+                &sim_settings,
+                &SimData::new(specie_n, particle_n),
+            ),
+            Dimension::Three => GfxData::new(
+                cc.wgpu_render_state.as_ref().unwrap(),
+                &view_settings,
+                &sim_settings,
+                &SimData::new_3d(specie_n, particle_n),
+            ),
+        };
+        let mut app = Self {
+            sweep_settings: SweepSettings::new(&sim_settings),
             view_settings,
             sim_settings,
             gfx_data,
+            bh_error_report: None,
+            perf_stats: PerfStats::new(),
+            stats_history: stats::StatsHistory::new(),
+            export_settings: ExportSettings::new(),
+            export_status: None,
+            recording: None,
+            trajectory: None,
+            census_settings: CensusSettings::new(),
+            census: None,
+            structure_settings: StructureSettings::new(),
+            structure: None,
+            gallery_dir: args.gallery.clone().unwrap_or_default(),
+            gallery: None,
+            gallery_error: None,
+            evolution_settings: EvolutionSettings::new(particle_n),
+            evolution: None,
+            evolution_error: None,
+            sweep: None,
+        };
+        if args.gallery.is_some() {
+            app.open_gallery(&cc.egui_ctx);
+        }
+        app
+    }
+}
+/// for export_status
+fn finish_trajectory(trajectory: trajectory::TrajectoryWriter) -> String {
+    let path = trajectory.path.clone();
+    let sample_n = trajectory.sample_n;
+    match trajectory.finish() {
+        Ok(()) => format!("saved {sample_n} samples to {}", path.display()),
+        Err(error) => format!("couldn't finish {}: {error}", path.display()),
+    }
+}
+
+impl App {
+    /// finds the clusters in a snapshot of the particles, and gives their labels to ColorMode::Cluster
+    fn take_census(&mut self) {
+        let census = census::Census::take(
+            &self.gfx_data.read_sim_data(),
+            self.sim_settings.specie_n,
+            match self.sim_settings.dimension {
+                Dimension::Two => 2,
+                Dimension::Three => 3,
+            },
+            self.census_settings.link_distance,
+            self.census_settings.min_cluster_size,
+            self.gfx_data.sim_time(),
+        );
+        self.gfx_data.set_cluster_labels(&census.labels);
+        self.census = Some(census);
+    }
+
+    /// adds a snapshot of the particles to self.structure, starting over if the settings changed
+    fn add_structure_snapshot(&mut self) {
+        let dimension = match self.sim_settings.dimension {
+            Dimension::Two => 2,
+            Dimension::Three => 3,
+        };
+        // particle_n could have gone up since k_n was picked
+        self.structure_settings.k_n = self
+            .structure_settings
+            .k_n
+            .min(structure::max_k_n(dimension, self.sim_settings.particle_n));
+        let StructureSettings { bin_n, k_n, .. } = self.structure_settings;
+        let local_radius = self.sim_settings.local_radius;
+        if self
+            .structure
+            .as_ref()
+            .is_none_or(|structure| !structure.matches(local_radius, bin_n, k_n))
+        {
+            self.structure = Some(structure::Structure::new(
+                self.sim_settings.specie_n,
+                dimension,
+                local_radius,
+                bin_n,
+                k_n,
+            ));
+        }
+        self.structure
+            .as_mut()
+            .unwrap()
+            .add_snapshot(&self.gfx_data.read_sim_data());
+    }
+
+    /// opens the search results in gallery_dir in the gallery window
+    fn open_gallery(&mut self, ctx: &egui::Context) {
+        match search::Gallery::open(ctx, self.gallery_dir.as_ref()) {
+            Ok(gallery) => {
+                self.gallery = Some(gallery);
+                self.gallery_error = None;
+            }
+            Err(error) => self.gallery_error = Some(error),
+        }
+    }
+
+    /// renders the sim again at export_settings.screenshot_size and writes it to a png in the working directory
+    /// returns what to show in export_status
+    fn save_screenshot(&mut self) -> String {
+        let size = self.export_settings.screenshot_size;
+        let rgba = export::apply_background(
+            &self
+                .gfx_data
+                .screenshot(&self.view_settings, &self.sim_settings, size),
+            self.export_settings.background(),
+        );
+        let path = export::timestamped_path("screenshot", "png");
+        match export::write_png(&path, size, &rgba) {
+            Ok(()) => format!("saved {}", path.display()),
+            Err(error) => format!("couldn't save {}: {error}", path.display()),
         }
     }
 }
 impl eframe::App for App {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         ctx.request_repaint();
         egui::CentralPanel::default()
             .frame(egui::Frame::none())
             .show(ctx, |ui| {
                 let dt = ctx.input(|input_state| input_state.stable_dt);
                 // println!("dt: {:?}", dt);
-                self.sim_settings.dt = dt;
+                self.sim_settings.dt = match self.recording {
+                    // so the recording is smooth even if rendering is slower than real time
+                    Some(ref recording) => 1.0 / recording.fps,
+                    None => dt,
+                };
 
                 let scale = ui.available_rect_before_wrap().size().min_elem();
                 let rect = egui::Rect::from_min_size(
@@ -247,15 +943,245 @@ impl eframe::App for App {
                 //     }
                 // }
 
+                // orbit camera
+                if self.sim_settings.dimension == Dimension::Three {
+                    let response = ui.interact(
+                        rect,
+                        egui::Id::new("orbit_camera"),
+                        egui::Sense::drag(),
+                    );
+                    let drag_delta = response.drag_delta();
+                    self.view_settings.camera.orbit(
+                        -drag_delta.x * std::f32::consts::TAU / scale,
+                        drag_delta.y * std::f32::consts::PI / scale,
+                    );
+                    if response.hovered() {
+                        let scroll_delta = ctx.input(|input| input.smooth_scroll_delta.y);
+                        self.view_settings.camera.distance =
+                            (self.view_settings.camera.distance * (-scroll_delta * 0.002).exp())
+                                .clamp(0.1, 10.0);
+                    }
+                }
+
+                // clicking a particle makes the radius circles follow it, and clicking nothing stops that
+                let guides_response = (self.sim_settings.dimension == Dimension::Two).then(|| {
+                    let response =
+                        ui.interact(rect, egui::Id::new("guides"), egui::Sense::click());
+                    if let Some(pointer_pos) = response.interact_pointer_pos() {
+                        if response.clicked() && self.view_settings.show_radius {
+                            self.gfx_data.select_particle(
+                                guides::screen_to_sim(rect, &self.view_settings, pointer_pos),
+                                self.sim_settings.local_radius,
+                            );
+                        }
+                    }
+                    response
+                });
+
                 // wgpu stuff
                 self.gfx_data
                     .render(&self.view_settings, &self.sim_settings);
+                if let Some(recording) = &mut self.recording {
+                    let frame = self.gfx_data.screenshot(
+                        &self.view_settings,
+                        &self.sim_settings,
+                        recording.render_size,
+                    );
+                    if let Err(error) = recording.add_frame(&frame) {
+                        self.export_status = Some(format!("recording stopped: {error}"));
+                        // the encoder might still be running, or it might be why this failed
+                        let _ = self.recording.take().unwrap().finish();
+                    } else if recording.is_done() {
+                        self.export_status = Some(
+                            self.recording
+                                .take()
+                                .unwrap()
+                                .finish()
+                                .unwrap_or_else(|error| {
+                                    format!("couldn't finish recording: {error}")
+                                }),
+                        );
+                    }
+                }
+
+                if let Some(trajectory) = &mut self.trajectory {
+                    let tick = self.gfx_data.tick_count();
+                    if trajectory.wants_sample(tick) {
+                        if let Err(error) = trajectory.sample(
+                            tick,
+                            self.gfx_data.sim_time(),
+                            &self.gfx_data.read_sim_data(),
+                        ) {
+                            self.export_status = Some(format!("trajectory stopped: {error}"));
+                            let _ = self.trajectory.take().unwrap().finish();
+                        }
+                    }
+                }
+
+                if self.census_settings.live && self.census.is_some() {
+                    self.take_census();
+                }
+                if self.structure_settings.live && self.structure.is_some() {
+                    self.add_structure_snapshot();
+                }
+                if let Some(evolution) = &mut self.evolution {
+                    evolution.update(ctx, &self.evolution_settings);
+                }
+                if let Some(sweep) = &mut self.sweep {
+                    sweep.step(&self.view_settings, &self.sim_settings);
+                }
 
                 egui::widgets::Image::from_texture(egui::load::SizedTexture::new(
                     self.gfx_data.texture_id,
                     Vec2::new(10.0, 10.0), // arbitrary size
                 ))
                 .paint_at(ui, rect);
+                if let Some(overlay_texture_id) = self.gfx_data.overlay_texture_id() {
+                    egui::widgets::Image::from_texture(egui::load::SizedTexture::new(
+                        overlay_texture_id,
+                        Vec2::new(10.0, 10.0), // arbitrary size
+                    ))
+                    .tint(egui::Color32::WHITE.gamma_multiply(self.view_settings.overlay_opacity))
+                    .paint_at(ui, rect);
+                }
+
+                if let Some(guides_response) = guides_response {
+                    let painter = ui.painter_at(rect);
+                    if self.view_settings.show_grid {
+                        guides::paint_grid(
+                            &painter,
+                            rect,
+                            &self.view_settings,
+                            self.sim_settings.local_radius,
+                        );
+                    }
+                    if self.view_settings.show_radius {
+                        let pos = self.gfx_data.selected_particle_pos().or_else(|| {
+                            guides_response.hover_pos().map(|hover_pos| {
+                                guides::screen_to_sim(rect, &self.view_settings, hover_pos)
+                            })
+                        });
+                        if let Some(pos) = pos {
+                            guides::paint_radius(
+                                &painter,
+                                rect,
+                                &self.view_settings,
+                                pos,
+                                self.sim_settings.local_radius,
+                            );
+                        }
+                    }
+                }
+
+                // performance overlay
+                let frame_ms = ctx.input(|input_state| input_state.unstable_dt) * 1000.0;
+                self.perf_stats
+                    .update(frame_ms, self.gfx_data.take_gpu_times());
+                if self.view_settings.show_perf_overlay {
+                    let galley = ui.painter().layout_no_wrap(
+                        self.perf_stats
+                            .text(self.sim_settings.substep_n, self.sim_settings.particle_n),
+                        egui::FontId::monospace(12.0),
+                        egui::Color32::WHITE,
+                    );
+                    let overlay_rect = egui::Rect::from_min_size(
+                        rect.right_top() + Vec2::new(-galley.size().x - 10.0, 0.0),
+                        galley.size(),
+                    )
+                    .translate(Vec2::new(-10.0, 10.0))
+                    .expand(4.0);
+                    ui.painter().rect_filled(
+                        overlay_rect,
+                        4.0,
+                        egui::Color32::from_black_alpha(160),
+                    );
+                    ui.painter().galley(
+                        overlay_rect.shrink(4.0).min,
+                        galley,
+                        egui::Color32::WHITE,
+                    );
+                }
+
+                // statistics panel
+                if let Some(stats) = self.gfx_data.take_stats(self.sim_settings.particle_n) {
+                    self.stats_history.push(stats);
+                }
+                egui::Window::new("stats")
+                    .open(&mut self.view_settings.show_stats)
+                    .default_pos(rect.right_top() + Vec2::new(-360.0, 160.0))
+                    .default_width(320.0)
+                    .show(ctx, |ui| {
+                        self.stats_history.ui(
+                            ui,
+                            &self.view_settings.specie_colors,
+                            match self.sim_settings.dimension {
+                                Dimension::Two => 2,
+                                Dimension::Three => 3,
+                            },
+                        );
+                    });
+
+                let mut census_open = self.census.is_some();
+                if let Some(census) = &self.census {
+                    egui::Window::new("census")
+                        .open(&mut census_open)
+                        .default_pos(rect.right_top() + Vec2::new(-460.0, 160.0))
+                        .show(ctx, |ui| {
+                            census.ui(ui, &self.view_settings.specie_colors);
+                        });
+                }
+                if !census_open {
+                    self.census = None;
+                }
+                let mut structure_open = self.structure.is_some();
+                if let Some(structure) = &mut self.structure {
+                    egui::Window::new("structure")
+                        .open(&mut structure_open)
+                        .default_pos(rect.right_top() + Vec2::new(-460.0, 200.0))
+                        .default_width(320.0)
+                        .show(ctx, |ui| {
+                            structure.ui(ui, &self.view_settings.specie_colors, &self.sim_settings);
+                        });
+                }
+                if !structure_open {
+                    self.structure = None;
+                }
+                let mut gallery_open = self.gallery.is_some();
+                if let Some(gallery) = &mut self.gallery {
+                    egui::Window::new("gallery")
+                        .open(&mut gallery_open)
+                        .default_pos(rect.right_top() + Vec2::new(-460.0, 240.0))
+                        .show(ctx, |ui| {
+                            gallery.ui(ui, &mut self.sim_settings);
+                        });
+                }
+                if !gallery_open {
+                    self.gallery = None;
+                }
+                let mut evolution_open = self.evolution.is_some();
+                if let Some(evolution) = &mut self.evolution {
+                    egui::Window::new("evolution")
+                        .open(&mut evolution_open)
+                        .default_pos(rect.right_top() + Vec2::new(-620.0, 160.0))
+                        .show(ctx, |ui| {
+                            evolution.ui(ui, &self.evolution_settings, &mut self.sim_settings);
+                        });
+                }
+                if !evolution_open {
+                    self.evolution = None;
+                }
+                let mut sweep_open = self.sweep.is_some();
+                if let Some(sweep) = &self.sweep {
+                    egui::Window::new("sweep")
+                        .open(&mut sweep_open)
+                        .default_pos(rect.left_top() + Vec2::new(20.0, 20.0))
+                        .show(ctx, |ui| {
+                            sweep.ui(ui);
+                        });
+                }
+                if !sweep_open {
+                    self.sweep = None;
+                }
 
                 // settings ui
                 // TODO: make the window thing go on the right
@@ -267,6 +1193,11 @@ impl eframe::App for App {
                     .show(ui, |ui| {
                         egui::CollapsingHeader::new("settings").show(ui, |ui| {
                             ui.collapsing("view_settings", |ui| {
+                                ui.checkbox(
+                                    &mut self.view_settings.show_perf_overlay,
+                                    "show_perf_overlay",
+                                );
+                                ui.checkbox(&mut self.view_settings.show_stats, "show_stats");
                                 ui.horizontal(|ui| {
                                     ui.add(
                                         egui::Slider::new(
@@ -277,36 +1208,252 @@ impl eframe::App for App {
                                         .text("particle_radius"), // .logarithmic(true),
                                     );
                                 });
+                                if self.sim_settings.dimension == Dimension::Two {
+                                    ui.horizontal(|ui| {
+                                        egui::ComboBox::from_label("glyph")
+                                            .selected_text(format!(
+                                                "{:?}",
+                                                self.view_settings.glyph
+                                            ))
+                                            .show_ui(ui, |ui| {
+                                                for glyph in Glyph::ALL {
+                                                    ui.selectable_value(
+                                                        &mut self.view_settings.glyph,
+                                                        glyph,
+                                                        format!("{glyph:?}"),
+                                                    );
+                                                }
+                                            });
+                                    });
+                                    if self.view_settings.glyph != Glyph::Circle {
+                                        ui.horizontal(|ui| {
+                                            ui.add(
+                                                egui::Slider::new(
+                                                    &mut self.view_settings.glyph_length,
+                                                    0.0..=0.5,
+                                                )
+                                                .clamping(egui::SliderClamping::Never)
+                                                .text("glyph_length"),
+                                            );
+                                        });
+                                    }
+                                }
+                                // the orbit camera does the zooming in 3d
+                                if self.sim_settings.dimension == Dimension::Two {
+                                    ui.horizontal(|ui| {
+                                        ui.add(
+                                            egui::Slider::new(
+                                                &mut self.view_settings.zoom_scale,
+                                                1.0..=10.0,
+                                            )
+                                            .clamping(egui::SliderClamping::Never)
+                                            .text("zoom_scale"),
+                                        );
+                                    });
+                                    ui.horizontal(|ui| {
+                                        ui.add(
+                                            egui::Slider::new(
+                                                &mut self.view_settings.zoom_center.x,
+                                                0.0..=1.0,
+                                            )
+                                            .clamping(egui::SliderClamping::Never)
+                                            .text("zoom_center.x"),
+                                        );
+                                    });
+                                    ui.horizontal(|ui| {
+                                        ui.add(
+                                            egui::Slider::new(
+                                                &mut self.view_settings.zoom_center.y,
+                                                0.0..=1.0,
+                                            )
+                                            .clamping(egui::SliderClamping::Never)
+                                            .text("zoom_center.y"),
+                                        );
+                                    });
+                                }
                                 ui.horizontal(|ui| {
-                                    ui.add(
-                                        egui::Slider::new(
-                                            &mut self.view_settings.zoom_scale,
-                                            1.0..=10.0,
-                                        )
-                                        .clamping(egui::SliderClamping::Never)
-                                        .text("zoom_scale"),
+                                    ui.checkbox(
+                                        &mut self.view_settings.antialiasing,
+                                        "antialiasing",
                                     );
+                                    ui.checkbox(&mut self.view_settings.msaa, "msaa");
                                 });
                                 ui.horizontal(|ui| {
                                     ui.add(
                                         egui::Slider::new(
-                                            &mut self.view_settings.zoom_center.x,
-                                            0.0..=1.0,
+                                            &mut self.view_settings.min_particle_pixels,
+                                            0.0..=4.0,
                                         )
-                                        .clamping(egui::SliderClamping::Never)
-                                        .text("zoom_center.x"),
+                                        .text("min_particle_pixels"),
                                     );
                                 });
                                 ui.horizontal(|ui| {
                                     ui.add(
                                         egui::Slider::new(
-                                            &mut self.view_settings.zoom_center.y,
-                                            0.0..=1.0,
+                                            &mut self.view_settings.trail_length,
+                                            0.0..=2.0,
                                         )
                                         .clamping(egui::SliderClamping::Never)
-                                        .text("zoom_center.y"),
+                                        .text("trail_length"),
                                     );
                                 });
+                                ui.horizontal(|ui| {
+                                    egui::ComboBox::from_label("color_mode")
+                                        .selected_text(format!(
+                                            "{:?}",
+                                            self.view_settings.color_mode
+                                        ))
+                                        .show_ui(ui, |ui| {
+                                            for color_mode in ColorMode::ALL {
+                                                ui.add_enabled_ui(
+                                                    color_mode.is_available(&self.sim_settings),
+                                                    |ui| {
+                                                        ui.selectable_value(
+                                                            &mut self.view_settings.color_mode,
+                                                            color_mode,
+                                                            format!("{color_mode:?}"),
+                                                        );
+                                                    },
+                                                );
+                                            }
+                                        });
+                                });
+                                let color_mode = self.view_settings.color_mode;
+                                if !color_mode.is_available(&self.sim_settings) {
+                                    ui.label(format!(
+                                        "{color_mode:?} is counted pairwise, so it's shown as Species above {} particles with this solver",
+                                        ColorMode::PAIRWISE_MAX_PARTICLE_N
+                                    ));
+                                }
+                                if color_mode == ColorMode::Cluster && self.census.is_none() {
+                                    ui.label("take a census to find the clusters");
+                                }
+                                if !matches!(color_mode, ColorMode::Species | ColorMode::Cluster) {
+                                    let default_range =
+                                        color_mode.default_range(self.sim_settings.particle_n);
+                                    let speed = 0.005 * (default_range[1] - default_range[0]);
+                                    let range =
+                                        &mut self.view_settings.color_ranges[color_mode as usize];
+                                    ui.horizontal(|ui| {
+                                        ui.add(egui::DragValue::new(&mut range[0]).speed(speed));
+                                        ui.add(egui::DragValue::new(&mut range[1]).speed(speed));
+                                        ui.label("color_range");
+                                        if ui.button("reset").clicked() {
+                                            *range = default_range;
+                                        }
+                                    });
+                                }
+                                if self.sim_settings.dimension == Dimension::Two {
+                                    ui.horizontal(|ui| {
+                                        ui.checkbox(&mut self.view_settings.show_grid, "show_grid");
+                                        ui.checkbox(
+                                            &mut self.view_settings.show_radius,
+                                            "show_radius",
+                                        )
+                                        .on_hover_text(
+                                            "click a particle to follow it, and click nothing to stop",
+                                        );
+                                    });
+                                    ui.horizontal(|ui| {
+                                        egui::ComboBox::from_label("overlay_mode")
+                                            .selected_text(format!(
+                                                "{:?}",
+                                                self.view_settings.overlay_mode
+                                            ))
+                                            .show_ui(ui, |ui| {
+                                                for overlay_mode in OverlayMode::ALL {
+                                                    ui.selectable_value(
+                                                        &mut self.view_settings.overlay_mode,
+                                                        overlay_mode,
+                                                        format!("{overlay_mode:?}"),
+                                                    );
+                                                }
+                                            });
+                                    });
+                                }
+                                if self.view_settings.overlay_mode == OverlayMode::Field {
+                                    ui.horizontal(|ui| {
+                                        ui.add(
+                                            egui::Slider::new(
+                                                &mut self.view_settings.overlay_specie,
+                                                0..=self.sim_settings.specie_n - 1,
+                                            )
+                                            .text("overlay_specie"),
+                                        );
+                                    });
+                                }
+                                if self.view_settings.overlay_mode != OverlayMode::Off {
+                                    ui.horizontal(|ui| {
+                                        ui.add(
+                                            egui::Slider::new(
+                                                &mut self.view_settings.overlay_scale,
+                                                0.1..=16.0,
+                                            )
+                                            .text("overlay_scale")
+                                            .logarithmic(true),
+                                        );
+                                    });
+                                    ui.horizontal(|ui| {
+                                        ui.add(
+                                            egui::Slider::new(
+                                                &mut self.view_settings.overlay_opacity,
+                                                0.0..=1.0,
+                                            )
+                                            .text("overlay_opacity"),
+                                        );
+                                    });
+                                }
+                                ui.checkbox(&mut self.view_settings.bloom, "bloom");
+                                if self.view_settings.bloom {
+                                    ui.horizontal(|ui| {
+                                        ui.add(
+                                            egui::Slider::new(
+                                                &mut self.view_settings.bloom_threshold,
+                                                0.0..=1.0,
+                                            )
+                                            .text("bloom_threshold"),
+                                        );
+                                    });
+                                    ui.horizontal(|ui| {
+                                        ui.add(
+                                            egui::Slider::new(
+                                                &mut self.view_settings.bloom_intensity,
+                                                0.0..=4.0,
+                                            )
+                                            .text("bloom_intensity"),
+                                        );
+                                    });
+                                    ui.horizontal(|ui| {
+                                        ui.add(
+                                            egui::Slider::new(
+                                                &mut self.view_settings.bloom_radius,
+                                                0.0..=0.02,
+                                            )
+                                            .text("bloom_radius"),
+                                        );
+                                    });
+                                }
+                                if self.sim_settings.dimension == Dimension::Three {
+                                    ui.horizontal(|ui| {
+                                        ui.add(
+                                            egui::Slider::new(
+                                                &mut self.view_settings.camera.distance,
+                                                0.1..=10.0,
+                                            )
+                                            .text("camera.distance")
+                                            .logarithmic(true),
+                                        );
+                                    });
+                                    ui.horizontal(|ui| {
+                                        ui.add(
+                                            egui::Slider::new(
+                                                &mut self.view_settings.camera.fov_y,
+                                                0.1..=2.5,
+                                            )
+                                            .text("camera.fov_y"),
+                                        );
+                                    });
+                                }
                                 ui.horizontal(|ui| {
                                     for color in self.view_settings.specie_colors.iter_mut() {
                                         let mut c = [color.r(), color.g(), color.b()];
@@ -316,12 +1463,102 @@ impl eframe::App for App {
                                 })
                             });
                             ui.collapsing("sim_settings", |ui| {
+                                if self.sim_settings.dimension == Dimension::Two {
+                                    ui.horizontal(|ui| {
+                                        egui::ComboBox::from_label("solver")
+                                            .selected_text(format!(
+                                                "{:?}",
+                                                self.sim_settings.solver
+                                            ))
+                                            .show_ui(ui, |ui| {
+                                                for solver in Solver::ALL {
+                                                    ui.selectable_value(
+                                                        &mut self.sim_settings.solver,
+                                                        solver,
+                                                        format!("{solver:?}"),
+                                                    );
+                                                }
+                                            });
+                                    });
+                                }
+                                if self.sim_settings.dimension == Dimension::Two {
+                                    ui.horizontal(|ui| {
+                                        ui.add(
+                                            egui::Slider::new(
+                                                &mut self.sim_settings.sort_interval,
+                                                0..=256,
+                                            )
+                                            .text("sort_interval")
+                                            .logarithmic(true),
+                                        );
+                                    });
+                                }
+                                ui.label(format!(
+                                    "workgroup_size: {} (set with --workgroup-size)",
+                                    self.sim_settings.workgroup_size
+                                ));
+                                // the overlay uses the particle mesh grid too
+                                if self.sim_settings.solver == Solver::ParticleMesh
+                                    || self.view_settings.overlay_mode != OverlayMode::Off
+                                {
+                                    ui.horizontal(|ui| {
+                                        ui.add(
+                                            egui::Slider::new(
+                                                &mut self.sim_settings.pm_grid_size,
+                                                16..=512,
+                                            )
+                                            .text("pm_grid_size")
+                                            .logarithmic(true),
+                                        );
+                                    });
+                                }
+                                if self.sim_settings.solver == Solver::BarnesHut {
+                                    ui.horizontal(|ui| {
+                                        ui.add(
+                                            egui::Slider::new(
+                                                &mut self.sim_settings.bh_depth,
+                                                1..=8,
+                                            )
+                                            .text("bh_depth"),
+                                        );
+                                    });
+                                    ui.horizontal(|ui| {
+                                        ui.add(
+                                            egui::Slider::new(
+                                                &mut self.sim_settings.bh_theta,
+                                                0.0..=2.0,
+                                            )
+                                            .text("bh_theta"),
+                                        );
+                                    });
+                                    ui.horizontal(|ui| {
+                                        if ui.button("measure error").clicked() {
+                                            self.bh_error_report =
+                                                Some(self.gfx_data.measure_bh_error(
+                                                    &self.view_settings,
+                                                    &self.sim_settings,
+                                                ));
+                                        }
+                                        if let Some(report) = self.bh_error_report {
+                                            ui.label(format!(
+                                                "relative rms error: {:.3}, max error: {:.3}, mean exact force: {:.3} ({} particles)",
+                                                report.relative_rms_error,
+                                                report.max_error,
+                                                report.mean_exact_force,
+                                                report.particle_n,
+                                            ));
+                                        }
+                                    });
+                                }
                                 ui.horizontal(|ui| {
-                                    ui.add(
+                                    // the trajectory's tick_interval is a multiple of it
+                                    ui.add_enabled(
+                                        self.trajectory.is_none(),
                                         egui::Slider::new(&mut self.sim_settings.substep_n, 1..=16)
                                             .clamping(egui::SliderClamping::Never)
                                             .text("substep_n"),
-                                    );
+                                    )
+                                    .on_disabled_hover_text("can't change while writing a trajectory");
                                 });
                                 ui.horizontal(|ui| {
                                     ui.add(
@@ -403,6 +1640,485 @@ impl eframe::App for App {
                                     }
                                 });
                             });
+                            ui.collapsing("census", |ui| {
+                                ui.horizontal(|ui| {
+                                    ui.add(
+                                        egui::Slider::new(
+                                            &mut self.census_settings.link_distance,
+                                            0.0..=self.sim_settings.local_radius,
+                                        )
+                                        .clamping(egui::SliderClamping::Never)
+                                        .text("link_distance"),
+                                    );
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.add(
+                                        egui::DragValue::new(
+                                            &mut self.census_settings.min_cluster_size,
+                                        )
+                                        .range(1..=self.sim_settings.particle_n),
+                                    );
+                                    ui.label("min_cluster_size");
+                                });
+                                ui.checkbox(&mut self.census_settings.live, "live")
+                                    .on_hover_text("take a new census every frame while the window is open, which reads back every particle");
+                                if ui.button("take census").clicked() {
+                                    self.take_census();
+                                }
+                            });
+                            ui.collapsing("structure", |ui| {
+                                ui.horizontal(|ui| {
+                                    ui.add(
+                                        egui::DragValue::new(&mut self.structure_settings.bin_n)
+                                            .range(1..=1000),
+                                    );
+                                    ui.label("bin_n");
+                                    let dimension = match self.sim_settings.dimension {
+                                        Dimension::Two => 2,
+                                        Dimension::Three => 3,
+                                    };
+                                    let max_k_n =
+                                        structure::max_k_n(dimension, self.sim_settings.particle_n);
+                                    ui.add(
+                                        egui::DragValue::new(&mut self.structure_settings.k_n)
+                                            .range(1..=max_k_n),
+                                    )
+                                    .on_hover_text(format!("at most {max_k_n} at this particle_n, since each snapshot costs particle_n times k_n^{dimension}"));
+                                    ui.label("k_n");
+                                });
+                                ui.checkbox(&mut self.structure_settings.live, "live")
+                                    .on_hover_text("add a snapshot every frame while the window is open, which reads back every particle");
+                                if ui
+                                    .button("add snapshot")
+                                    .on_hover_text("starts over if bin_n, k_n or local_radius changed")
+                                    .clicked()
+                                {
+                                    self.add_structure_snapshot();
+                                }
+                            });
+                            ui.collapsing("gallery", |ui| {
+                                ui.horizontal(|ui| {
+                                    ui.text_edit_singleline(&mut self.gallery_dir)
+                                        .on_hover_text("a directory made by --search");
+                                    if ui.button("open").clicked() {
+                                        self.open_gallery(ui.ctx());
+                                    }
+                                });
+                                if let Some(gallery_error) = &self.gallery_error {
+                                    ui.label(gallery_error);
+                                }
+                            });
+                            ui.collapsing("evolution", |ui| {
+                                let settings = &mut self.evolution_settings;
+                                ui.horizontal(|ui| {
+                                    ui.add(
+                                        egui::DragValue::new(&mut settings.population_n)
+                                            .range(2..=64),
+                                    );
+                                    ui.label("population_n");
+                                    ui.add(
+                                        egui::DragValue::new(&mut settings.survivor_n)
+                                            .range(1..=settings.population_n),
+                                    );
+                                    ui.label("survivor_n");
+                                });
+                                ui.add_enabled_ui(self.evolution.is_none(), |ui| {
+                                    ui.horizontal(|ui| {
+                                        ui.add(
+                                            egui::DragValue::new(&mut settings.particle_n)
+                                                .range(1..=self.sim_settings.particle_n),
+                                        );
+                                        ui.label("particle_n");
+                                    });
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.add(
+                                        egui::DragValue::new(&mut settings.tick_n)
+                                            .range(64..=100_000),
+                                    );
+                                    ui.label("tick_n");
+                                    ui.add(
+                                        egui::DragValue::new(&mut settings.steps_per_frame)
+                                            .range(1..=64),
+                                    );
+                                    ui.label("steps_per_frame");
+                                });
+                                ui.horizontal(|ui| {
+                                    egui::ComboBox::from_label("fitness")
+                                        .selected_text(format!("{:?}", settings.fitness))
+                                        .show_ui(ui, |ui| {
+                                            for fitness in evolution::Fitness::ALL {
+                                                ui.selectable_value(
+                                                    &mut settings.fitness,
+                                                    fitness,
+                                                    format!("{fitness:?}"),
+                                                );
+                                            }
+                                        });
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.add(
+                                        egui::Slider::new(&mut settings.mutation_size, 0.0..=1.0)
+                                            .text("mutation_size"),
+                                    );
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.checkbox(
+                                        &mut settings.mutate_local_radius,
+                                        "mutate_local_radius",
+                                    );
+                                    ui.checkbox(&mut settings.mutate_friction, "mutate_friction");
+                                });
+                                ui.checkbox(&mut settings.auto, "auto")
+                                    .on_hover_text("breed the fittest as soon as a generation is scored, without waiting for you to pick");
+                                if ui
+                                    .button("start evolution")
+                                    .on_hover_text("starts from the current attractions, and mutations of them")
+                                    .clicked()
+                                {
+                                    match evolution::Evolution::start(
+                                        frame.wgpu_render_state().unwrap(),
+                                        &self.view_settings,
+                                        &self.sim_settings,
+                                        settings,
+                                    ) {
+                                        Ok(evolution) => {
+                                            self.evolution = Some(evolution);
+                                            self.evolution_error = None;
+                                        }
+                                        Err(error) => {
+                                            self.evolution_error =
+                                                Some(format!("couldn't start evolution: {error}"));
+                                        }
+                                    }
+                                }
+                                if let Some(evolution_error) = &self.evolution_error {
+                                    ui.label(evolution_error);
+                                }
+                            });
+                            if self.sim_settings.dimension == Dimension::Two {
+                                ui.collapsing("sweep", |ui| {
+                                    let settings = &mut self.sweep_settings;
+                                    let param_combo_box =
+                                        |ui: &mut egui::Ui,
+                                         label: &str,
+                                         param: &mut Option<sweep::SweepParam>,
+                                         allow_none: bool,
+                                         other_param: Option<sweep::SweepParam>| {
+                                            egui::ComboBox::from_label(label)
+                                                .selected_text(match param {
+                                                    Some(param) => format!("{param:?}"),
+                                                    None => "None".to_owned(),
+                                                })
+                                                .show_ui(ui, |ui| {
+                                                    let mut changed = false;
+                                                    if allow_none {
+                                                        changed |= ui
+                                                            .selectable_value(param, None, "None")
+                                                            .changed();
+                                                    }
+                                                    for option in sweep::SweepParam::ALL {
+                                                        // the same param on both axes would just be y
+                                                        changed |= ui
+                                                            .add_enabled_ui(
+                                                                Some(option) != other_param,
+                                                                |ui| {
+                                                                    ui.selectable_value(
+                                                                        param,
+                                                                        Some(option),
+                                                                        format!("{option:?}"),
+                                                                    )
+                                                                },
+                                                            )
+                                                            .inner
+                                                            .changed();
+                                                    }
+                                                    changed
+                                                })
+                                                .inner
+                                                .unwrap_or(false)
+                                        };
+                                    let mut x_param = Some(settings.x_param);
+                                    if param_combo_box(
+                                        ui,
+                                        "x_param",
+                                        &mut x_param,
+                                        false,
+                                        settings.y_param,
+                                    ) {
+                                        settings.x_param = x_param.unwrap();
+                                        settings.x_range =
+                                            settings.x_param.default_range(&self.sim_settings);
+                                    }
+                                    ui.horizontal(|ui| {
+                                        for value in &mut settings.x_range {
+                                            ui.add(
+                                                egui::DragValue::new(value)
+                                                    .speed(0.001)
+                                                    .range(settings.x_param.valid_range()),
+                                            );
+                                        }
+                                        ui.label("x_range");
+                                        ui.add(egui::DragValue::new(&mut settings.x_n).range(1..=16));
+                                        ui.label("x_n");
+                                    });
+                                    if param_combo_box(
+                                        ui,
+                                        "y_param",
+                                        &mut settings.y_param,
+                                        true,
+                                        Some(settings.x_param),
+                                    ) {
+                                        if let Some(y_param) = settings.y_param {
+                                            settings.y_range =
+                                                y_param.default_range(&self.sim_settings);
+                                        }
+                                    }
+                                    ui.add_enabled_ui(settings.y_param.is_some(), |ui| {
+                                        ui.horizontal(|ui| {
+                                            for value in &mut settings.y_range {
+                                                let mut drag_value =
+                                                    egui::DragValue::new(value).speed(0.001);
+                                                if let Some(y_param) = settings.y_param {
+                                                    drag_value =
+                                                        drag_value.range(y_param.valid_range());
+                                                }
+                                                ui.add(drag_value);
+                                            }
+                                            ui.label("y_range");
+                                            ui.add(
+                                                egui::DragValue::new(&mut settings.y_n)
+                                                    .range(1..=16),
+                                            );
+                                            ui.label("y_n");
+                                        });
+                                    });
+                                    ui.horizontal(|ui| {
+                                        ui.add(
+                                            egui::DragValue::new(&mut settings.instance_particle_n)
+                                                .range(1..=4096),
+                                        );
+                                        ui.label("particle_n");
+                                        ui.add(
+                                            egui::DragValue::new(&mut settings.tile_size)
+                                                .range(16..=512),
+                                        );
+                                        ui.label("tile_size");
+                                    });
+                                    ui.add(
+                                        egui::Slider::new(&mut settings.particle_radius, 0.0..=0.05)
+                                            .text("particle_radius"),
+                                    );
+                                    if ui
+                                        .button("start sweep")
+                                        .on_hover_text("every sim starts from the same particles and uses the current attractions and the rest of the settings, which still apply while it runs")
+                                        .clicked()
+                                    {
+                                        self.sweep = Some(sweep::Sweep::new(
+                                            frame.wgpu_render_state().unwrap(),
+                                            &self.view_settings,
+                                            &self.sim_settings,
+                                            settings,
+                                        ));
+                                    }
+                                });
+                            }
+                            ui.collapsing("export", |ui| {
+                                ui.horizontal(|ui| {
+                                    ui.add(
+                                        egui::DragValue::new(
+                                            &mut self.export_settings.screenshot_size,
+                                        )
+                                        .range(1..=self.gfx_data.max_texture_size()),
+                                    );
+                                    ui.label("screenshot_size");
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.checkbox(
+                                        &mut self.export_settings.transparent_background,
+                                        "transparent_background",
+                                    );
+                                    if !self.export_settings.transparent_background {
+                                        ui.color_edit_button_srgba(
+                                            &mut self.export_settings.background_color,
+                                        );
+                                    }
+                                });
+                                if ui.button("save screenshot").clicked() {
+                                    self.export_status = Some(self.save_screenshot());
+                                }
+                                ui.separator();
+                                ui.add_enabled_ui(self.recording.is_none(), |ui| {
+                                    ui.horizontal(|ui| {
+                                        ui.radio_value(
+                                            &mut self.export_settings.recording_output,
+                                            export::RecordingOutput::PngSequence,
+                                            "png sequence",
+                                        );
+                                        ui.radio_value(
+                                            &mut self.export_settings.recording_output,
+                                            export::RecordingOutput::Encoder,
+                                            "encoder",
+                                        );
+                                    });
+                                    if self.export_settings.recording_output
+                                        == export::RecordingOutput::Encoder
+                                    {
+                                        ui.text_edit_singleline(
+                                            &mut self.export_settings.encoder_command,
+                                        )
+                                        .on_hover_text(
+                                            "gets raw rgba frames on stdin. {size}, {fps} and {name} are replaced",
+                                        );
+                                    }
+                                    ui.horizontal(|ui| {
+                                        ui.add(
+                                            egui::DragValue::new(
+                                                &mut self.export_settings.recording_size,
+                                            )
+                                            .range(1..=self.gfx_data.max_texture_size()),
+                                        );
+                                        ui.label("recording_size");
+                                    });
+                                    ui.horizontal(|ui| {
+                                        ui.add(
+                                            egui::DragValue::new(
+                                                &mut self.export_settings.recording_fps,
+                                            )
+                                            .range(1.0..=240.0),
+                                        );
+                                        ui.label("recording_fps");
+                                    });
+                                    ui.horizontal(|ui| {
+                                        ui.add(
+                                            egui::DragValue::new(
+                                                &mut self.export_settings.gif_size,
+                                            )
+                                            .range(1..=1024),
+                                        );
+                                        ui.label("gif_size");
+                                        ui.add(
+                                            egui::DragValue::new(&mut self.export_settings.gif_fps)
+                                                .range(1.0..=50.0),
+                                        );
+                                        ui.label("gif_fps");
+                                        ui.add(
+                                            egui::DragValue::new(
+                                                &mut self.export_settings.gif_seconds,
+                                            )
+                                            .range(0.1..=30.0),
+                                        );
+                                        ui.label("gif_seconds");
+                                    });
+                                });
+                                match self.recording.take() {
+                                    None => {
+                                        let started = ui
+                                            .horizontal(|ui| {
+                                                // both buttons are drawn before either is handled
+                                                let start = ui.button("start recording").clicked();
+                                                let gif = ui.button("record gif").clicked();
+                                                if start {
+                                                    Some(export::Recording::start(
+                                                        self.export_settings.recording_output,
+                                                        self.export_settings.recording_size,
+                                                        self.export_settings.recording_fps,
+                                                        self.export_settings.background(),
+                                                        &self.export_settings.encoder_command,
+                                                    ))
+                                                } else if gif {
+                                                    Some(export::Recording::start_gif(
+                                                        self.export_settings.gif_size,
+                                                        self.export_settings.gif_fps,
+                                                        self.export_settings.gif_seconds,
+                                                        self.export_settings.background(),
+                                                        &self.view_settings.specie_colors,
+                                                    ))
+                                                } else {
+                                                    None
+                                                }
+                                            })
+                                            .inner;
+                                        match started {
+                                            Some(Ok(recording)) => self.recording = Some(recording),
+                                            Some(Err(error)) => {
+                                                self.export_status = Some(format!(
+                                                    "couldn't start recording: {error}"
+                                                ));
+                                            }
+                                            None => {}
+                                        }
+                                    }
+                                    Some(recording) => {
+                                        ui.label(format!("recorded {} frames", recording.frame_n));
+                                        if ui.button("stop recording").clicked() {
+                                            self.export_status =
+                                                Some(recording.finish().unwrap_or_else(|error| {
+                                                    format!("couldn't finish recording: {error}")
+                                                }));
+                                        } else {
+                                            self.recording = Some(recording);
+                                        }
+                                    }
+                                }
+                                ui.separator();
+                                ui.add_enabled_ui(self.trajectory.is_none(), |ui| {
+                                    ui.horizontal(|ui| {
+                                        for format in trajectory::TrajectoryFormat::ALL {
+                                            ui.radio_value(
+                                                &mut self.export_settings.trajectory_format,
+                                                format,
+                                                format!("{format:?}"),
+                                            );
+                                        }
+                                    });
+                                    ui.horizontal(|ui| {
+                                        ui.add(
+                                            egui::DragValue::new(
+                                                &mut self.export_settings.trajectory_interval,
+                                            )
+                                            .range(1..=u64::MAX),
+                                        );
+                                        ui.label("trajectory_interval");
+                                    });
+                                });
+                                match self.trajectory.take() {
+                                    None => {
+                                        if ui.button("start trajectory").clicked() {
+                                            match trajectory::TrajectoryWriter::start(
+                                                self.export_settings.trajectory_format,
+                                                self.export_settings.trajectory_interval,
+                                                &self.sim_settings,
+                                                &self.gfx_data.read_sim_data().species,
+                                            ) {
+                                                Ok(trajectory) => {
+                                                    self.trajectory = Some(trajectory);
+                                                }
+                                                Err(error) => {
+                                                    self.export_status = Some(format!(
+                                                        "couldn't start trajectory: {error}"
+                                                    ));
+                                                }
+                                            }
+                                        }
+                                    }
+                                    Some(trajectory) => {
+                                        ui.label(format!(
+                                            "wrote {} samples, every {} ticks",
+                                            trajectory.sample_n, trajectory.tick_interval
+                                        ));
+                                        if ui.button("stop trajectory").clicked() {
+                                            self.export_status =
+                                                Some(finish_trajectory(trajectory));
+                                        } else {
+                                            self.trajectory = Some(trajectory);
+                                        }
+                                    }
+                                }
+                                if let Some(export_status) = &self.export_status {
+                                    ui.label(export_status);
+                                }
+                            });
                         });
                     });
             });
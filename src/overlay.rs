@@ -0,0 +1,141 @@
+use eframe::{egui_wgpu, wgpu};
+
+use crate::pm::ParticleMesh;
+
+const TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// gpu state for drawing the particle mesh grids over the particles, see overlay.wgsl
+/// it has its own texture so trails and bloom don't apply to it
+pub(crate) struct Overlay {
+    pub(crate) texture_id: eframe::egui::TextureId,
+    pub(crate) size: wgpu::Extent3d,
+    // of the particle mesh whose buffers are in bind_group
+    pub(crate) grid_size: u32,
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+}
+impl Overlay {
+    /// `render_bind_group_layout` is group 0, for the params and specie colors
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        renderer: &mut egui_wgpu::Renderer,
+        shader_module: &wgpu::ShaderModule,
+        render_bind_group_layout: &wgpu::BindGroupLayout,
+        particle_mesh: &ParticleMesh,
+        size: wgpu::Extent3d,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("overlay_texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: TEXTURE_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let texture_id = renderer.register_native_texture(
+            device,
+            &texture.create_view(&Default::default()),
+            wgpu::FilterMode::Nearest,
+        );
+
+        let buffer_entry = |binding, buffer: &wgpu::Buffer| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: wgpu::BufferSize::new(buffer.size()),
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("overlay_bind_group_layout"),
+            entries: &[
+                // density_buffer
+                buffer_entry(0, &particle_mesh.density_buffer),
+                // field_buffer
+                buffer_entry(1, &particle_mesh.field_buffer),
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("overlay_bind_group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: particle_mesh.density_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: particle_mesh.field_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("overlay_pipeline_layout"),
+            bind_group_layouts: &[render_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("overlay_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: shader_module,
+                entry_point: "fade_vs",
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader_module,
+                entry_point: "overlay_fs",
+                compilation_options: Default::default(),
+                targets: &[Some(TEXTURE_FORMAT.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            texture_id,
+            size,
+            grid_size: particle_mesh.grid_size,
+            texture,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    /// draws the grids, which ParticleMesh::dispatch_field() should have just filled
+    pub(crate) fn dispatch(
+        &self,
+        command_encoder: &mut wgpu::CommandEncoder,
+        render_bind_group: &wgpu::BindGroup,
+    ) {
+        let view = self.texture.create_view(&Default::default());
+        let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("overlay_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, render_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,75 @@
+// the density and field overlays, appended after pm.wgsl since they show its grids
+// drawn with fade_vs to their own texture, which the app paints over the particles
+
+@group(1) @binding(0) var<storage, read> overlay_density: array<u32>;
+@group(1) @binding(1) var<storage, read> overlay_field: array<vec2<f32>>;
+
+// the same as OverlayMode in main.rs
+const OVERLAY_MODE_DENSITY_HEATMAP: u32 = 1u;
+const OVERLAY_MODE_DENSITY_CONTOURS: u32 = 2u;
+const OVERLAY_MODE_FIELD: u32 = 3u;
+
+// interpolated from the 4 nearest cells like pm_integrate
+fn overlay_density_at(specie: u32, cic: Cic) -> f32 {
+    let w = vec2(1.0, 1.0) - cic.frac;
+    let count = f32(overlay_density[grid_index(specie, cic.cell)]) * w.x * w.y
+        + f32(overlay_density[grid_index(specie, cic.cell + vec2(1, 0))]) * cic.frac.x * w.y
+        + f32(overlay_density[grid_index(specie, cic.cell + vec2(0, 1))]) * w.x * cic.frac.y
+        + f32(overlay_density[grid_index(specie, cic.cell + vec2(1, 1))]) * cic.frac.x * cic.frac.y;
+    return count / DENSITY_SCALE;
+}
+
+fn overlay_field_at(specie: u32, cic: Cic) -> vec2<f32> {
+    let w = vec2(1.0, 1.0) - cic.frac;
+    return overlay_field[grid_index(specie, cic.cell)] * w.x * w.y
+        + overlay_field[grid_index(specie, cic.cell + vec2(1, 0))] * cic.frac.x * w.y
+        + overlay_field[grid_index(specie, cic.cell + vec2(0, 1))] * w.x * cic.frac.y
+        + overlay_field[grid_index(specie, cic.cell + vec2(1, 1))] * cic.frac.x * cic.frac.y;
+}
+
+@fragment
+fn overlay_fs(@builtin(position) frag_pos: vec4<f32>) -> @location(0) vec4<f32> {
+    // the inverse of main_vs, where the rows of the texture go down but clip space goes up
+    let screen = vec2(frag_pos.x, f32(params.texture_size) - frag_pos.y) / f32(params.texture_size);
+    let pos = fract(screen / params.zoom_scale + vec2(params.zoom_center_x, params.zoom_center_y));
+    let cic = get_cic(pos);
+    // densities are relative to the average of one specie
+    let cell_n = f32(params.pm_grid_size * params.pm_grid_size);
+    let mean_density = f32(params.particle_n) / (f32(params.specie_n) * cell_n);
+
+    switch params.overlay_mode {
+        case OVERLAY_MODE_DENSITY_HEATMAP: {
+            // the species colors mixed by density, brighter where there are more particles
+            var color = vec3(0.0, 0.0, 0.0);
+            var total = 0.0;
+            for (var specie = 0u; specie < params.specie_n; specie++) {
+                let density = overlay_density_at(specie, cic) / mean_density;
+                color += specie_colors[specie].rgb * density;
+                total += density;
+            }
+            let intensity = saturate(total / (f32(params.specie_n) * params.overlay_scale));
+            return vec4(color / max(total, 1e-6) * intensity, intensity);
+        }
+        case OVERLAY_MODE_DENSITY_CONTOURS: {
+            // a line about a pixel wide in the specie's color every quarter of overlay_scale
+            var color = vec4(0.0, 0.0, 0.0, 0.0);
+            for (var specie = 0u; specie < params.specie_n; specie++) {
+                let level = overlay_density_at(specie, cic) / mean_density / (0.25 * params.overlay_scale);
+                let line = (1.0 - saturate(abs(fract(level + 0.5) - 0.5) / max(fwidth(level), 1e-6)))
+                    * step(0.5, level);
+                color = mix(color, vec4(specie_colors[specie].rgb, 1.0), line);
+            }
+            return color;
+        }
+        case OVERLAY_MODE_FIELD: {
+            // direction as a hue like ColorMode::Heading, brighter where the force is stronger
+            let force = overlay_field_at(params.overlay_specie, cic) * params.force_multiplier;
+            let intensity = saturate(length(force) / params.overlay_scale);
+            let heading = degrees(atan2(force.y, force.x));
+            return vec4(hue(fract(heading / 360.0 + 0.5)).rgb * intensity, intensity);
+        }
+        default: {
+            return vec4(0.0, 0.0, 0.0, 0.0);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use eframe::wgpu;
+
+/// gpu state for the particle mesh solver, see pm.wgsl
+pub(crate) struct ParticleMesh {
+    pub(crate) grid_size: u32,
+    // also read by the overlay
+    pub(crate) density_buffer: wgpu::Buffer,
+    pub(crate) field_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    particles_per_group: usize,
+    deposit_pipeline: wgpu::ComputePipeline,
+    field_pipeline: wgpu::ComputePipeline,
+    integrate_pipeline: wgpu::ComputePipeline,
+}
+impl ParticleMesh {
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        shader_module: &wgpu::ShaderModule,
+        compute_bind_group_layout: &wgpu::BindGroupLayout,
+        particles_per_group: usize,
+        specie_n: usize,
+        grid_size: u32,
+    ) -> Self {
+        let cell_n = specie_n as u64 * grid_size as u64 * grid_size as u64;
+        let density_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("pm_density_buffer"),
+            size: cell_n * size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let field_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("pm_field_buffer"),
+            size: cell_n * 2 * size_of::<f32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("pm_bind_group_layout"),
+            entries: &[
+                // density_buffer
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(density_buffer.size()),
+                    },
+                    count: None,
+                },
+                // field_buffer
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(field_buffer.size()),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("pm_bind_group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: density_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: field_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("pm_pipeline_layout"),
+            bind_group_layouts: &[compute_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: shader_module,
+                entry_point,
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+
+        Self {
+            grid_size,
+            deposit_pipeline: create_pipeline("pm_deposit"),
+            field_pipeline: create_pipeline("pm_field"),
+            integrate_pipeline: create_pipeline("pm_integrate"),
+            density_buffer,
+            field_buffer,
+            bind_group,
+            particles_per_group,
+        }
+    }
+
+    /// does one tick, reading from and writing to the buffers in `compute_bind_group`
+    pub(crate) fn dispatch(
+        &self,
+        command_encoder: &mut wgpu::CommandEncoder,
+        compute_bind_group: &wgpu::BindGroup,
+        specie_n: usize,
+        particle_n: usize,
+    ) {
+        self.record(
+            command_encoder,
+            compute_bind_group,
+            specie_n,
+            particle_n,
+            true,
+        );
+    }
+
+    /// fills density_buffer and field_buffer from the positions in `compute_bind_group` without moving anything
+    pub(crate) fn dispatch_field(
+        &self,
+        command_encoder: &mut wgpu::CommandEncoder,
+        compute_bind_group: &wgpu::BindGroup,
+        specie_n: usize,
+        particle_n: usize,
+    ) {
+        self.record(
+            command_encoder,
+            compute_bind_group,
+            specie_n,
+            particle_n,
+            false,
+        );
+    }
+
+    fn record(
+        &self,
+        command_encoder: &mut wgpu::CommandEncoder,
+        compute_bind_group: &wgpu::BindGroup,
+        specie_n: usize,
+        particle_n: usize,
+        integrate: bool,
+    ) {
+        command_encoder.clear_buffer(&self.density_buffer, 0, None);
+        let mut compute_pass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("pm_compute_pass"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_bind_group(0, compute_bind_group, &[]);
+        compute_pass.set_bind_group(1, &self.bind_group, &[]);
+
+        let particle_group_count = particle_n.div_ceil(self.particles_per_group) as u32;
+        let cell_group_count = (specie_n * self.grid_size as usize * self.grid_size as usize)
+            .div_ceil(self.particles_per_group) as u32;
+        compute_pass.set_pipeline(&self.deposit_pipeline);
+        compute_pass.dispatch_workgroups(particle_group_count, 1, 1);
+        compute_pass.set_pipeline(&self.field_pipeline);
+        compute_pass.dispatch_workgroups(cell_group_count, 1, 1);
+        if integrate {
+            compute_pass.set_pipeline(&self.integrate_pipeline);
+            compute_pass.dispatch_workgroups(particle_group_count, 1, 1);
+        }
+    }
+}
@@ -0,0 +1,105 @@
+// particle mesh solver, appended to common.wgsl and shader.wgsl
+// each specie's particles are deposited onto a pm_grid_size x pm_grid_size grid (wrapping like the simulation),
+// then for each specie the force it would feel is computed at each cell by a direct stencil over the densities,
+// then each particle gets the force of its specie interpolated from the 4 nearest cells.
+// it's O(particle_n + pm_grid_size^2 * specie_n^2 * (local_radius * pm_grid_size)^2),
+// and the repulsion at distances less than about a cell is smeared out
+
+// atomics don't work on floats, so densities are stored as fixed point
+const DENSITY_SCALE: f32 = 65536.0;
+
+// density[(specie * pm_grid_size + y) * pm_grid_size + x]
+@group(1) @binding(0) var<storage, read_write> density: array<atomic<u32>>;
+// field[(specie * pm_grid_size + y) * pm_grid_size + x] is the force a particle of that specie would feel at the cell center
+@group(1) @binding(1) var<storage, read_write> field: array<vec2<f32>>;
+
+fn grid_index(specie: u32, cell: vec2<i32>) -> u32 {
+    let size = i32(params.pm_grid_size);
+    let wrapped = ((cell % size) + size) % size;
+    return (specie * params.pm_grid_size + u32(wrapped.y)) * params.pm_grid_size + u32(wrapped.x);
+}
+
+// cloud in cell weights of the 4 cells around pos
+struct Cic {
+    cell: vec2<i32>,
+    frac: vec2<f32>,
+}
+fn get_cic(pos: vec2<f32>) -> Cic {
+    // cell centers are at (i + 0.5) / pm_grid_size
+    let grid_pos = pos * f32(params.pm_grid_size) - vec2(0.5, 0.5);
+    let cell = floor(grid_pos);
+    return Cic(vec2<i32>(cell), grid_pos - cell);
+}
+
+@compute
+@workgroup_size(PARTICLES_PER_GROUP)
+fn pm_deposit(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let index = global_invocation_id.x;
+    if (index >= params.particle_n) {
+        return;
+    }
+    let specie = species[index];
+    let cic = get_cic(pos_src[index]);
+    let w = vec2(1.0, 1.0) - cic.frac;
+    atomicAdd(&density[grid_index(specie, cic.cell)], u32(w.x * w.y * DENSITY_SCALE));
+    atomicAdd(&density[grid_index(specie, cic.cell + vec2(1, 0))], u32(cic.frac.x * w.y * DENSITY_SCALE));
+    atomicAdd(&density[grid_index(specie, cic.cell + vec2(0, 1))], u32(w.x * cic.frac.y * DENSITY_SCALE));
+    atomicAdd(&density[grid_index(specie, cic.cell + vec2(1, 1))], u32(cic.frac.x * cic.frac.y * DENSITY_SCALE));
+}
+
+// one invocation per (specie, cell)
+@compute
+@workgroup_size(PARTICLES_PER_GROUP)
+fn pm_field(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let index = global_invocation_id.x;
+    let cell_n = params.pm_grid_size * params.pm_grid_size;
+    if (index >= params.specie_n * cell_n) {
+        return;
+    }
+    let specie = index / cell_n;
+    let cell = vec2<i32>(vec2(index % params.pm_grid_size, (index % cell_n) / params.pm_grid_size));
+    let cell_size = 1.0 / f32(params.pm_grid_size);
+    let reach = i32(ceil(params.local_radius / cell_size));
+
+    var force = vec2(0.0, 0.0);
+    for (var dy = -reach; dy <= reach; dy++) {
+        for (var dx = -reach; dx <= reach; dx++) {
+            let to_neighbor = vec2(f32(dx), f32(dy)) * cell_size;
+            let distance2 = dot(to_neighbor, to_neighbor);
+            if distance2 > params.local_radius2 {
+                continue;
+            }
+            if distance2 == 0.0 {
+                continue;
+            }
+            let distance = sqrt(distance2);
+            let dir = to_neighbor / distance;
+            for (var neighbor_specie = 0u; neighbor_specie < params.specie_n; neighbor_specie++) {
+                let count = f32(atomicLoad(&density[grid_index(neighbor_specie, cell + vec2(dx, dy))])) / DENSITY_SCALE;
+                force += dir * count * get_attraction_force(
+                    distance * (1.0 / params.local_radius),
+                    attractions[specie * params.specie_n + neighbor_specie],
+                );
+            }
+        }
+    }
+    field[index] = force;
+}
+
+@compute
+@workgroup_size(PARTICLES_PER_GROUP)
+fn pm_integrate(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let index = global_invocation_id.x;
+    if (index >= params.particle_n) {
+        return;
+    }
+    let pos = pos_src[index];
+    let specie = species[index];
+    let cic = get_cic(pos);
+    let w = vec2(1.0, 1.0) - cic.frac;
+    let force = field[grid_index(specie, cic.cell)] * w.x * w.y
+        + field[grid_index(specie, cic.cell + vec2(1, 0))] * cic.frac.x * w.y
+        + field[grid_index(specie, cic.cell + vec2(0, 1))] * w.x * cic.frac.y
+        + field[grid_index(specie, cic.cell + vec2(1, 1))] * cic.frac.x * cic.frac.y;
+    integrate(index, pos, force);
+}
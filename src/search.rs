@@ -0,0 +1,429 @@
+use std::path::{Path, PathBuf};
+
+use eframe::{egui, egui_wgpu};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    arg_value, bench::list_arg, census::Census, export, headless, Args, Dimension, GfxData,
+    SimData, SimSettings, ViewSettings,
+};
+
+const SPECIE_N: usize = 6;
+// censuses per candidate, spread over the second half of its run
+const SAMPLE_N: usize = 4;
+pub(crate) const THUMBNAIL_SIZE: u32 = 128;
+// particles in clusters smaller than this are counted as loose
+const MIN_CLUSTER_SIZE: usize = 8;
+
+/// how interesting a candidate looked, each in [0, 1]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub(crate) struct Scores {
+    // high for many clusters holding most of the particles, 0 for a gas or one big blob
+    pub(crate) clusters: f32,
+    // high for particles that keep moving, low for frozen crystals
+    pub(crate) motion: f32,
+    // high if the number of clusters stays the same between censuses
+    pub(crate) stability: f32,
+    // the weighted geometric mean of the others, so a candidate has to do ok at all of them
+    pub(crate) total: f32,
+}
+
+/// one of the best candidates, which can be loaded from the gallery
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub(crate) struct Preset {
+    // the attractions came from StdRng::seed_from_u64(seed)
+    seed: u64,
+    attractions: Vec<Vec<f32>>,
+    scores: Scores,
+    // averaged over the censuses
+    cluster_n: f32,
+    mean_speed: f32,
+    // the file name of its last frame, in the same directory as gallery.json
+    thumbnail: String,
+}
+
+/// gallery.json
+#[derive(serde::Serialize, serde::Deserialize)]
+struct GalleryFile {
+    dimension: Dimension,
+    specie_n: usize,
+    local_radius: f32,
+    friction_half_life: f32,
+    // the weights of Scores::clusters, motion and stability in Scores::total
+    weights: [f32; 3],
+    // everything the candidates were run with, just for reference
+    sim_settings: serde_json::Value,
+    // best first
+    presets: Vec<Preset>,
+}
+
+/// runs random attraction matrices headless, scores them by their clusters and motion,
+/// and writes the best ones with thumbnails to a directory the gallery can open
+/// `--search-candidates 64`, `--search-top 8`, `--search-seconds 8`, `--search-seed 0`,
+/// `--search-weights 1,1,1` and `--search-out search_1700000000` set it up, and `--particle-n` and `--3d` work too
+pub(crate) fn run(args: &Args) {
+    let candidate_n: u64 = number_arg("--search-candidates", 64);
+    let top_n: usize = number_arg("--search-top", 8);
+    let seconds: f32 = number_arg("--search-seconds", 8.0);
+    let first_seed: u64 = number_arg("--search-seed", 0);
+    let weights: [f32; 3] = list_arg("--search-weights", &[1.0, 1.0, 1.0])
+        .try_into()
+        .expect("--search-weights should be 3 numbers for clusters, motion and stability");
+    let out = PathBuf::from(
+        arg_value("--search-out").unwrap_or_else(|| export::timestamped_name("search")),
+    );
+    std::fs::create_dir_all(&out).expect("should be able to make the output directory");
+
+    let render_state = headless::create_render_state();
+    let view_settings = ViewSettings::new(args.dimension, SPECIE_N, args.particle_n);
+    let base_settings = SimSettings {
+        workgroup_size: args.workgroup_size,
+        dt: 1.0 / 60.0,
+        // each candidate has its own
+        attractions: Vec::new(),
+        ..SimSettings::new(args.dimension, SPECIE_N, args.particle_n)
+    };
+    let frame_n = (seconds / base_settings.dt) as usize;
+
+    // the best so far and their thumbnails, best first
+    let mut best: Vec<(Preset, Vec<u8>)> = Vec::new();
+    for seed in first_seed..first_seed + candidate_n {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let sim_settings = SimSettings {
+            attractions: (0..SPECIE_N)
+                .map(|_| (0..SPECIE_N).map(|_| rng.gen_range(-1.0..=1.0)).collect())
+                .collect(),
+            ..base_settings.clone()
+        };
+        // the positions come from the same rng, so a seed reproduces the whole trial
+        let mut trial = Trial::new(
+            &render_state,
+            &view_settings,
+            sim_settings,
+            frame_n,
+            &mut rng,
+        );
+        while !trial.is_done() {
+            trial.step();
+        }
+        let scores = trial.scores(weights);
+        let (cluster_n, mean_speed) = (trial.cluster_n(), trial.mean_speed());
+        println!(
+            "seed {seed:>4}  total {:.3}  clusters {:.3}  motion {:.3}  stability {:.3}  cluster_n {cluster_n:.1}  mean_speed {mean_speed:.4}",
+            scores.total, scores.clusters, scores.motion, scores.stability,
+        );
+
+        let rank = best.partition_point(|(preset, _)| preset.scores.total >= scores.total);
+        // a NaN mean_speed would be a null in gallery.json that can't be loaded back
+        if rank < top_n && mean_speed.is_finite() {
+            let preset = Preset {
+                seed,
+                attractions: trial.sim_settings.attractions.clone(),
+                scores,
+                cluster_n,
+                mean_speed,
+                thumbnail: format!("seed_{seed}.png"),
+            };
+            best.insert(rank, (preset, trial.thumbnail()));
+            best.truncate(top_n);
+        }
+    }
+
+    for (preset, thumbnail) in &best {
+        export::write_png(&out.join(&preset.thumbnail), THUMBNAIL_SIZE, thumbnail)
+            .expect("should be able to write the thumbnail");
+    }
+    let gallery_file = GalleryFile {
+        dimension: args.dimension,
+        specie_n: SPECIE_N,
+        local_radius: base_settings.local_radius,
+        friction_half_life: base_settings.friction_half_life,
+        weights,
+        sim_settings: serde_json::to_value(&base_settings).unwrap(),
+        presets: best.into_iter().map(|(preset, _)| preset).collect(),
+    };
+    std::fs::write(
+        out.join("gallery.json"),
+        serde_json::to_string_pretty(&gallery_file).unwrap(),
+    )
+    .expect("should be able to write the gallery");
+    println!(
+        "wrote {}, open it with --gallery {}",
+        out.join("gallery.json").display(),
+        out.display()
+    );
+}
+
+/// one sim_settings run headless for frame_n frames, with a census at the end of each of
+/// SAMPLE_N equal parts of the second half to score it
+pub(crate) struct Trial {
+    gfx_data: GfxData,
+    view_settings: ViewSettings,
+    pub(crate) sim_settings: SimSettings,
+    frame: usize,
+    frame_n: usize,
+    // from each census
+    cluster_ns: Vec<f32>,
+    clustered_fractions: Vec<f32>,
+    mean_speeds: Vec<f32>,
+}
+impl Trial {
+    /// starts from random positions drawn from `rng`, with view_settings only used for the thumbnail
+    pub(crate) fn new(
+        render_state: &egui_wgpu::RenderState,
+        view_settings: &ViewSettings,
+        sim_settings: SimSettings,
+        frame_n: usize,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let view_settings = ViewSettings {
+            // it's never rendered to the window, so the texture might as well be small
+            texture_size: THUMBNAIL_SIZE,
+            ..view_settings.clone()
+        };
+        let gfx_data = match sim_settings.dimension {
+            Dimension::Two => GfxData::new(
+                render_state,
+                &view_settings,
+                &sim_settings,
+                &SimData::from_rng(rng, sim_settings.specie_n, sim_settings.particle_n),
+            ),
+            Dimension::Three => GfxData::new(
+                render_state,
+                &view_settings,
+                &sim_settings,
+                &SimData::from_rng_3d(rng, sim_settings.specie_n, sim_settings.particle_n),
+            ),
+        };
+        Self {
+            gfx_data,
+            view_settings,
+            sim_settings,
+            frame: 0,
+            frame_n: frame_n.max(2 * SAMPLE_N),
+            cluster_ns: Vec::new(),
+            clustered_fractions: Vec::new(),
+            mean_speeds: Vec::new(),
+        }
+    }
+
+    pub(crate) fn is_done(&self) -> bool {
+        self.frame == self.frame_n
+    }
+
+    /// the fraction of its frames done
+    pub(crate) fn progress(&self) -> f32 {
+        self.frame as f32 / self.frame_n as f32
+    }
+
+    /// does sim_settings.substep_n ticks and waits for them, then takes a census if it's time
+    pub(crate) fn step(&mut self) {
+        self.gfx_data.step(&self.view_settings, &self.sim_settings);
+        self.gfx_data.wait();
+        self.frame += 1;
+        let sample_interval = self.frame_n / 2 / SAMPLE_N;
+        if self.frame > self.frame_n - SAMPLE_N * sample_interval
+            && (self.frame_n - self.frame).is_multiple_of(sample_interval)
+        {
+            let sim_data = self.gfx_data.read_sim_data();
+            let census = Census::take(
+                &sim_data,
+                self.sim_settings.specie_n,
+                match self.sim_settings.dimension {
+                    Dimension::Two => 2,
+                    Dimension::Three => 3,
+                },
+                // about where particles start repelling each other, like CensusSettings
+                0.3 * self.sim_settings.local_radius,
+                MIN_CLUSTER_SIZE,
+                self.gfx_data.sim_time(),
+            );
+            let particle_n = self.sim_settings.particle_n as f32;
+            self.cluster_ns.push(census.clusters.len() as f32);
+            self.clustered_fractions
+                .push(1.0 - census.loose_n as f32 / particle_n);
+            self.mean_speeds.push(
+                sim_data
+                    .vels
+                    .iter()
+                    .map(|vel| vel.iter().map(|v| v * v).sum::<f32>().sqrt())
+                    .sum::<f32>()
+                    / particle_n,
+            );
+        }
+    }
+
+    /// the mean number of clusters over the censuses so far
+    pub(crate) fn cluster_n(&self) -> f32 {
+        mean(&self.cluster_ns)
+    }
+
+    pub(crate) fn mean_speed(&self) -> f32 {
+        mean(&self.mean_speeds)
+    }
+
+    /// `weights` are for clusters, motion and stability in the total, once it's done
+    pub(crate) fn scores(&self, weights: [f32; 3]) -> Scores {
+        let cluster_n = self.cluster_n();
+        let clusters = if cluster_n > 0.0 {
+            mean(&self.clustered_fractions) * (1.0 - 1.0 / cluster_n.max(1.0))
+        } else {
+            0.0
+        };
+        // a particle going local_radius per second counts as moving
+        let motion = 1.0 - (-self.mean_speed() / self.sim_settings.local_radius).exp();
+        let stability = if cluster_n > 0.0 {
+            let change_n = self
+                .cluster_ns
+                .windows(2)
+                .map(|pair| (pair[1] - pair[0]).abs())
+                .sum::<f32>();
+            (1.0 - change_n / (self.cluster_ns.len() - 1) as f32 / cluster_n).max(0.0)
+        } else {
+            0.0
+        };
+        let total = [clusters, motion, stability]
+            .iter()
+            .zip(weights)
+            .map(|(score, weight)| score.powf(weight))
+            .product::<f32>()
+            .powf(1.0 / weights.iter().sum::<f32>().max(f32::EPSILON));
+        // blown up sims have NaN speeds, which would make NaN scores that can't be sorted or saved
+        let finite_or_zero = |score: f32| if score.is_finite() { score } else { 0.0 };
+        Scores {
+            clusters: finite_or_zero(clusters),
+            motion: finite_or_zero(motion),
+            stability: finite_or_zero(stability),
+            total: finite_or_zero(total),
+        }
+    }
+
+    /// the current frame, THUMBNAIL_SIZE square as rgba over black
+    pub(crate) fn thumbnail(&mut self) -> Vec<u8> {
+        export::apply_background(
+            &self
+                .gfx_data
+                .screenshot(&self.view_settings, &self.sim_settings, THUMBNAIL_SIZE),
+            export::Background::Color(egui::Color32::BLACK),
+        )
+    }
+}
+
+fn mean(values: &[f32]) -> f32 {
+    values.iter().sum::<f32>() / values.len() as f32
+}
+
+/// the number after `name`, or `default`
+fn number_arg<T: std::str::FromStr>(name: &str, default: T) -> T {
+    arg_value(name).map_or(default, |value| {
+        value
+            .parse()
+            .unwrap_or_else(|_| panic!("{name} should be a number"))
+    })
+}
+
+/// the presets from a search, shown as thumbnails that load their attractions when clicked
+pub(crate) struct Gallery {
+    dir: PathBuf,
+    file: GalleryFile,
+    // None if the png couldn't be read
+    thumbnails: Vec<Option<egui::TextureHandle>>,
+    // the index of the last preset loaded
+    loaded: Option<usize>,
+}
+impl Gallery {
+    /// reads dir/gallery.json and the thumbnails
+    pub(crate) fn open(ctx: &egui::Context, dir: &Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(dir.join("gallery.json"))
+            .map_err(|error| format!("couldn't read {}: {error}", dir.display()))?;
+        let file: GalleryFile = serde_json::from_str(&json)
+            .map_err(|error| format!("couldn't parse {}: {error}", dir.display()))?;
+        let thumbnails = file
+            .presets
+            .iter()
+            .map(|preset| {
+                let path = dir.join(&preset.thumbnail);
+                let image = read_png(&path).ok()?;
+                Some(ctx.load_texture(
+                    path.display().to_string(),
+                    image,
+                    egui::TextureOptions::LINEAR,
+                ))
+            })
+            .collect();
+        Ok(Self {
+            dir: dir.to_owned(),
+            file,
+            thumbnails,
+            loaded: None,
+        })
+    }
+
+    /// clicking a preset puts its attractions, local_radius and friction_half_life in sim_settings
+    pub(crate) fn ui(&mut self, ui: &mut egui::Ui, sim_settings: &mut SimSettings) {
+        ui.label(self.dir.display().to_string());
+        let fits = self.file.dimension == sim_settings.dimension
+            && self.file.specie_n == sim_settings.specie_n;
+        if !fits {
+            ui.label(format!(
+                "these are for {:?} with {} species, so they can't be loaded",
+                self.file.dimension, self.file.specie_n
+            ));
+        }
+        egui::ScrollArea::vertical()
+            .max_height(400.0)
+            .show(ui, |ui| {
+                egui::Grid::new("gallery").show(ui, |ui| {
+                    for (preset_i, (preset, thumbnail)) in
+                        self.file.presets.iter().zip(&self.thumbnails).enumerate()
+                    {
+                        let size = egui::Vec2::splat(THUMBNAIL_SIZE as f32);
+                        let button = match thumbnail {
+                            Some(thumbnail) => {
+                                egui::Button::image(egui::load::SizedTexture::new(
+                                    thumbnail.id(),
+                                    size,
+                                ))
+                            }
+                            None => egui::Button::new(format!("seed {}", preset.seed))
+                                .min_size(size),
+                        }
+                        .selected(self.loaded == Some(preset_i));
+                        let Scores {
+                            clusters,
+                            motion,
+                            stability,
+                            total,
+                        } = preset.scores;
+                        if ui
+                            .add_enabled(fits, button)
+                            .on_hover_text(format!(
+                                "seed {}\ntotal {total:.3}\nclusters {clusters:.3}\nmotion {motion:.3}\nstability {stability:.3}\n{:.1} clusters, mean speed {:.4}",
+                                preset.seed, preset.cluster_n, preset.mean_speed,
+                            ))
+                            .clicked()
+                        {
+                            sim_settings.attractions = preset.attractions.clone();
+                            sim_settings.local_radius = self.file.local_radius;
+                            sim_settings.friction_half_life = self.file.friction_half_life;
+                            self.loaded = Some(preset_i);
+                        }
+                        if preset_i % 3 == 2 {
+                            ui.end_row();
+                        }
+                    }
+                });
+            });
+    }
+}
+
+/// the thumbnails are always rgba, from export::write_png
+fn read_png(path: &Path) -> Result<egui::ColorImage, png::DecodingError> {
+    let mut reader = png::Decoder::new(std::fs::File::open(path)?).read_info()?;
+    let mut rgba = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut rgba)?;
+    Ok(egui::ColorImage::from_rgba_unmultiplied(
+        [info.width as usize, info.height as usize],
+        &rgba,
+    ))
+}
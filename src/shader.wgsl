@@ -18,40 +18,24 @@
 // @group(0) @binding(2) var<storage, read_write> particlesDst: array<Particle>;
 
 
-struct Params {
-    specie_n: u32,
-    particle_n: u32,
-    local_radius: f32,
-    local_radius2: f32,
-    friction: f32,
-    dt: f32,
-    force_multiplier: f32,
-    particle_radius: f32,
-    particle_radius2: f32,
-    texture_size: u32,
-    zoom_scale: f32,
-    // zoom_center: vec2<f32>,
-    zoom_center_x: f32,
-    zoom_center_y: f32,
-}
-
-@group(0) @binding(0) var<uniform> params: Params;
 @group(0) @binding(1) var<storage, read> pos_src: array<vec2<f32>>;
 @group(0) @binding(2) var<storage, read> vel_src: array<vec2<f32>>;
 @group(0) @binding(3) var<storage, read_write> pos_dst: array<vec2<f32>>;
 @group(0) @binding(4) var<storage, read_write> vel_dst: array<vec2<f32>>;
-@group(0) @binding(5) var<storage, read> species: array<u32>;
-@group(0) @binding(6) var<storage, read> attractions: array<f32>;
-@group(0) @binding(7) var<storage, read> specie_colors: array<vec4<f32>>;
 
 // https://github.com/austinEng/Project6-Vulkan-Flocking/blob/master/data/shaders/computeparticles/particle.comp
 @compute
-@workgroup_size(64) // TODO: wtf should i do with this
+@workgroup_size(PARTICLES_PER_GROUP)
 fn main_cs(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
     let index = global_invocation_id.x;
     if (index >= params.particle_n) {
         return;
     }
+    integrate(index, pos_src[index], pairwise_force(index));
+}
+
+// the exact force on particle index, which the approximate solvers are compared against
+fn pairwise_force(index: u32) -> vec2<f32> {
     // let prev_prev_pos = pos_dst[index];
     // storageBarrier();
     // workgroupBarrier();
@@ -65,45 +49,146 @@ fn main_cs(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
             continue;
         }
 
-        let neighbor_pos = pos_src[neighbor_i];
-        var to_neighbor = neighbor_pos - pos;
+        force += pair_force(
+            pos,
+            pos_src[neighbor_i],
+            attractions[species[index]*params.specie_n + species[neighbor_i]],
+        );
+    }
+
+    return force;
+}
+
+// the force on a particle at pos from a particle at neighbor_pos
+fn pair_force(pos: vec2<f32>, neighbor_pos: vec2<f32>, attraction: f32) -> vec2<f32> {
+    var to_neighbor = neighbor_pos - pos;
+
+    // allow to_neighber to wrap around the walls
+    to_neighbor -= step(vec2(0.5, 0.5), to_neighbor);
+    to_neighbor += step(to_neighbor, vec2(-0.5, -0.5));
+    // if to_neighbor.x > 0.5 {
+    //     to_neighbor.x -= 1.0;
+    // } else if to_neighbor.x < -0.5 {
+    //     to_neighbor.x += 1.0;
+    // }
+    // if to_neighbor.y > 0.5 {
+    //     to_neighbor.y -= 1.0;
+    // } else if to_neighbor.y < -0.5 {
+    //     to_neighbor.y += 1.0;
+    // }
+
+    let distance2 = dot(to_neighbor, to_neighbor);
+    if distance2 > params.local_radius2 {
+        return vec2(0.0, 0.0);
+    }
+    if distance2 == 0.0 {
+        return vec2(0.0, 0.0);
+    }
+    let distance = sqrt(distance2);
+    return (to_neighbor / distance)
+        * get_attraction_force(distance * (1.0 / params.local_radius), attraction);
+}
+
+// same as main_cs, but each workgroup loads a tile of PARTICLES_PER_GROUP neighbors into workgroup memory,
+// so each neighbor is read from storage once per workgroup instead of once per particle
+var<workgroup> tile_poses: array<vec2<f32>, PARTICLES_PER_GROUP>;
+var<workgroup> tile_species: array<u32, PARTICLES_PER_GROUP>;
+
+@compute
+@workgroup_size(PARTICLES_PER_GROUP)
+fn main_cs_tiled(
+    @builtin(global_invocation_id) global_invocation_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    let index = global_invocation_id.x;
+    // invocations past the end still help load tiles, because every invocation has to reach the barriers
+    // max so it doesn't underflow with no particles
+    let clamped_index = min(index, max(params.particle_n, 1u) - 1u);
+    let pos = pos_src[clamped_index];
+    let attraction_row = species[clamped_index] * params.specie_n;
+    var force = vec2(0.0, 0.0);
+
+    for (var tile_start = 0u; tile_start < params.particle_n; tile_start += PARTICLES_PER_GROUP) {
+        let load_i = tile_start + local_index;
+        if (load_i < params.particle_n) {
+            tile_poses[local_index] = pos_src[load_i];
+            tile_species[local_index] = species[load_i];
+        }
+        workgroupBarrier();
+
+        let tile_n = min(PARTICLES_PER_GROUP, params.particle_n - tile_start);
+        for (var tile_i = 0u; tile_i < tile_n; tile_i++) {
+            if (tile_start + tile_i == index) {
+                continue;
+            }
+            force += pair_force(pos, tile_poses[tile_i], attractions[attraction_row + tile_species[tile_i]]);
+        }
+        workgroupBarrier();
+    }
+
+    if (index < params.particle_n) {
+        integrate(index, pos, force);
+    }
+}
+
+// the neighbor count, net force or cluster of each particle for ColorMode, once per frame before rendering
+// always pairwise, whatever the solver is, which is why ColorMode::is_available limits the particle_n for it
+@group(1) @binding(0) var<storage, read_write> color_values: array<f32>;
+// the cluster label of each particle by stable id, and the stable id of the particle in each slot
+@group(1) @binding(1) var<storage, read> cluster_labels: array<f32>;
+@group(1) @binding(2) var<storage, read> particle_ids: array<u32>;
+
+@compute
+@workgroup_size(PARTICLES_PER_GROUP)
+fn color_value_cs(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let index = global_invocation_id.x;
+    if (index >= params.particle_n) {
+        return;
+    }
+    if (params.color_mode == COLOR_MODE_CLUSTER) {
+        color_values[index] = cluster_labels[particle_ids[index]];
+        return;
+    }
+    if (params.color_mode == COLOR_MODE_FORCE) {
+        color_values[index] = length(pairwise_force(index) * params.force_multiplier);
+        return;
+    }
+
+    color_values[index] = f32(neighbor_count(index));
+}
 
-        // allow to_neighber to wrap around the walls
+// how many particles are within local_radius of particle index
+fn neighbor_count(index: u32) -> u32 {
+    let pos = pos_src[index];
+    var neighbor_n = 0u;
+    for (var neighbor_i: u32 = 0; neighbor_i < params.particle_n; neighbor_i++) {
+        var to_neighbor = pos_src[neighbor_i] - pos;
         to_neighbor -= step(vec2(0.5, 0.5), to_neighbor);
         to_neighbor += step(to_neighbor, vec2(-0.5, -0.5));
-        // if to_neighbor.x > 0.5 {
-        //     to_neighbor.x -= 1.0;
-        // } else if to_neighbor.x < -0.5 {
-        //     to_neighbor.x += 1.0;
-        // }
-        // if to_neighbor.y > 0.5 {
-        //     to_neighbor.y -= 1.0;
-        // } else if to_neighbor.y < -0.5 {
-        //     to_neighbor.y += 1.0;
-        // }
-        
-
-        let distance2 = dot(to_neighbor, to_neighbor);
-        if distance2 > params.local_radius2 {
-            continue;
-        }
-        if distance2 == 0.0 {
-            continue;
+        if (neighbor_i != index && dot(to_neighbor, to_neighbor) <= params.local_radius2) {
+            neighbor_n++;
         }
-        let distance = sqrt(distance2);
-        force += (to_neighbor / distance)
-            * get_attraction_force(
-                distance * (1.0 / params.local_radius),
-                attractions[species[index]*params.specie_n + species[neighbor_i]],
-            );
     }
+    return neighbor_n;
+}
+
+// for stats.wgsl, which is shared with 3d
+fn particle_pos3(index: u32) -> vec3<f32> {
+    return vec3(pos_src[index], 0.0);
+}
+fn particle_vel3(index: u32) -> vec3<f32> {
+    return vec3(vel_src[index], 0.0);
+}
 
+// shared by all the solvers
+// writes the new position and velocity of particle index given the net force on it
+fn integrate(index: u32, pos: vec2<f32>, force: vec2<f32>) {
     // scale the force to make it nicer
     // force = normalize(force) * clamp(length(force), 0.0, 10.0);
-    force *= params.force_multiplier;
+    let scaled_force = force * params.force_multiplier;
 
     // euler integration
-    var new_vel = vel_src[index] + force * params.dt;
+    var new_vel = vel_src[index] + scaled_force * params.dt;
     new_vel *= params.friction;
     var new_pos = pos + new_vel * params.dt;
 
@@ -126,41 +211,87 @@ fn main_cs(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
     vel_dst[index] = new_vel;
 }
 
-// TODO: this but without distance normalized by local_radius so i can do a convolution
-const BETA: f32 = 0.3;
-fn get_attraction_force(distance: f32, attraction: f32) -> f32 {
-    if (distance < BETA) {
-        return distance * (1.0 / BETA) - 1.0;
-    } else {
-        return attraction * (1.0 - abs(2.0 * distance - (1.0 + BETA)) / (1.0 - BETA));
+// the same as Glyph in main.rs
+const GLYPH_CIRCLE: u32 = 0u;
+const GLYPH_ARROW: u32 = 1u;
+const GLYPH_TEARDROP: u32 = 2u;
+
+// how far behind the particle the tail of its glyph reaches
+fn glyph_tail(vel: vec2<f32>) -> f32 {
+    if params.glyph == GLYPH_CIRCLE {
+        return 0.0;
     }
+    return length(vel) * params.glyph_length;
+}
+
+// the signed distances below are negative inside, with the glyph pointing along +x from the particle at the origin
+// and are from https://iquilezles.org/articles/distfunctions2d/
+
+// a triangle head as wide as the circle, from (radius, 0) to (-radius, 0), and a thin tail to (-tail, 0)
+fn arrow_distance(p: vec2<f32>, radius: f32, tail: f32) -> f32 {
+    // the isosceles triangle, with its tip at the front
+    let q = vec2(abs(p.y), radius - p.x);
+    let size = vec2(radius, 2.0 * radius);
+    let a = q - size * saturate(dot(q, size) / dot(size, size));
+    let b = q - size * vec2(saturate(q.x / size.x), 1.0);
+    let d = min(vec2(dot(a, a), size.x * q.y - size.y * q.x), vec2(dot(b, b), size.y - q.y));
+    let head = -sqrt(d.x) * sign(d.y);
+    if tail <= radius {
+        return head;
+    }
+    // the box
+    let e = abs(p - vec2(-0.5 * (tail + radius), 0.0)) - vec2(0.5 * (tail - radius), radius / 3.0);
+    let shaft = length(max(e, vec2(0.0, 0.0))) + min(max(e.x, e.y), 0.0);
+    return min(head, shaft);
+}
+
+// a circle with a tail that narrows to a point at (-tail, 0)
+fn teardrop_distance(p: vec2<f32>, radius: f32, tail: f32) -> f32 {
+    if tail <= radius {
+        return length(p) - radius;
+    }
+    // the uneven capsule, with the second radius 0 and the tail along +y
+    let q = vec2(abs(p.y), -p.x);
+    let b = radius / tail;
+    let a = sqrt(1.0 - b * b);
+    let k = dot(q, vec2(-b, a));
+    if k < 0.0 {
+        return length(q) - radius;
+    }
+    if k > a * tail {
+        return length(q - vec2(0.0, tail));
+    }
+    return dot(q, vec2(a, b)) - radius;
 }
 
-// TODO: use other rendering method
 struct VertexOutput {
-    @builtin(position) weird_pos: vec4<f32>,
+    @builtin(position) clip_pos: vec4<f32>,
+    // offset from the center of the particle, in the same units as particle_radius
+    // for glyphs other than circles, it's before rotating to the velocity
+    @location(0) corner: vec2<f32>,
     @location(1) particle_pos: vec2<f32>,
     @location(2) particle_vel: vec2<f32>,
     @location(3) particle_species: u32,
+    @location(4) color_value: f32,
 }
 
+// each particle is drawn as a quad, 4 times, see below
 @vertex
 fn main_vs(
-    @location(0) vertex_pos: vec2<f32>,
+    @builtin(vertex_index) vertex_index: u32,
     @location(1) particle_pos: vec2<f32>,
     @location(2) particle_vel: vec2<f32>,
     @location(3) particle_species: u32,
+    @location(4) color_value: f32,
 ) -> VertexOutput {
-    // let angle = -atan2(particle_vel.x, particle_vel.y);
-    // // let angle = 0.0;
-    // let rotated_veretex = vec2<f32>(
-    //     vertex_pos.x * cos(angle) - vertex_pos.y * sin(angle),
-    //     vertex_pos.x * sin(angle) + vertex_pos.y * cos(angle)
-    // );
-
     var translated_particle_pos = particle_pos - vec2(params.zoom_center_x, params.zoom_center_y);
     translated_particle_pos -= step(vec2(1.0, 1.0), translated_particle_pos);
     translated_particle_pos += step(translated_particle_pos, vec2(0.0, 0.0));
+    // vertex_index / 6 is which copy of the particle this is. the copies are shifted across the nearest wall
+    // on neither, one or both axes, so particles straddling a wall are drawn on both sides of it
+    let copy_i = vertex_index / 6u;
+    let wall_shift = select(vec2(-1.0, -1.0), vec2(1.0, 1.0), translated_particle_pos < vec2(0.5, 0.5));
+    translated_particle_pos += wall_shift * vec2(f32(copy_i & 1u), f32(copy_i >> 1u));
     translated_particle_pos *= params.zoom_scale;
     // translated_particle_pos += vec2(0.5, 0.5);
 
@@ -168,75 +299,67 @@ fn main_vs(
     // let scaled_particle_pos = (particle_pos - params.zoom_center + vec2(0.5, 0.5)) * 2.0 - vec2(1.0, 1.0);
     // let scaled_particle_pos = (particle_pos - vec2(params.zoom_center_x - 0.5, params.zoom_center_y - 0.5)) * 2.0 - vec2(1.0, 1.0);
 
-    let scaled_particle_pos = translated_particle_pos * 2.0 - vec2(1.0, 1.0);
+    let draw_radius = max(params.particle_radius, params.min_particle_radius);
+    let tail = glyph_tail(particle_vel);
+    // big enough for the smooth edge too
+    let quad_radius = max(draw_radius, tail) + params.antialias_width;
+    if any(translated_particle_pos < vec2(-quad_radius)) || any(translated_particle_pos > vec2(1.0 + quad_radius)) {
+        // this copy isn't in view, so give it no area
+        return VertexOutput(vec4(0.0, 0.0, 0.0, 1.0), vec2(0.0, 0.0), particle_pos, particle_vel, particle_species, color_value);
+    }
+
+    var corner = quad_corner(vertex_index) * quad_radius;
+    var offset = corner;
+    if params.glyph != GLYPH_CIRCLE {
+        // the quad goes from the end of the tail to the front of the head along x, then gets rotated to the velocity
+        let edge = draw_radius + params.antialias_width;
+        let unit_corner = quad_corner(vertex_index);
+        corner = vec2(
+            mix(-tail - params.antialias_width, edge, 0.5 * unit_corner.x + 0.5),
+            unit_corner.y * edge,
+        );
+        let speed = length(particle_vel);
+        let forward = select(vec2(1.0, 0.0), particle_vel / speed, speed > 0.0);
+        offset = forward * corner.x + vec2(-forward.y, forward.x) * corner.y;
+    }
+    // clip space is [-1, 1] but the view is [0, 1], so everything is scaled by 2
+    let scaled_particle_pos = (translated_particle_pos + offset) * 2.0 - vec2(1.0, 1.0);
     return VertexOutput(
-        vec4(vertex_pos + scaled_particle_pos, 0.0, 1.0),
-        particle_pos, particle_vel, particle_species);
+        vec4(scaled_particle_pos, 0.0, 1.0),
+        corner,
+        particle_pos, particle_vel, particle_species, color_value);
 }
 
 @fragment
 fn main_fs(in: VertexOutput) -> @location(0) vec4<f32> {
-    // rp is the rasterization_point
-    let rp_pos = in.weird_pos.xy; // each pixel is 1.0 units
-    // let rp_depth = in.weird_pos.z;
-    // let rp_perspective_divisor = in.weird_pos.w;
-
-    var pixel_pos = rp_pos / f32(params.texture_size);
-    pixel_pos = vec2(pixel_pos.x, 1.0 - pixel_pos.y);
-    // pixel_pos = pixel_pos - vec2(params.zoom_center_x - 0.5, params.zoom_center_y - 0.5);
-    // pixel_pos = pixel_pos - vec2(params.zoom_center_x, params.zoom_center_y);
-
-    // let particle_pos = (in.particle_pos + vec2(1.0, 1.0)) / 2.0;
-    var particle_pos = in.particle_pos - vec2(params.zoom_center_x, params.zoom_center_y);
-    particle_pos -= step(vec2(1.0, 1.0), particle_pos);
-    particle_pos += step(particle_pos, vec2(0.0, 0.0));
-    particle_pos *= params.zoom_scale;
-    // particle_pos += vec2(0.5, 0.5);
-    if particle_pos.x < 0.0 || particle_pos.x > 1.0 || particle_pos.y < 0.0 || particle_pos.y > 1.0 {
-        discard;
+    let draw_radius = max(params.particle_radius, params.min_particle_radius);
+    // particles smaller than min_particle_radius fade out by area instead of shrinking,
+    // so they don't flicker between covering a pixel and not
+    let fade = min(params.particle_radius2 / (draw_radius * draw_radius), 1.0);
+    // to the edge of the glyph, negative inside
+    var distance: f32;
+    switch params.glyph {
+        case GLYPH_ARROW: {
+            distance = arrow_distance(in.corner, draw_radius, glyph_tail(in.particle_vel));
+        }
+        case GLYPH_TEARDROP: {
+            distance = teardrop_distance(in.corner, draw_radius, glyph_tail(in.particle_vel));
+        }
+        default: {
+            distance = length(in.corner) - draw_radius;
+        }
     }
-    // let particle_pos = vec2(in.particle_pos.x, 1.0 - in.particle_pos.y);
-    // let radius = 2.0 * params.particle_radius; // *2.0 because we're in normalized device coordinates
-    let to_particle = particle_pos - pixel_pos;
-    // return turbo(length(to_particle), 0.0, 1.0);
-    // return vec4(abs(to_particle.x), abs(to_particle.y), 0.0, 1.0);
-    // return turbo(length(pixel_pos), 0.0, 1.0);
-    // return vec4(pixel_pos, 0.0, 1.0);
-    // return turbo(length(particle_pos), 0.0, 1.0);
-    // return vec4(particle_pos, 0.0, 1.0);
-    // return turbo(particle_pos.x, 0.0, 1.0);
-    // return turbo(particle_pos.y, 0.0, 1.0);
-    // return turbo(pixel_pos.x, 0.0, 1.0);
-    // return turbo(pixel_pos.y, 0.0, 1.0);
-    // it seems like a y-down / y-up problem
-
-    // TODO: antialiasing
-    if dot(to_particle, to_particle) > params.particle_radius2 {
+    var coverage = 1.0;
+    if params.antialias_width > 0.0 {
+        // the fraction of the pixel inside the glyph, approximately
+        coverage = clamp(-distance / params.antialias_width + 0.5, 0.0, 1.0);
+    } else if distance > 0.0 {
+        coverage = 0.0;
+    }
+    if coverage <= 0.0 {
         discard;
     }
-    let color = specie_colors[in.particle_species];
-    return color;
+    let color = particle_color(in.particle_species, vec3(in.particle_vel, 0.0), in.color_value);
+    return vec4(color.rgb, color.a * coverage * fade);
 }
 
-
-fn turbo(value: f32, min: f32, max: f32) -> vec4<f32> {
-    let kRedVec4: vec4<f32> = vec4(0.13572138, 4.61539260, -42.66032258, 132.13108234);
-    let kGreenVec4: vec4<f32> = vec4(0.09140261, 2.19418839, 4.84296658, -14.18503333);
-    let kBlueVec4: vec4<f32> = vec4(0.10667330, 12.64194608, -60.58204836, 110.36276771);
-    let kRedVec2: vec2<f32> = vec2(-152.94239396, 59.28637943);
-    let kGreenVec2: vec2<f32> = vec2(4.27729857, 2.82956604);
-    let kBlueVec2: vec2<f32> = vec2(-89.90310912, 27.34824973);
-
-    let x = saturate((value - min) / (max - min));
-    if abs(x) < 0.51 && abs(x) > 0.49 {
-        return vec4(1.0, 1.0, 1.0, 1.0);
-    }
-    let v4: vec4<f32> = vec4( 1.0, x, x * x, x * x * x);
-    let v2: vec2<f32> = v4.zw * v4.z;
-    return vec4(
-        dot(v4, kRedVec4)   + dot(v2, kRedVec2),
-        dot(v4, kGreenVec4) + dot(v2, kGreenVec2),
-        dot(v4, kBlueVec4)  + dot(v2, kBlueVec2),
-        1.0,
-    );
-}
\ No newline at end of file
@@ -0,0 +1,220 @@
+// the 3d version of shader.wgsl
+// positions and velocities are vec3s padded to vec4s, and space is a 3-torus
+
+struct Camera {
+    view: mat4x4<f32>,
+    proj: mat4x4<f32>,
+}
+
+@group(0) @binding(1) var<storage, read> pos_src: array<vec4<f32>>;
+@group(0) @binding(2) var<storage, read> vel_src: array<vec4<f32>>;
+@group(0) @binding(3) var<storage, read_write> pos_dst: array<vec4<f32>>;
+@group(0) @binding(4) var<storage, read_write> vel_dst: array<vec4<f32>>;
+@group(0) @binding(8) var<uniform> camera: Camera;
+
+@compute
+@workgroup_size(PARTICLES_PER_GROUP)
+fn main_cs(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let index = global_invocation_id.x;
+    if (index >= params.particle_n) {
+        return;
+    }
+
+    let pos: vec3<f32> = pos_src[index].xyz;
+    var force: vec3<f32> = vec3(0.0, 0.0, 0.0);
+
+    for (var neighbor_i: u32 = 0; neighbor_i < params.particle_n; neighbor_i++) {
+        if (neighbor_i == index) {
+            continue;
+        }
+
+        var to_neighbor = pos_src[neighbor_i].xyz - pos;
+
+        // allow to_neighber to wrap around the walls
+        to_neighbor -= step(vec3(0.5, 0.5, 0.5), to_neighbor);
+        to_neighbor += step(to_neighbor, vec3(-0.5, -0.5, -0.5));
+
+        let distance2 = dot(to_neighbor, to_neighbor);
+        if distance2 > params.local_radius2 {
+            continue;
+        }
+        if distance2 == 0.0 {
+            continue;
+        }
+        let distance = sqrt(distance2);
+        force += (to_neighbor / distance)
+            * get_attraction_force(
+                distance * (1.0 / params.local_radius),
+                attractions[species[index]*params.specie_n + species[neighbor_i]],
+            );
+    }
+
+    force *= params.force_multiplier;
+
+    // euler integration
+    var new_vel = vel_src[index].xyz + force * params.dt;
+    new_vel *= params.friction;
+    var new_pos = pos + new_vel * params.dt;
+
+    // wall wrapping
+    new_pos -= step(vec3(1.0, 1.0, 1.0), new_pos);
+    new_pos += step(new_pos, vec3(0.0, 0.0, 0.0));
+
+    pos_dst[index] = vec4(new_pos, 0.0);
+    vel_dst[index] = vec4(new_vel, 0.0);
+}
+
+// the neighbor count, net force or cluster of each particle for ColorMode, like in shader.wgsl
+@group(1) @binding(0) var<storage, read_write> color_values: array<f32>;
+// the cluster label of each particle by stable id, and the stable id of the particle in each slot
+@group(1) @binding(1) var<storage, read> cluster_labels: array<f32>;
+@group(1) @binding(2) var<storage, read> particle_ids: array<u32>;
+
+@compute
+@workgroup_size(PARTICLES_PER_GROUP)
+fn color_value_cs(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let index = global_invocation_id.x;
+    if (index >= params.particle_n) {
+        return;
+    }
+    if (params.color_mode == COLOR_MODE_CLUSTER) {
+        color_values[index] = cluster_labels[particle_ids[index]];
+        return;
+    }
+
+    let pos = pos_src[index].xyz;
+    var neighbor_n = 0u;
+    var force = vec3(0.0, 0.0, 0.0);
+    for (var neighbor_i: u32 = 0; neighbor_i < params.particle_n; neighbor_i++) {
+        var to_neighbor = pos_src[neighbor_i].xyz - pos;
+        to_neighbor -= step(vec3(0.5, 0.5, 0.5), to_neighbor);
+        to_neighbor += step(to_neighbor, vec3(-0.5, -0.5, -0.5));
+        let distance2 = dot(to_neighbor, to_neighbor);
+        if (neighbor_i == index || distance2 > params.local_radius2 || distance2 == 0.0) {
+            continue;
+        }
+        neighbor_n++;
+        let distance = sqrt(distance2);
+        force += (to_neighbor / distance)
+            * get_attraction_force(
+                distance * (1.0 / params.local_radius),
+                attractions[species[index]*params.specie_n + species[neighbor_i]],
+            );
+    }
+    color_values[index] = select(
+        f32(neighbor_n),
+        length(force * params.force_multiplier),
+        params.color_mode == COLOR_MODE_FORCE,
+    );
+}
+
+// the same as in shader.wgsl
+fn neighbor_count(index: u32) -> u32 {
+    let pos = pos_src[index].xyz;
+    var neighbor_n = 0u;
+    for (var neighbor_i: u32 = 0; neighbor_i < params.particle_n; neighbor_i++) {
+        var to_neighbor = pos_src[neighbor_i].xyz - pos;
+        to_neighbor -= step(vec3(0.5, 0.5, 0.5), to_neighbor);
+        to_neighbor += step(to_neighbor, vec3(-0.5, -0.5, -0.5));
+        if (neighbor_i != index && dot(to_neighbor, to_neighbor) <= params.local_radius2) {
+            neighbor_n++;
+        }
+    }
+    return neighbor_n;
+}
+fn particle_pos3(index: u32) -> vec3<f32> {
+    return pos_src[index].xyz;
+}
+fn particle_vel3(index: u32) -> vec3<f32> {
+    return vel_src[index].xyz;
+}
+
+// each particle is drawn as a camera facing quad with a sphere painted on it
+struct VertexOutput {
+    @builtin(position) clip_pos: vec4<f32>,
+    // position in the billboard, the sphere is the unit disk
+    @location(0) corner: vec2<f32>,
+    @location(1) view_center: vec3<f32>,
+    // the radius of the drawn sphere, which is bigger than particle_radius for particles smaller than min_particle_radius
+    @location(2) draw_radius: f32,
+    @location(3) particle_species: u32,
+    @location(4) fade: f32,
+    @location(5) particle_vel: vec3<f32>,
+    @location(6) color_value: f32,
+}
+
+@vertex
+fn main_vs(
+    @builtin(vertex_index) vertex_index: u32,
+    @location(1) particle_pos: vec4<f32>,
+    @location(2) particle_vel: vec4<f32>,
+    @location(3) particle_species: u32,
+    @location(4) color_value: f32,
+) -> VertexOutput {
+    let view_center = (camera.view * vec4(particle_pos.xyz, 1.0)).xyz;
+    // min_particle_radius is in units of the texture, so compare to the radius after projection
+    // the texture is [0, 1] but clip space is [-1, 1]
+    let projected_radius = 0.5 * params.particle_radius * camera.proj[1][1] / max(-view_center.z, 1e-6);
+    let draw_scale = max(params.min_particle_radius / projected_radius, 1.0);
+    let draw_radius = params.particle_radius * draw_scale;
+    // a bit bigger than the sphere for the smooth edge, antialias_width is a pixel in units of the texture
+    let corner = quad_corner(vertex_index) * (1.0 + params.antialias_width / (projected_radius * draw_scale));
+    // offsetting in view space makes the billboard face the camera
+    let view_corner = view_center + vec3(corner * draw_radius, 0.0);
+    return VertexOutput(
+        camera.proj * vec4(view_corner, 1.0),
+        corner,
+        view_center,
+        draw_radius,
+        particle_species,
+        // fade by area like in 2d
+        1.0 / (draw_scale * draw_scale),
+        particle_vel.xyz,
+        color_value,
+    );
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    @builtin(frag_depth) depth: f32,
+}
+
+// the particles are drawn twice, first main_fs with only the opaque fragments, which write depth,
+// then main_fs_translucent with only the ones faded by antialiasing or min_particle_pixels, which don't,
+// so a nearly transparent edge doesn't hide the particles behind it
+@fragment
+fn main_fs(in: VertexOutput) -> FragmentOutput {
+    return sphere_fs(in, false);
+}
+@fragment
+fn main_fs_translucent(in: VertexOutput) -> FragmentOutput {
+    return sphere_fs(in, true);
+}
+fn sphere_fs(in: VertexOutput, translucent: bool) -> FragmentOutput {
+    let corner_distance = length(in.corner);
+    var coverage = 1.0;
+    if params.antialias_width > 0.0 {
+        // fwidth is about the size of a pixel in units of the billboard
+        coverage = clamp((1.0 - corner_distance) / fwidth(corner_distance) + 0.5, 0.0, 1.0);
+    } else if corner_distance > 1.0 {
+        coverage = 0.0;
+    }
+    // the specie's own alpha doesn't count, those are still sorted by depth
+    if coverage <= 0.0 || (coverage * in.fade < 1.0) != translucent {
+        discard;
+    }
+
+    // view space normal of the sphere at this pixel, the camera looks down -z
+    let corner = in.corner / max(corner_distance, 1.0);
+    let normal = vec3(corner, sqrt(max(1.0 - dot(corner, corner), 0.0)));
+    let view_pos = in.view_center + normal * in.draw_radius;
+    let clip_pos = camera.proj * vec4(view_pos, 1.0);
+
+    let light_dir = normalize(vec3(0.4, 0.6, 1.0));
+    let shade = 0.3 + 0.7 * max(dot(normal, light_dir), 0.0);
+    let color = particle_color(in.particle_species, in.particle_vel, in.color_value);
+    return FragmentOutput(
+        vec4(color.rgb * shade, color.a * coverage * in.fade),
+        clip_pos.z / clip_pos.w,
+    );
+}
@@ -0,0 +1,157 @@
+use eframe::wgpu;
+
+// must match sort.wgsl
+const SORT_KEY_N: u64 = 4096;
+
+/// gpu state for sorting the particles by where they are, see sort.wgsl
+pub(crate) struct ParticleSorter {
+    key_count_buffer: wgpu::Buffer,
+    // only used on the gpu, but the bind group needs it to stay alive
+    _rank_buffer: wgpu::Buffer,
+    specie_sorted_buffer: wgpu::Buffer,
+    id_sorted_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    count_pipeline: wgpu::ComputePipeline,
+    scan_pipeline: wgpu::ComputePipeline,
+    scatter_pipeline: wgpu::ComputePipeline,
+    particles_per_group: usize,
+}
+impl ParticleSorter {
+    /// `id_buffer` and `slot_buffer` are the permutation between slots in the particle buffers and stable ids
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        shader_module: &wgpu::ShaderModule,
+        compute_bind_group_layout: &wgpu::BindGroupLayout,
+        particles_per_group: usize,
+        particle_n: usize,
+        id_buffer: &wgpu::Buffer,
+        slot_buffer: &wgpu::Buffer,
+    ) -> Self {
+        let particle_buffer = |label| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: (particle_n * size_of::<u32>()) as _,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            })
+        };
+        let key_count_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sort_key_count_buffer"),
+            size: SORT_KEY_N * size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let rank_buffer = particle_buffer("sort_rank_buffer");
+        let specie_sorted_buffer = particle_buffer("sort_specie_sorted_buffer");
+        let id_sorted_buffer = particle_buffer("sort_id_sorted_buffer");
+
+        let buffers = [
+            &key_count_buffer,
+            &rank_buffer,
+            &specie_sorted_buffer,
+            id_buffer,
+            &id_sorted_buffer,
+            slot_buffer,
+        ];
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("sort_bind_group_layout"),
+            entries: &buffers
+                .iter()
+                .enumerate()
+                .map(|(binding, buffer)| wgpu::BindGroupLayoutEntry {
+                    binding: binding as _,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(buffer.size()),
+                    },
+                    count: None,
+                })
+                .collect::<Vec<_>>(),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("sort_bind_group"),
+            layout: &bind_group_layout,
+            entries: &buffers
+                .iter()
+                .enumerate()
+                .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                    binding: binding as _,
+                    resource: buffer.as_entire_binding(),
+                })
+                .collect::<Vec<_>>(),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("sort_pipeline_layout"),
+            bind_group_layouts: &[compute_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: shader_module,
+                entry_point,
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+
+        Self {
+            count_pipeline: create_pipeline("sort_count"),
+            scan_pipeline: create_pipeline("sort_scan"),
+            scatter_pipeline: create_pipeline("sort_scatter"),
+            key_count_buffer,
+            _rank_buffer: rank_buffer,
+            specie_sorted_buffer,
+            id_sorted_buffer,
+            bind_group,
+            particles_per_group,
+        }
+    }
+
+    /// sorts from the src buffers of `compute_bind_group` into its dst buffers, like a tick does,
+    /// and sorts `specie_buffer` and `id_buffer` in place
+    pub(crate) fn dispatch(
+        &self,
+        command_encoder: &mut wgpu::CommandEncoder,
+        compute_bind_group: &wgpu::BindGroup,
+        particle_n: usize,
+        specie_buffer: &wgpu::Buffer,
+        id_buffer: &wgpu::Buffer,
+    ) {
+        command_encoder.clear_buffer(&self.key_count_buffer, 0, None);
+        {
+            let mut compute_pass =
+                command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("sort_compute_pass"),
+                    timestamp_writes: None,
+                });
+            compute_pass.set_bind_group(0, compute_bind_group, &[]);
+            compute_pass.set_bind_group(1, &self.bind_group, &[]);
+            let particle_group_count = particle_n.div_ceil(self.particles_per_group) as u32;
+            compute_pass.set_pipeline(&self.count_pipeline);
+            compute_pass.dispatch_workgroups(particle_group_count, 1, 1);
+            compute_pass.set_pipeline(&self.scan_pipeline);
+            compute_pass.dispatch_workgroups(1, 1, 1);
+            compute_pass.set_pipeline(&self.scatter_pipeline);
+            compute_pass.dispatch_workgroups(particle_group_count, 1, 1);
+        }
+        command_encoder.copy_buffer_to_buffer(
+            &self.specie_sorted_buffer,
+            0,
+            specie_buffer,
+            0,
+            specie_buffer.size(),
+        );
+        command_encoder.copy_buffer_to_buffer(
+            &self.id_sorted_buffer,
+            0,
+            id_buffer,
+            0,
+            id_buffer.size(),
+        );
+    }
+}
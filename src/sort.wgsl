@@ -0,0 +1,95 @@
+// counting sort of all the particle buffers by the morton code of their cell in a 2^SORT_GRID_BITS x 2^SORT_GRID_BITS grid,
+// appended to common.wgsl and shader.wgsl
+// sort_count counts the particles per cell, sort_scan turns the counts into offsets, and sort_scatter
+// writes each particle to the dst buffers, and to species_sorted and ids_sorted, which gfx.rs copies back
+
+const SORT_GRID_BITS: u32 = 6u;
+const SORT_KEY_N: u32 = 4096u; // 4^SORT_GRID_BITS
+const SCAN_GROUP_SIZE: u32 = 256u;
+
+// first the number of particles with each key, then the first slot of each key
+@group(1) @binding(0) var<storage, read_write> key_counts: array<atomic<u32>>;
+// ranks[index] is the order that particle was counted in within its key
+@group(1) @binding(1) var<storage, read_write> ranks: array<u32>;
+@group(1) @binding(2) var<storage, read_write> species_sorted: array<u32>;
+// ids[slot] is the stable id of the particle in that slot of the particle buffers
+@group(1) @binding(3) var<storage, read_write> ids: array<u32>;
+@group(1) @binding(4) var<storage, read_write> ids_sorted: array<u32>;
+// slots[id] is the slot of the particle with that stable id, the inverse of ids
+@group(1) @binding(5) var<storage, read_write> slots: array<u32>;
+
+// spreads the low 16 bits of x out to the even bits
+fn spread_bits(x: u32) -> u32 {
+    var v = x & 0xffffu;
+    v = (v | (v << 8u)) & 0x00ff00ffu;
+    v = (v | (v << 4u)) & 0x0f0f0f0fu;
+    v = (v | (v << 2u)) & 0x33333333u;
+    v = (v | (v << 1u)) & 0x55555555u;
+    return v;
+}
+
+fn sort_key(pos: vec2<f32>) -> u32 {
+    let side = 1u << SORT_GRID_BITS;
+    let cell = min(vec2<u32>(pos * f32(side)), vec2(side - 1u));
+    return spread_bits(cell.x) | (spread_bits(cell.y) << 1u);
+}
+
+@compute
+@workgroup_size(PARTICLES_PER_GROUP)
+fn sort_count(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let index = global_invocation_id.x;
+    if (index >= params.particle_n) {
+        return;
+    }
+    ranks[index] = atomicAdd(&key_counts[sort_key(pos_src[index])], 1u);
+}
+
+var<workgroup> scan_sums: array<u32, SCAN_GROUP_SIZE>;
+
+// exclusive prefix sum of key_counts in a single workgroup
+@compute
+@workgroup_size(SCAN_GROUP_SIZE)
+fn sort_scan(@builtin(local_invocation_index) local_index: u32) {
+    let keys_per_invocation = SORT_KEY_N / SCAN_GROUP_SIZE;
+    let first_key = local_index * keys_per_invocation;
+    var sum = 0u;
+    for (var i = 0u; i < keys_per_invocation; i++) {
+        sum += atomicLoad(&key_counts[first_key + i]);
+    }
+    scan_sums[local_index] = sum;
+    workgroupBarrier();
+
+    // hillis steele inclusive scan of the sums
+    for (var offset = 1u; offset < SCAN_GROUP_SIZE; offset *= 2u) {
+        var other = 0u;
+        if (local_index >= offset) {
+            other = scan_sums[local_index - offset];
+        }
+        workgroupBarrier();
+        scan_sums[local_index] += other;
+        workgroupBarrier();
+    }
+
+    var offset = scan_sums[local_index] - sum;
+    for (var i = 0u; i < keys_per_invocation; i++) {
+        let count = atomicLoad(&key_counts[first_key + i]);
+        atomicStore(&key_counts[first_key + i], offset);
+        offset += count;
+    }
+}
+
+@compute
+@workgroup_size(PARTICLES_PER_GROUP)
+fn sort_scatter(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let index = global_invocation_id.x;
+    if (index >= params.particle_n) {
+        return;
+    }
+    let slot = atomicLoad(&key_counts[sort_key(pos_src[index])]) + ranks[index];
+    pos_dst[slot] = pos_src[index];
+    vel_dst[slot] = vel_src[index];
+    species_sorted[slot] = species[index];
+    let id = ids[index];
+    ids_sorted[slot] = id;
+    slots[id] = slot;
+}
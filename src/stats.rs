@@ -0,0 +1,413 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use eframe::{egui, wgpu};
+
+// the layout of a record in stats.wgsl
+const GLOBAL_N: usize = 7;
+const PER_SPECIE: usize = 7;
+// how many readings the plots go back
+const HISTORY_LEN: usize = 600;
+
+/// one reading of the statistics panel, with every particle's mass as 1
+#[derive(Clone, Debug)]
+pub(crate) struct Stats {
+    // sim time in seconds
+    pub(crate) time: f64,
+    pub(crate) mean_speed: f32,
+    pub(crate) kinetic_energy: f32,
+    // the sum of the velocities, which isn't conserved because the attractions aren't symmetric
+    pub(crate) momentum: [f32; 3],
+    pub(crate) mean_neighbor_n: f32,
+    // roughly the rms distance of each specie's particles from its centroid, in box widths
+    pub(crate) centroid_spreads: Vec<f32>,
+}
+impl Stats {
+    /// `sums` is stats_sums from stats.wgsl
+    fn from_sums(time: f64, sums: &[f32], particle_n: usize) -> Self {
+        let particle_n = particle_n as f32;
+        let centroid_spreads = sums[GLOBAL_N..]
+            .chunks_exact(PER_SPECIE)
+            .map(|specie_sums| {
+                let count = specie_sums[0];
+                if count == 0.0 {
+                    return 0.0;
+                }
+                // the spread of a wrapped normal distribution with the same mean resultant length on each axis
+                specie_sums[1..]
+                    .chunks_exact(2)
+                    .map(|cos_sin| {
+                        let resultant = (cos_sin[0].hypot(cos_sin[1]) / count).clamp(1e-6, 1.0);
+                        -2.0 * resultant.ln() / std::f32::consts::TAU.powi(2)
+                    })
+                    .sum::<f32>()
+                    .sqrt()
+            })
+            .collect();
+        Self {
+            time,
+            mean_speed: sums[0] / particle_n,
+            kinetic_energy: 0.5 * sums[1],
+            momentum: [sums[2], sums[3], sums[4]],
+            // over the particles it was counted for
+            mean_neighbor_n: sums[5] / sums[6].max(1.0),
+            centroid_spreads,
+        }
+    }
+}
+
+/// gpu state for the statistics, see stats.wgsl
+/// only stats_sums is read back, without blocking like GpuTimer, and it's only dispatched when that's free
+pub(crate) struct StatsReducer {
+    bind_group: wgpu::BindGroup,
+    stats_pipeline: wgpu::ComputePipeline,
+    reduce_pipeline: wgpu::ComputePipeline,
+    sums_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    particles_per_group: usize,
+    record_len: usize,
+    // the sim time of the dispatch that's being read back
+    time: f64,
+    // whether dispatch() copied into readback_buffer this frame, so start_readback() should map it
+    copied: bool,
+    // true from mapping readback_buffer until it's unmapped, since it can't be copied to while mapped
+    readback_busy: bool,
+    readback_ready: Arc<AtomicBool>,
+}
+impl StatsReducer {
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        shader_module: &wgpu::ShaderModule,
+        compute_bind_group_layout: &wgpu::BindGroupLayout,
+        particles_per_group: usize,
+        specie_n: usize,
+        particle_n: usize,
+    ) -> Self {
+        let record_len = GLOBAL_N + PER_SPECIE * specie_n;
+        let record_size = (record_len * size_of::<f32>()) as u64;
+        let partials_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("stats_partials_buffer"),
+            size: particle_n.div_ceil(particles_per_group) as u64 * record_size,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let sums_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("stats_sums_buffer"),
+            size: record_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("stats_readback_buffer"),
+            size: record_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let buffer_entry = |binding, buffer: &wgpu::Buffer| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: wgpu::BufferSize::new(buffer.size()),
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("stats_bind_group_layout"),
+            entries: &[
+                // partials_buffer
+                buffer_entry(0, &partials_buffer),
+                // sums_buffer
+                buffer_entry(1, &sums_buffer),
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("stats_bind_group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: partials_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: sums_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("stats_pipeline_layout"),
+            bind_group_layouts: &[compute_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: shader_module,
+                entry_point,
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+
+        Self {
+            bind_group,
+            stats_pipeline: create_pipeline("stats_cs"),
+            reduce_pipeline: create_pipeline("stats_reduce_cs"),
+            sums_buffer,
+            readback_buffer,
+            particles_per_group,
+            record_len,
+            time: 0.0,
+            copied: false,
+            readback_busy: false,
+            readback_ready: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// sums up the particles in `compute_bind_group`, unless the last sums are still being read back
+    /// `time` is the sim time they're at
+    pub(crate) fn dispatch(
+        &mut self,
+        command_encoder: &mut wgpu::CommandEncoder,
+        compute_bind_group: &wgpu::BindGroup,
+        particle_n: usize,
+        time: f64,
+    ) {
+        if self.readback_busy {
+            return;
+        }
+        {
+            let mut compute_pass =
+                command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("stats_compute_pass"),
+                    timestamp_writes: None,
+                });
+            compute_pass.set_bind_group(0, compute_bind_group, &[]);
+            compute_pass.set_bind_group(1, &self.bind_group, &[]);
+            compute_pass.set_pipeline(&self.stats_pipeline);
+            compute_pass.dispatch_workgroups(
+                particle_n.div_ceil(self.particles_per_group) as _,
+                1,
+                1,
+            );
+            compute_pass.set_pipeline(&self.reduce_pipeline);
+            compute_pass.dispatch_workgroups(
+                self.record_len.div_ceil(self.particles_per_group) as _,
+                1,
+                1,
+            );
+        }
+        command_encoder.copy_buffer_to_buffer(
+            &self.sums_buffer,
+            0,
+            &self.readback_buffer,
+            0,
+            self.sums_buffer.size(),
+        );
+        self.time = time;
+        self.copied = true;
+    }
+
+    /// call after submitting the command buffer that dispatch() was recorded into
+    pub(crate) fn start_readback(&mut self) {
+        if !self.copied {
+            return;
+        }
+        self.copied = false;
+        self.readback_busy = true;
+        let readback_ready = self.readback_ready.clone();
+        self.readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                result.unwrap();
+                readback_ready.store(true, Ordering::Release);
+            });
+    }
+
+    /// the last stats that finished reading back, if some have since the last call
+    pub(crate) fn take_stats(&mut self, device: &wgpu::Device, particle_n: usize) -> Option<Stats> {
+        device.poll(wgpu::Maintain::Poll);
+        if !self.readback_ready.swap(false, Ordering::Acquire) {
+            return None;
+        }
+        let sums: Vec<f32> =
+            bytemuck::cast_slice(&self.readback_buffer.slice(..).get_mapped_range()).to_vec();
+        self.readback_buffer.unmap();
+        self.readback_busy = false;
+        Some(Stats::from_sums(self.time, &sums, particle_n))
+    }
+}
+
+/// the last HISTORY_LEN readings, and the plots of them
+pub(crate) struct StatsHistory {
+    readings: VecDeque<Stats>,
+}
+impl StatsHistory {
+    pub(crate) fn new() -> Self {
+        Self {
+            readings: VecDeque::with_capacity(HISTORY_LEN),
+        }
+    }
+
+    pub(crate) fn push(&mut self, stats: Stats) {
+        if self.readings.len() == HISTORY_LEN {
+            self.readings.pop_front();
+        }
+        self.readings.push_back(stats);
+    }
+
+    /// a plot for each statistic, with a line per specie for the centroid spreads
+    /// `dimension` is how many momentum components to plot
+    pub(crate) fn ui(&mut self, ui: &mut egui::Ui, specie_colors: &[egui::Rgba], dimension: usize) {
+        let Some(last) = self.readings.back() else {
+            ui.label("waiting for the first reading");
+            return;
+        };
+        ui.label(format!(
+            "t = {:.2} s, {} readings",
+            last.time,
+            self.readings.len()
+        ));
+        let series = |value: fn(&Stats) -> f32| self.readings.iter().map(value).collect::<Vec<_>>();
+        plot(
+            ui,
+            "mean_speed",
+            HISTORY_LEN,
+            &[(egui::Color32::WHITE, series(|stats| stats.mean_speed))],
+        );
+        plot(
+            ui,
+            "kinetic_energy",
+            HISTORY_LEN,
+            &[(egui::Color32::WHITE, series(|stats| stats.kinetic_energy))],
+        );
+        plot(
+            ui,
+            "mean_neighbor_n",
+            HISTORY_LEN,
+            &[(egui::Color32::WHITE, series(|stats| stats.mean_neighbor_n))],
+        );
+        let axis_colors = [
+            egui::Color32::LIGHT_RED,
+            egui::Color32::LIGHT_GREEN,
+            egui::Color32::LIGHT_BLUE,
+        ];
+        plot(
+            ui,
+            "momentum (x, y, z)",
+            HISTORY_LEN,
+            &(0..dimension)
+                .map(|axis| {
+                    (
+                        axis_colors[axis],
+                        self.readings
+                            .iter()
+                            .map(|stats| stats.momentum[axis])
+                            .collect(),
+                    )
+                })
+                .collect::<Vec<_>>(),
+        );
+        plot(
+            ui,
+            "centroid_spread",
+            HISTORY_LEN,
+            &specie_colors
+                .iter()
+                .enumerate()
+                .map(|(specie_i, &specie_color)| {
+                    (
+                        specie_color.into(),
+                        self.readings
+                            .iter()
+                            .map(|stats| stats.centroid_spreads[specie_i])
+                            .collect(),
+                    )
+                })
+                .collect::<Vec<_>>(),
+        );
+        if ui.button("clear").clicked() {
+            self.readings.clear();
+        }
+    }
+}
+
+/// lines scaled to fit, with the range and the last values in the label
+/// the values are spread evenly across the width, which fits `x_n` of them
+pub(crate) fn plot(
+    ui: &mut egui::Ui,
+    name: &str,
+    x_n: usize,
+    series: &[(egui::Color32, Vec<f32>)],
+) {
+    let (min, max) = series
+        .iter()
+        .flat_map(|(_, values)| values)
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &value| {
+            (min.min(value), max.max(value))
+        });
+    let last_values = series
+        .iter()
+        .filter_map(|(_, values)| values.last())
+        .map(|value| format!("{value:.4}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    ui.label(format!("{name}: {last_values}"));
+
+    let (rect, _) = ui.allocate_exact_size(
+        egui::Vec2::new(ui.available_width().max(200.0), 60.0),
+        egui::Sense::hover(),
+    );
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, egui::Color32::from_black_alpha(160));
+    // so flat lines are in the middle instead of dividing by 0
+    let (min, max) = if max - min > f32::EPSILON * max.abs().max(1.0) {
+        (min, max)
+    } else {
+        (min - 0.5, max + 0.5)
+    };
+    let to_y = |value: f32| egui::remap(value, min..=max, rect.bottom()..=rect.top());
+    if min < 0.0 && max > 0.0 {
+        painter.hline(
+            rect.x_range(),
+            to_y(0.0),
+            egui::Stroke::new(1.0, egui::Color32::from_white_alpha(40)),
+        );
+    }
+    for (color, values) in series {
+        let points = values
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| {
+                let x = rect.left() + rect.width() * i as f32 / (x_n - 1).max(1) as f32;
+                egui::Pos2::new(x, to_y(value))
+            })
+            .collect::<Vec<_>>();
+        painter.add(egui::Shape::line(points, egui::Stroke::new(1.0, *color)));
+    }
+    let range_font = egui::FontId::monospace(10.0);
+    painter.text(
+        rect.left_top(),
+        egui::Align2::LEFT_TOP,
+        format!("{max:.3}"),
+        range_font.clone(),
+        egui::Color32::GRAY,
+    );
+    painter.text(
+        rect.left_bottom(),
+        egui::Align2::LEFT_BOTTOM,
+        format!("{min:.3}"),
+        range_font,
+        egui::Color32::GRAY,
+    );
+}
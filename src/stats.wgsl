@@ -0,0 +1,126 @@
+// sums for the statistics panel, see stats.rs
+// appended to both the 2d and 3d modules, which give it particle_pos3, particle_vel3 and neighbor_count
+// stats_cs sums each workgroup's particles into a record in stats_partials,
+// then stats_reduce_cs sums the records into stats_sums, which is the only thing that gets read back
+
+// the sums at the start of each record, of |vel|, |vel|^2, vel.xyz, then the neighbor count and how many particles it was counted for
+const STATS_SPEED: u32 = 0u;
+const STATS_SPEED2: u32 = 1u;
+const STATS_MOMENTUM: u32 = 2u;
+const STATS_NEIGHBOR_N: u32 = 5u;
+const STATS_NEIGHBOR_SAMPLE_N: u32 = 6u;
+const STATS_GLOBAL_N: u32 = 7u;
+// the neighbor count is pairwise, so it's only counted for about this many particles spread evenly through the buffers,
+// which keeps this O(particle_n), and the sort keeps them spread out in space too
+const NEIGHBOR_SAMPLE_N: u32 = 256u;
+// then for each specie, the particle count and the sums of the cos and sin of the angle around each axis of the torus,
+// which give a centroid and a spread that don't jump when a cluster wraps around a wall
+const STATS_PER_SPECIE: u32 = 7u;
+
+@group(1) @binding(0) var<storage, read_write> stats_partials: array<f32>;
+@group(1) @binding(1) var<storage, read_write> stats_sums: array<f32>;
+
+var<workgroup> stats_scratch: array<f32, PARTICLES_PER_GROUP>;
+
+fn stats_record_len() -> u32 {
+    return STATS_GLOBAL_N + STATS_PER_SPECIE * params.specie_n;
+}
+
+// the sum of value over the workgroup, which every invocation has to call
+fn workgroup_sum(value: f32, local_index: u32) -> f32 {
+    stats_scratch[local_index] = value;
+    workgroupBarrier();
+    // starting from half the next power of 2, so PARTICLES_PER_GROUP doesn't have to be one
+    var stride = 0u;
+    if (PARTICLES_PER_GROUP > 1u) {
+        stride = 1u << firstLeadingBit(PARTICLES_PER_GROUP - 1u);
+    }
+    for (; stride > 0u; stride >>= 1u) {
+        if (local_index < stride && local_index + stride < PARTICLES_PER_GROUP) {
+            stats_scratch[local_index] += stats_scratch[local_index + stride];
+        }
+        workgroupBarrier();
+    }
+    let sum = stats_scratch[0];
+    // so the next call doesn't overwrite it before everyone has read it
+    workgroupBarrier();
+    return sum;
+}
+
+@compute
+@workgroup_size(PARTICLES_PER_GROUP)
+fn stats_cs(
+    @builtin(global_invocation_id) global_invocation_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+) {
+    let index = global_invocation_id.x;
+    // invocations past the end add zeros, because every invocation has to reach the barriers
+    // max so it doesn't underflow with no particles
+    let clamped_index = min(index, max(params.particle_n, 1u) - 1u);
+    let weight = select(0.0, 1.0, index < params.particle_n);
+    let record_start = workgroup_id.x * stats_record_len();
+
+    let vel = particle_vel3(clamped_index);
+    let sample_stride = max(params.particle_n / NEIGHBOR_SAMPLE_N, 1u);
+    var neighbor_n = 0.0;
+    var neighbor_sample = 0.0;
+    if (index < params.particle_n && index % sample_stride == 0u) {
+        neighbor_n = f32(neighbor_count(index));
+        neighbor_sample = 1.0;
+    }
+    var values = array<f32, STATS_GLOBAL_N>(
+        length(vel),
+        dot(vel, vel),
+        vel.x,
+        vel.y,
+        vel.z,
+        neighbor_n,
+        neighbor_sample,
+    );
+    for (var value_i = 0u; value_i < STATS_GLOBAL_N; value_i++) {
+        let sum = workgroup_sum(weight * values[value_i], local_index);
+        if (local_index == 0u) {
+            stats_partials[record_start + value_i] = sum;
+        }
+    }
+
+    let angle = particle_pos3(clamped_index) * 6.2831855;
+    var specie_values = array<f32, STATS_PER_SPECIE>(
+        1.0,
+        cos(angle.x),
+        sin(angle.x),
+        cos(angle.y),
+        sin(angle.y),
+        cos(angle.z),
+        sin(angle.z),
+    );
+    let specie = species[clamped_index];
+    for (var specie_i = 0u; specie_i < params.specie_n; specie_i++) {
+        let specie_weight = select(0.0, weight, specie == specie_i);
+        let specie_start = record_start + STATS_GLOBAL_N + specie_i * STATS_PER_SPECIE;
+        for (var value_i = 0u; value_i < STATS_PER_SPECIE; value_i++) {
+            let sum = workgroup_sum(specie_weight * specie_values[value_i], local_index);
+            if (local_index == 0u) {
+                stats_partials[specie_start + value_i] = sum;
+            }
+        }
+    }
+}
+
+// one invocation per value in a record, summing it over the workgroups of stats_cs
+@compute
+@workgroup_size(PARTICLES_PER_GROUP)
+fn stats_reduce_cs(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let value_i = global_invocation_id.x;
+    let record_len = stats_record_len();
+    if (value_i >= record_len) {
+        return;
+    }
+    let group_n = (params.particle_n + PARTICLES_PER_GROUP - 1u) / PARTICLES_PER_GROUP;
+    var sum = 0.0;
+    for (var group_i = 0u; group_i < group_n; group_i++) {
+        sum += stats_partials[group_i * record_len + value_i];
+    }
+    stats_sums[value_i] = sum;
+}
@@ -0,0 +1,432 @@
+use std::io::Write;
+
+use eframe::egui;
+
+use crate::{cell_grid::CellGrid, stats, SimData, SimSettings};
+
+// the fourier modes cost a complex multiply per particle per k, so k_n is limited to keep a snapshot
+// under about this many, which is around a tenth of a second
+const MAX_PHASE_PRODUCT_N: usize = 1 << 25;
+
+/// the biggest k_n a snapshot of `particle_n` particles can afford, at least 1
+/// the k in half_lattice() grow like k_n^2 in 2d and k_n^3 in 3d
+pub(crate) fn max_k_n(dimension: usize, particle_n: usize) -> usize {
+    let max_k_count = MAX_PHASE_PRODUCT_N as f64 / particle_n.max(1) as f64;
+    // about half of the disk or ball of radius k_n + 0.5, with some room for the lattice points on its edge
+    let k_n = match dimension {
+        2 => (max_k_count / std::f64::consts::FRAC_PI_2).sqrt(),
+        _ => (max_k_count / (2.0 / 3.0 * std::f64::consts::PI)).cbrt(),
+    } - 1.0;
+    (k_n as usize).clamp(1, 64)
+}
+
+/// radial distribution functions g_ij(r) out to local_radius and structure factors S_ij(k) for each pair of species,
+/// averaged over snapshots of the particles on the cpu
+/// all the distances go the short way around the walls, and the box is 1 wide so the volume is 1
+pub(crate) struct Structure {
+    dimension: usize,
+    specie_n: usize,
+    local_radius: f32,
+    // the rdf bins split 0..local_radius
+    bin_n: usize,
+    // the structure factor is at |k| = 2π, 4π, ... 2π k_n, for the k that fit the periodic box
+    k_n: usize,
+    // particles of each specie, which is the same in every snapshot
+    specie_counts: Vec<usize>,
+    // pair_counts[(a * specie_n + b) * bin_n + bin] is the pairs of an a and a b in that bin, both ways around,
+    // summed over the snapshots
+    pair_counts: Vec<u64>,
+    // density_products[(a * specie_n + b) * k_n + shell] is the sum of Re(rho_a(k) conj(rho_b(k)))
+    // over the k in that shell and the snapshots, where rho_a(k) is the sum of exp(-i k.x) over the a particles
+    density_products: Vec<f64>,
+    // how many k are in each shell, only counting one of k and -k since they're the same
+    shell_counts: Vec<usize>,
+    pub(crate) snapshot_n: usize,
+    // the specie whose pairs are plotted
+    plotted_specie: usize,
+    // where the last export went, or why it failed
+    export_status: Option<String>,
+}
+impl Structure {
+    /// `dimension` is 2 or 3
+    pub(crate) fn new(
+        specie_n: usize,
+        dimension: usize,
+        local_radius: f32,
+        bin_n: usize,
+        k_n: usize,
+    ) -> Self {
+        let mut shell_counts = vec![0; k_n];
+        for n in half_lattice(dimension, k_n) {
+            shell_counts[shell_of(n, k_n).unwrap()] += 1;
+        }
+        Self {
+            dimension,
+            specie_n,
+            local_radius,
+            bin_n,
+            k_n,
+            specie_counts: vec![0; specie_n],
+            pair_counts: vec![0; specie_n * specie_n * bin_n],
+            density_products: vec![0.0; specie_n * specie_n * k_n],
+            shell_counts,
+            snapshot_n: 0,
+            plotted_specie: 0,
+            export_status: None,
+        }
+    }
+
+    /// whether it was made with these settings, so snapshots with them can be added
+    pub(crate) fn matches(&self, local_radius: f32, bin_n: usize, k_n: usize) -> bool {
+        self.local_radius == local_radius && self.bin_n == bin_n && self.k_n == k_n
+    }
+
+    /// `sim_data` is from GfxData::read_sim_data()
+    pub(crate) fn add_snapshot(&mut self, sim_data: &SimData<[f32; 3]>) {
+        let (specie_n, bin_n, k_n) = (self.specie_n, self.bin_n, self.k_n);
+        self.specie_counts = vec![0; specie_n];
+        for &specie in &sim_data.species {
+            self.specie_counts[specie as usize] += 1;
+        }
+
+        let bin_width = self.local_radius / bin_n as f32;
+        CellGrid::new(&sim_data.poses, self.dimension, self.local_radius).for_each_pair(
+            &sim_data.poses,
+            |particle_i, other_i, distance2| {
+                let bin = ((distance2.sqrt() / bin_width) as usize).min(bin_n - 1);
+                let (a, b) = (
+                    sim_data.species[particle_i] as usize,
+                    sim_data.species[other_i] as usize,
+                );
+                self.pair_counts[(a * specie_n + b) * bin_n + bin] += 1;
+                self.pair_counts[(b * specie_n + a) * bin_n + bin] += 1;
+            },
+        );
+
+        // rho[a * k_count + k_i] for the k in half_lattice() order
+        let lattice = half_lattice(self.dimension, k_n).collect::<Vec<_>>();
+        let mut rho = vec![[0.0_f64; 2]; specie_n * lattice.len()];
+        let k_range = 2 * k_n + 1;
+        // phases[axis * k_range + k_n + m] is exp(-i 2π m x) for m in -k_n..=k_n
+        let mut phases = vec![[0.0_f64; 2]; 3 * k_range];
+        for (pos, &specie) in sim_data.poses.iter().zip(&sim_data.species) {
+            for axis in 0..3 {
+                let angle = -std::f64::consts::TAU * pos[axis] as f64;
+                let step = [angle.cos(), angle.sin()];
+                let row = &mut phases[axis * k_range..(axis + 1) * k_range];
+                row[k_n] = [1.0, 0.0];
+                for m in 1..=k_n {
+                    row[k_n + m] = complex_mul(row[k_n + m - 1], step);
+                    row[k_n - m] = [row[k_n + m][0], -row[k_n + m][1]];
+                }
+            }
+            let specie_rho = &mut rho[specie as usize * lattice.len()..][..lattice.len()];
+            for (k_rho, n) in specie_rho.iter_mut().zip(&lattice) {
+                let phase = (0..3).fold([1.0, 0.0], |phase, axis| {
+                    complex_mul(
+                        phase,
+                        phases[axis * k_range + (k_n as isize + n[axis]) as usize],
+                    )
+                });
+                k_rho[0] += phase[0];
+                k_rho[1] += phase[1];
+            }
+        }
+        for (k_i, &n) in lattice.iter().enumerate() {
+            let shell = shell_of(n, k_n).unwrap();
+            for a in 0..specie_n {
+                for b in 0..specie_n {
+                    let [ar, ai] = rho[a * lattice.len() + k_i];
+                    let [br, bi] = rho[b * lattice.len() + k_i];
+                    self.density_products[(a * specie_n + b) * k_n + shell] += ar * br + ai * bi;
+                }
+            }
+        }
+        self.snapshot_n += 1;
+    }
+
+    /// g_ab for each bin, which is 1.0 if the b particles around an a particle are as dense as the average
+    fn rdf(&self, a: usize, b: usize) -> Vec<f32> {
+        let bin_width = self.local_radius / self.bin_n as f32;
+        let pairs = self.specie_counts[a] as f32
+            * (self.specie_counts[b] as f32 - if a == b { 1.0 } else { 0.0 })
+            * self.snapshot_n as f32;
+        (0..self.bin_n)
+            .map(|bin| {
+                let (r0, r1) = (bin as f32 * bin_width, (bin + 1) as f32 * bin_width);
+                let shell_volume = match self.dimension {
+                    2 => std::f32::consts::PI * (r1.powi(2) - r0.powi(2)),
+                    _ => 4.0 / 3.0 * std::f32::consts::PI * (r1.powi(3) - r0.powi(3)),
+                };
+                let count = self.pair_counts[(a * self.specie_n + b) * self.bin_n + bin];
+                if pairs > 0.0 {
+                    count as f32 / (pairs * shell_volume)
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+
+    /// S_ab for each shell, normalized by sqrt(N_a N_b) so S_aa is 1.0 for particles placed at random
+    /// with `b` as None it's the total S(k) of all the particles together, normalized by N
+    fn structure_factor(&self, a: usize, b: Option<usize>) -> Vec<f32> {
+        (0..self.k_n)
+            .map(|shell| {
+                let (sum, norm) = match b {
+                    Some(b) => (
+                        self.density_products[(a * self.specie_n + b) * self.k_n + shell],
+                        (self.specie_counts[a] as f64 * self.specie_counts[b] as f64).sqrt(),
+                    ),
+                    None => (
+                        (0..self.specie_n * self.specie_n)
+                            .map(|pair| self.density_products[pair * self.k_n + shell])
+                            .sum(),
+                        self.specie_counts.iter().sum::<usize>() as f64,
+                    ),
+                };
+                let k_count = (self.shell_counts[shell] * self.snapshot_n) as f64;
+                if norm > 0.0 && k_count > 0.0 {
+                    (sum / (k_count * norm)) as f32
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+
+    /// plots of g_ab(r) and S_ab(k) for plotted_specie as a and every b, and the export button
+    pub(crate) fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        specie_colors: &[egui::Rgba],
+        sim_settings: &SimSettings,
+    ) {
+        ui.label(format!("averaged over {} snapshots", self.snapshot_n));
+        ui.horizontal(|ui| {
+            ui.label("pairs of");
+            for (specie_i, &specie_color) in specie_colors.iter().enumerate() {
+                let button = egui::Button::new("")
+                    .min_size(ui.spacing().interact_size)
+                    .fill(specie_color)
+                    .selected(specie_i == self.plotted_specie);
+                if ui.add(button).clicked() {
+                    self.plotted_specie = specie_i;
+                }
+            }
+        });
+        let a = self.plotted_specie;
+        stats::plot(
+            ui,
+            &format!("g(r), r from 0 to {}", self.local_radius),
+            self.bin_n,
+            &specie_colors
+                .iter()
+                .enumerate()
+                .map(|(b, &specie_color)| (specie_color.into(), self.rdf(a, b)))
+                .collect::<Vec<_>>(),
+        );
+        stats::plot(
+            ui,
+            &format!("S(k), |k| from 2π to {}π", 2 * self.k_n),
+            self.k_n,
+            &std::iter::once((egui::Color32::WHITE, self.structure_factor(a, None)))
+                .chain(specie_colors.iter().enumerate().map(|(b, &specie_color)| {
+                    (specie_color.into(), self.structure_factor(a, Some(b)))
+                }))
+                .collect::<Vec<_>>(),
+        );
+        ui.label("white is the total S(k) of all the species");
+        if ui.button("export csv").clicked() {
+            self.export_status = Some(match self.write_csvs(sim_settings) {
+                Ok(paths) => format!("saved {paths}"),
+                Err(error) => format!("couldn't export: {error}"),
+            });
+        }
+        if let Some(export_status) = &self.export_status {
+            ui.label(export_status);
+        }
+    }
+
+    /// writes every pair's g(r) and S(k) to rdf_1700000000.csv and structure_factor_1700000000.csv,
+    /// each with the sim_settings as json on a `# ` comment line first
+    /// returns the paths
+    fn write_csvs(&self, sim_settings: &SimSettings) -> std::io::Result<String> {
+        let metadata = serde_json::to_string(&serde_json::json!({
+            "sim_settings": sim_settings,
+            "snapshot_n": self.snapshot_n,
+        }))?;
+        let pairs = (0..self.specie_n)
+            .flat_map(|a| (0..self.specie_n).map(move |b| (a, b)))
+            .collect::<Vec<_>>();
+        let header = pairs
+            .iter()
+            .map(|(a, b)| format!("{a}_{b}"))
+            .collect::<Vec<_>>();
+
+        let rdf_path = crate::export::timestamped_path("rdf", "csv");
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&rdf_path)?);
+        writeln!(file, "# {metadata}")?;
+        writeln!(file, "r,g_{}", header.join(",g_"))?;
+        let rdfs = pairs
+            .iter()
+            .map(|&(a, b)| self.rdf(a, b))
+            .collect::<Vec<_>>();
+        let bin_width = self.local_radius / self.bin_n as f32;
+        for bin in 0..self.bin_n {
+            // the middle of the bin
+            write!(file, "{}", (bin as f32 + 0.5) * bin_width)?;
+            for rdf in &rdfs {
+                write!(file, ",{}", rdf[bin])?;
+            }
+            writeln!(file)?;
+        }
+        file.flush()?;
+
+        let structure_factor_path = crate::export::timestamped_path("structure_factor", "csv");
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&structure_factor_path)?);
+        writeln!(file, "# {metadata}")?;
+        writeln!(file, "k,s_total,s_{}", header.join(",s_"))?;
+        let structure_factors = std::iter::once(self.structure_factor(0, None))
+            .chain(
+                pairs
+                    .iter()
+                    .map(|&(a, b)| self.structure_factor(a, Some(b))),
+            )
+            .collect::<Vec<_>>();
+        for shell in 0..self.k_n {
+            write!(file, "{}", std::f32::consts::TAU * (shell + 1) as f32)?;
+            for structure_factor in &structure_factors {
+                write!(file, ",{}", structure_factor[shell])?;
+            }
+            writeln!(file)?;
+        }
+        file.flush()?;
+
+        Ok(format!(
+            "{} and {}",
+            rdf_path.display(),
+            structure_factor_path.display()
+        ))
+    }
+}
+
+/// the integer vectors n with 0 < |n| < k_n + 0.5, where k = 2π n fits the box,
+/// leaving out -n for each n since it gives the conjugate density
+fn half_lattice(dimension: usize, k_n: usize) -> impl Iterator<Item = [isize; 3]> {
+    let k_n = k_n as isize;
+    let z_range = if dimension == 3 { -k_n..=k_n } else { 0..=0 };
+    z_range
+        .flat_map(move |z| (-k_n..=k_n).flat_map(move |y| (-k_n..=k_n).map(move |x| [x, y, z])))
+        .filter(move |&n| {
+            // the last nonzero component is positive
+            let first = n.into_iter().rev().find(|&component| component != 0);
+            first.is_some_and(|first| first > 0) && shell_of(n, k_n as usize).is_some()
+        })
+}
+
+/// the shell that |n| rounds to, where shell s is |n| near s + 1
+fn shell_of(n: [isize; 3], k_n: usize) -> Option<usize> {
+    let length = (n
+        .iter()
+        .map(|&component| component * component)
+        .sum::<isize>() as f64)
+        .sqrt();
+    let rounded = length.round() as usize;
+    (1..=k_n).contains(&rounded).then(|| rounded - 1)
+}
+
+fn complex_mul(a: [f64; 2], b: [f64; 2]) -> [f64; 2] {
+    [a[0] * b[0] - a[1] * b[1], a[0] * b[1] + a[1] * b[0]]
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::{half_lattice, max_k_n, shell_of, Structure, MAX_PHASE_PRODUCT_N};
+    use crate::SimData;
+
+    // particles placed uniformly at random, half of each specie
+    fn random_structure(dimension: usize, k_n: usize) -> Structure {
+        let mut rng = StdRng::seed_from_u64(0);
+        let particle_n = 1000;
+        let mut structure = Structure::new(2, dimension, 0.2, 4, k_n);
+        for _ in 0..50 {
+            structure.add_snapshot(&SimData {
+                poses: (0..particle_n)
+                    .map(|_| {
+                        std::array::from_fn(|axis| {
+                            if axis < dimension {
+                                rng.gen_range(0.0..1.0)
+                            } else {
+                                0.0
+                            }
+                        })
+                    })
+                    .collect(),
+                vels: vec![[0.0; 3]; particle_n],
+                species: (0..particle_n).map(|i| (i % 2) as u32).collect(),
+            });
+        }
+        structure
+    }
+
+    #[test]
+    fn test_random_particles_are_uniform() {
+        for (dimension, k_n) in [(2, 8), (3, 4)] {
+            let structure = random_structure(dimension, k_n);
+            for a in 0..2 {
+                for b in 0..2 {
+                    for g in structure.rdf(a, b) {
+                        assert!((g - 1.0).abs() < 0.05, "{dimension}d g_{a}{b} {g}");
+                    }
+                    // species placed independently of each other aren't correlated at all
+                    let expected = if a == b { 1.0 } else { 0.0 };
+                    for s in structure.structure_factor(a, Some(b)) {
+                        assert!((s - expected).abs() < 0.3, "{dimension}d S_{a}{b} {s}");
+                    }
+                }
+            }
+            for s in structure.structure_factor(0, None) {
+                assert!((s - 1.0).abs() < 0.3, "{dimension}d S {s}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_half_lattice_has_one_of_each_pair() {
+        for (dimension, k_n) in [(2, 5), (3, 3)] {
+            let lattice = half_lattice(dimension, k_n).collect::<Vec<_>>();
+            let z_range = if dimension == 3 { -3..=3 } else { 0..=0 };
+            for z in z_range {
+                for y in -5..=5isize {
+                    for x in -5..=5isize {
+                        let n = [x, y, z];
+                        let in_range = n != [0; 3] && shell_of(n, k_n).is_some();
+                        let count = [n, n.map(|component| -component)]
+                            .iter()
+                            .filter(|n| lattice.contains(n))
+                            .count();
+                        assert_eq!(count, usize::from(in_range), "{n:?}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_max_k_n_fits_the_budget() {
+        for dimension in [2, 3] {
+            for particle_n in [1000, 3000, 10_000, 30_000, 100_000, 300_000, 1_000_000] {
+                let k_n = max_k_n(dimension, particle_n);
+                if k_n > 1 {
+                    assert!(
+                        half_lattice(dimension, k_n).count() * particle_n <= MAX_PHASE_PRODUCT_N,
+                        "{dimension}d particle_n {particle_n} k_n {k_n}"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,633 @@
+use std::sync::Arc;
+
+use eframe::{
+    egui, egui_wgpu,
+    wgpu::{self, util::DeviceExt},
+};
+
+use crate::{
+    gfx::{self, ShaderParams},
+    SimData, SimSettings, SweepSettings, ViewSettings,
+};
+
+/// a setting that varies along an axis of the sweep grid
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SweepParam {
+    LocalRadius,
+    FrictionHalfLife,
+    // multiplies every attraction, but not the repulsion
+    AttractionScale,
+}
+impl SweepParam {
+    pub(crate) const ALL: [Self; 3] = [
+        Self::LocalRadius,
+        Self::FrictionHalfLife,
+        Self::AttractionScale,
+    ];
+
+    /// a range around the current value, within valid_range()
+    pub(crate) fn default_range(self, sim_settings: &SimSettings) -> [f32; 2] {
+        let valid_range = self.valid_range();
+        match self {
+            Self::LocalRadius => [0.5, 2.0].map(|scale| scale * sim_settings.local_radius),
+            Self::FrictionHalfLife => {
+                [0.25, 4.0].map(|scale| scale * sim_settings.friction_half_life)
+            }
+            Self::AttractionScale => [0.0, 2.0],
+        }
+        .map(|value| value.clamp(*valid_range.start(), *valid_range.end()))
+    }
+
+    /// the values that make sense for it
+    pub(crate) fn valid_range(self) -> std::ops::RangeInclusive<f32> {
+        match self {
+            // past 0.5 the nearest copy of a particle across the walls isn't always the one counted
+            Self::LocalRadius => 0.001..=0.5,
+            // a negative half life would make the friction speed particles up until they're NaN
+            Self::FrictionHalfLife => 0.001..=100.0,
+            // a negative scale flips the attractions, which is fine
+            Self::AttractionScale => -10.0..=10.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, bytemuck::NoUninit)]
+#[repr(C)]
+struct SweepParams {
+    instance_particle_n: u32,
+    instance_n: u32,
+    column_n: u32,
+    row_n: u32,
+    gap: f32,
+    pixel_size: f32,
+}
+
+#[derive(Clone, Copy, bytemuck::NoUninit)]
+#[repr(C)]
+struct SweepInstance {
+    local_radius: f32,
+    friction: f32,
+    attraction_scale: f32,
+    _padding: f32,
+}
+
+// the empty border around each box, as a fraction of its tile
+const GAP: f32 = 0.03;
+
+/// gpu state for the sweep grid, see sweep.wgsl
+/// a grid of small sims that all start from the same particles, each with different values of one or two settings,
+/// stepped together in one dispatch per tick and drawn together into one texture. only in 2d
+pub(crate) struct Sweep {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    renderer: Arc<egui::mutex::RwLock<egui_wgpu::Renderer>>,
+    x_param: SweepParam,
+    // the value of x_param in each column
+    x_values: Vec<f32>,
+    // None for a single row
+    y_param: Option<SweepParam>,
+    // the value of y_param in each row, from the bottom
+    y_values: Vec<f32>,
+    instance_particle_n: usize,
+    particle_radius: f32,
+    tile_size: u32,
+    particles_per_group: usize,
+    shader_params_buffer: wgpu::Buffer,
+    instance_buffer: wgpu::Buffer,
+    attraction_buffer: wgpu::Buffer,
+    specie_color_buffer: wgpu::Buffer,
+    pos_buffers: [wgpu::Buffer; 2],
+    specie_buffer: wgpu::Buffer,
+    common_bind_group: wgpu::BindGroup,
+    // compute_bind_groups[i] reads from pos_buffers[i] and writes to the other one
+    compute_bind_groups: [wgpu::BindGroup; 2],
+    render_bind_group: wgpu::BindGroup,
+    compute_pipeline: wgpu::ComputePipeline,
+    render_pipeline: wgpu::RenderPipeline,
+    texture: wgpu::Texture,
+    texture_id: egui::TextureId,
+    swap_parity: bool,
+}
+impl Sweep {
+    pub(crate) fn new(
+        render_state: &egui_wgpu::RenderState,
+        view_settings: &ViewSettings,
+        sim_settings: &SimSettings,
+        settings: &SweepSettings,
+    ) -> Self {
+        let device = render_state.device.clone();
+        let queue = render_state.queue.clone();
+        let renderer = render_state.renderer.clone();
+        let particles_per_group = sim_settings.workgroup_size as usize;
+
+        let axis_values = |range: [f32; 2], n: usize| {
+            (0..n)
+                .map(|i| range[0] + (range[1] - range[0]) * i as f32 / (n - 1).max(1) as f32)
+                .collect::<Vec<_>>()
+        };
+        let x_values = axis_values(settings.x_range, settings.x_n);
+        let y_values = match settings.y_param {
+            Some(_) => axis_values(settings.y_range, settings.y_n),
+            None => vec![0.0],
+        };
+        let instance_n = x_values.len() * y_values.len();
+        let instance_particle_n = settings.instance_particle_n;
+
+        // every instance starts from the same particles, so the only difference is the settings
+        let sim_data = SimData::new(sim_settings.specie_n, instance_particle_n);
+        let repeat = |values: &[egui::Vec2]| values.repeat(instance_n);
+        let sweep_params = SweepParams {
+            instance_particle_n: instance_particle_n as _,
+            instance_n: instance_n as _,
+            column_n: x_values.len() as _,
+            row_n: y_values.len() as _,
+            gap: GAP,
+            pixel_size: 1.0 / (settings.tile_size as f32 * (1.0 - 2.0 * GAP)),
+        };
+
+        let shader_params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sweep_shader_params_buffer"),
+            size: size_of::<ShaderParams>() as _,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let sweep_params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("sweep_params_buffer"),
+            contents: bytemuck::bytes_of(&sweep_params),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sweep_instance_buffer"),
+            size: (instance_n * size_of::<SweepInstance>()) as _,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let attraction_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sweep_attraction_buffer"),
+            size: (sim_settings.specie_n * sim_settings.specie_n * size_of::<f32>()) as _,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let specie_color_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sweep_specie_color_buffer"),
+            size: size_of_val(view_settings.specie_colors.as_slice()) as _,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let poses = repeat(&sim_data.poses);
+        let vels = repeat(&sim_data.vels);
+        let pos_buffers = [0, 1].map(|i| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("sweep_pos_buffer{i}")),
+                contents: bytemuck::cast_slice(&poses),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+            })
+        });
+        let vel_buffers = [0, 1].map(|i| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("sweep_vel_buffer{i}")),
+                contents: bytemuck::cast_slice(&vels),
+                usage: wgpu::BufferUsages::STORAGE,
+            })
+        });
+        let specie_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("sweep_specie_buffer"),
+            contents: bytemuck::cast_slice(&sim_data.species.repeat(instance_n)),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+        });
+
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("sweep_shader_module"),
+            source: wgpu::ShaderSource::Wgsl(
+                [
+                    // a const instead of an override, like in gfx.rs
+                    format!("const PARTICLES_PER_GROUP: u32 = {particles_per_group}u;\n").as_str(),
+                    gfx::COMMON_WGSL,
+                    include_str!("sweep.wgsl"),
+                ]
+                .concat()
+                .into(),
+            ),
+        });
+
+        let buffer_entry = |binding, visibility, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let uniform = wgpu::BufferBindingType::Uniform;
+        let read_only = wgpu::BufferBindingType::Storage { read_only: true };
+        let read_write = wgpu::BufferBindingType::Storage { read_only: false };
+        // the bindings from common.wgsl that sweep.wgsl uses
+        let common_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("sweep_common_bind_group_layout"),
+                entries: &[
+                    // shader_params_buffer
+                    buffer_entry(
+                        0,
+                        wgpu::ShaderStages::COMPUTE
+                            | wgpu::ShaderStages::VERTEX
+                            | wgpu::ShaderStages::FRAGMENT,
+                        uniform,
+                    ),
+                    // specie_buffer
+                    buffer_entry(5, wgpu::ShaderStages::COMPUTE, read_only),
+                    // attraction_buffer
+                    buffer_entry(6, wgpu::ShaderStages::COMPUTE, read_only),
+                    // specie_color_buffer
+                    buffer_entry(7, wgpu::ShaderStages::FRAGMENT, read_only),
+                ],
+            });
+        let compute_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("sweep_compute_bind_group_layout"),
+                entries: &[
+                    // sweep_params_buffer
+                    buffer_entry(0, wgpu::ShaderStages::COMPUTE, uniform),
+                    // instance_buffer
+                    buffer_entry(1, wgpu::ShaderStages::COMPUTE, read_only),
+                    // the src pos and vel buffers
+                    buffer_entry(2, wgpu::ShaderStages::COMPUTE, read_only),
+                    buffer_entry(3, wgpu::ShaderStages::COMPUTE, read_only),
+                    // the dst pos and vel buffers
+                    buffer_entry(4, wgpu::ShaderStages::COMPUTE, read_write),
+                    buffer_entry(5, wgpu::ShaderStages::COMPUTE, read_write),
+                ],
+            });
+        // the vertex and fragment shaders can't have the read_write buffers
+        let render_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("sweep_render_bind_group_layout"),
+                entries: &[
+                    // sweep_params_buffer
+                    buffer_entry(
+                        0,
+                        wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        uniform,
+                    ),
+                ],
+            });
+
+        let common_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("sweep_common_bind_group"),
+            layout: &common_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: shader_params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: specie_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: attraction_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: specie_color_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let compute_bind_groups = [0, 1].map(|src| {
+            let dst = 1 - src;
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(&format!("sweep_compute_bind_group{src}")),
+                layout: &compute_bind_group_layout,
+                entries: &[
+                    (0, &sweep_params_buffer),
+                    (1, &instance_buffer),
+                    (2, &pos_buffers[src]),
+                    (3, &vel_buffers[src]),
+                    (4, &pos_buffers[dst]),
+                    (5, &vel_buffers[dst]),
+                ]
+                .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                    binding,
+                    resource: buffer.as_entire_binding(),
+                }),
+            })
+        });
+        let render_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("sweep_render_bind_group"),
+            layout: &render_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: sweep_params_buffer.as_entire_binding(),
+            }],
+        });
+
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("sweep_compute_pipeline"),
+            layout: Some(
+                &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("sweep_compute_pipeline_layout"),
+                    bind_group_layouts: &[&common_bind_group_layout, &compute_bind_group_layout],
+                    push_constant_ranges: &[],
+                }),
+            ),
+            module: &shader_module,
+            entry_point: "sweep_cs",
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("sweep_render_pipeline"),
+            layout: Some(
+                &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("sweep_render_pipeline_layout"),
+                    bind_group_layouts: &[&common_bind_group_layout, &render_bind_group_layout],
+                    push_constant_ranges: &[],
+                }),
+            ),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "sweep_vs",
+                compilation_options: Default::default(),
+                buffers: &[
+                    // @location(1) particle_pos: vec2<f32>
+                    wgpu::VertexBufferLayout {
+                        array_stride: size_of::<egui::Vec2>() as _,
+                        step_mode: wgpu::VertexStepMode::Instance,
+                        attributes: &wgpu::vertex_attr_array![1 => Float32x2],
+                    },
+                    // @location(3) particle_species: u32
+                    wgpu::VertexBufferLayout {
+                        array_stride: size_of::<u32>() as _,
+                        step_mode: wgpu::VertexStepMode::Instance,
+                        attributes: &wgpu::vertex_attr_array![3 => Uint32],
+                    },
+                ],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: "sweep_fs",
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: gfx::TEXTURE_FORMAT,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::all(),
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let texture = gfx::create_texture(
+            &device,
+            wgpu::Extent3d {
+                width: x_values.len() as u32 * settings.tile_size,
+                height: y_values.len() as u32 * settings.tile_size,
+                depth_or_array_layers: 1,
+            },
+        );
+        let texture_id = renderer.write().register_native_texture(
+            &device,
+            &texture.create_view(&Default::default()),
+            wgpu::FilterMode::Linear,
+        );
+
+        Self {
+            device,
+            queue,
+            renderer,
+            x_param: settings.x_param,
+            x_values,
+            y_param: settings.y_param,
+            y_values,
+            instance_particle_n,
+            particle_radius: settings.particle_radius,
+            tile_size: settings.tile_size,
+            particles_per_group,
+            shader_params_buffer,
+            instance_buffer,
+            attraction_buffer,
+            specie_color_buffer,
+            pos_buffers,
+            specie_buffer,
+            common_bind_group,
+            compute_bind_groups,
+            render_bind_group,
+            compute_pipeline,
+            render_pipeline,
+            texture,
+            texture_id,
+            swap_parity: false,
+        }
+    }
+
+    fn instance_n(&self) -> usize {
+        self.x_values.len() * self.y_values.len()
+    }
+
+    /// does sim_settings.substep_n ticks of every instance and draws them, with the attractions,
+    /// substep_n, dt and whatever isn't swept from sim_settings
+    pub(crate) fn step(&mut self, view_settings: &ViewSettings, sim_settings: &SimSettings) {
+        let tick_dt = sim_settings.tick_dt();
+        let instances = (0..self.instance_n())
+            .map(|instance_i| {
+                let mut local_radius = sim_settings.local_radius;
+                let mut friction_half_life = sim_settings.friction_half_life;
+                let mut attraction_scale = 1.0;
+                let column = instance_i % self.x_values.len();
+                let row = instance_i / self.x_values.len();
+                for (param, value) in [
+                    (Some(self.x_param), self.x_values[column]),
+                    (self.y_param, self.y_values[row]),
+                ] {
+                    match param {
+                        Some(SweepParam::LocalRadius) => local_radius = value,
+                        Some(SweepParam::FrictionHalfLife) => friction_half_life = value,
+                        Some(SweepParam::AttractionScale) => attraction_scale = value,
+                        None => {}
+                    }
+                }
+                SweepInstance {
+                    local_radius,
+                    friction: 0.5_f32.powf(tick_dt / friction_half_life),
+                    attraction_scale,
+                    _padding: 0.0,
+                }
+            })
+            .collect::<Vec<_>>();
+        self.queue
+            .write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instances));
+        // force_multiplier depends on the particle_n of one instance
+        let shader_params = ShaderParams::new(
+            &ViewSettings {
+                particle_radius: self.particle_radius,
+                zoom_scale: 1.0,
+                texture_size: self.tile_size,
+                ..view_settings.clone()
+            },
+            &SimSettings {
+                particle_n: self.instance_particle_n,
+                ..sim_settings.clone()
+            },
+        );
+        self.queue.write_buffer(
+            &self.shader_params_buffer,
+            0,
+            bytemuck::bytes_of(&shader_params),
+        );
+        self.queue.write_buffer(
+            &self.attraction_buffer,
+            0,
+            bytemuck::cast_slice(&sim_settings.attractions.concat()),
+        );
+        self.queue.write_buffer(
+            &self.specie_color_buffer,
+            0,
+            bytemuck::cast_slice(&view_settings.specie_colors),
+        );
+
+        let mut command_encoder = self.device.create_command_encoder(&Default::default());
+        let particle_n = self.instance_n() * self.instance_particle_n;
+        for _ in 0..sim_settings.substep_n {
+            let mut compute_pass = command_encoder.begin_compute_pass(&Default::default());
+            compute_pass.set_pipeline(&self.compute_pipeline);
+            compute_pass.set_bind_group(0, &self.common_bind_group, &[]);
+            compute_pass.set_bind_group(
+                1,
+                &self.compute_bind_groups[self.swap_parity as usize],
+                &[],
+            );
+            compute_pass.dispatch_workgroups(
+                particle_n.div_ceil(self.particles_per_group) as _,
+                1,
+                1,
+            );
+            drop(compute_pass);
+            self.swap_parity = !self.swap_parity;
+        }
+
+        let texture_view = self.texture.create_view(&Default::default());
+        let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("sweep_render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.common_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.render_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.pos_buffers[self.swap_parity as usize].slice(..));
+        render_pass.set_vertex_buffer(1, self.specie_buffer.slice(..));
+        render_pass.draw(0..6, 0..particle_n as u32);
+        drop(render_pass);
+        self.queue.submit([command_encoder.finish()]);
+    }
+
+    /// the grid with the values of the swept settings along its edges
+    pub(crate) fn ui(&self, ui: &mut egui::Ui) {
+        let tile_size = self.tile_size as f32 / ui.ctx().pixels_per_point();
+        let column_n = self.x_values.len();
+        let row_n = self.y_values.len();
+        let label_width = 48.0;
+        let label_height = 2.0 * ui.text_style_height(&egui::TextStyle::Body);
+        let grid_size = egui::Vec2::new(column_n as f32, row_n as f32) * tile_size;
+        let (response, painter) = ui.allocate_painter(
+            grid_size + egui::Vec2::new(label_width, label_height),
+            egui::Sense::hover(),
+        );
+        let grid_rect = egui::Rect::from_min_size(
+            response.rect.min + egui::Vec2::new(label_width, 0.0),
+            grid_size,
+        );
+        painter.image(
+            self.texture_id,
+            grid_rect,
+            egui::Rect::from_min_max(egui::Pos2::ZERO, egui::Pos2::new(1.0, 1.0)),
+            egui::Color32::WHITE,
+        );
+        // the rows go up from the bottom, like y in the sim
+        let tile_rect = |column: usize, row: usize| {
+            egui::Rect::from_min_size(
+                grid_rect.left_bottom()
+                    + egui::Vec2::new(column as f32, -(row as f32 + 1.0)) * tile_size,
+                egui::Vec2::splat(tile_size),
+            )
+        };
+        let font_id = egui::TextStyle::Small.resolve(ui.style());
+        let text_color = ui.visuals().text_color();
+        for row in 0..row_n {
+            for column in 0..column_n {
+                painter.rect_stroke(
+                    tile_rect(column, row).shrink(GAP * tile_size),
+                    0.0,
+                    egui::Stroke::new(1.0, egui::Color32::from_gray(60)),
+                );
+            }
+        }
+        for (column, x_value) in self.x_values.iter().enumerate() {
+            painter.text(
+                tile_rect(column, 0).center_bottom(),
+                egui::Align2::CENTER_TOP,
+                format!("{x_value:.3}"),
+                font_id.clone(),
+                text_color,
+            );
+        }
+        painter.text(
+            grid_rect.center_bottom() + egui::Vec2::new(0.0, label_height),
+            egui::Align2::CENTER_BOTTOM,
+            format!("{:?}", self.x_param),
+            font_id.clone(),
+            text_color,
+        );
+        if let Some(y_param) = self.y_param {
+            for (row, y_value) in self.y_values.iter().enumerate() {
+                painter.text(
+                    tile_rect(0, row).left_center() - egui::Vec2::new(4.0, 0.0),
+                    egui::Align2::RIGHT_CENTER,
+                    format!("{y_value:.3}"),
+                    font_id.clone(),
+                    text_color,
+                );
+            }
+            painter.text(
+                response.rect.left_bottom(),
+                egui::Align2::LEFT_BOTTOM,
+                format!("{y_param:?}"),
+                font_id,
+                text_color,
+            );
+        }
+        if let Some(hover_pos) = response.hover_pos().filter(|pos| grid_rect.contains(*pos)) {
+            let column =
+                (((hover_pos.x - grid_rect.left()) / tile_size) as usize).min(column_n - 1);
+            let row = (((grid_rect.bottom() - hover_pos.y) / tile_size) as usize).min(row_n - 1);
+            response.on_hover_text_at_pointer(match self.y_param {
+                Some(y_param) => format!(
+                    "{:?}: {:.4}\n{y_param:?}: {:.4}",
+                    self.x_param, self.x_values[column], self.y_values[row]
+                ),
+                None => format!("{:?}: {:.4}", self.x_param, self.x_values[column]),
+            });
+        }
+    }
+}
+// the renderer is shared with the app, so the texture would stay registered in it otherwise
+impl Drop for Sweep {
+    fn drop(&mut self) {
+        self.renderer.write().free_texture(&self.texture_id);
+    }
+}
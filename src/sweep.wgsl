@@ -0,0 +1,110 @@
+// the sweep grid, see sweep.rs, appended to common.wgsl in its own shader module
+// every instance is its own box of sweep.instance_particle_n particles with its own settings,
+// and they're all stepped by one dispatch and drawn by one draw, each into its own tile
+
+struct SweepParams {
+    instance_particle_n: u32,
+    instance_n: u32,
+    column_n: u32,
+    row_n: u32,
+    // the empty border around each box, as a fraction of its tile
+    gap: f32,
+    // the size of a pixel of a tile in the units of the box
+    pixel_size: f32,
+}
+
+// the settings that vary between instances
+struct SweepInstance {
+    local_radius: f32,
+    // per tick, like params.friction
+    friction: f32,
+    // multiplies the attractions but not the repulsion
+    attraction_scale: f32,
+    _padding: f32,
+}
+
+@group(1) @binding(0) var<uniform> sweep: SweepParams;
+@group(1) @binding(1) var<storage, read> sweep_instances: array<SweepInstance>;
+@group(1) @binding(2) var<storage, read> sweep_pos_src: array<vec2<f32>>;
+@group(1) @binding(3) var<storage, read> sweep_vel_src: array<vec2<f32>>;
+@group(1) @binding(4) var<storage, read_write> sweep_pos_dst: array<vec2<f32>>;
+@group(1) @binding(5) var<storage, read_write> sweep_vel_dst: array<vec2<f32>>;
+
+// main_cs in shader.wgsl, but only over the particles in the same instance
+@compute
+@workgroup_size(PARTICLES_PER_GROUP)
+fn sweep_cs(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let index = global_invocation_id.x;
+    if (index >= sweep.instance_n * sweep.instance_particle_n) {
+        return;
+    }
+    let instance = sweep_instances[index / sweep.instance_particle_n];
+    let first = index / sweep.instance_particle_n * sweep.instance_particle_n;
+    let local_radius2 = instance.local_radius * instance.local_radius;
+
+    let pos = sweep_pos_src[index];
+    var force = vec2(0.0, 0.0);
+    for (var neighbor_i = first; neighbor_i < first + sweep.instance_particle_n; neighbor_i++) {
+        var to_neighbor = sweep_pos_src[neighbor_i] - pos;
+        to_neighbor -= step(vec2(0.5, 0.5), to_neighbor);
+        to_neighbor += step(to_neighbor, vec2(-0.5, -0.5));
+        let distance2 = dot(to_neighbor, to_neighbor);
+        // also skips the particle itself
+        if (distance2 > local_radius2 || distance2 == 0.0) {
+            continue;
+        }
+        let distance = sqrt(distance2);
+        let attraction = attractions[species[index] * params.specie_n + species[neighbor_i]];
+        force += (to_neighbor / distance)
+            * get_attraction_force(distance / instance.local_radius, attraction * instance.attraction_scale);
+    }
+
+    // integrate() in shader.wgsl
+    let new_vel = (sweep_vel_src[index] + force * params.force_multiplier * params.dt) * instance.friction;
+    var new_pos = pos + new_vel * params.dt;
+    new_pos -= step(vec2(1.0, 1.0), new_pos);
+    new_pos += step(new_pos, vec2(0.0, 0.0));
+    sweep_pos_dst[index] = new_pos;
+    sweep_vel_dst[index] = new_vel;
+}
+
+struct SweepVertexOutput {
+    @builtin(position) clip_pos: vec4<f32>,
+    // offset from the center of the particle, in the units of the box
+    @location(0) corner: vec2<f32>,
+    @location(1) @interpolate(flat) particle_species: u32,
+}
+
+// each particle is a quad in the tile of its instance, which is the instance_index / instance_particle_n
+@vertex
+fn sweep_vs(
+    @builtin(vertex_index) vertex_index: u32,
+    @builtin(instance_index) particle_i: u32,
+    @location(1) particle_pos: vec2<f32>,
+    @location(3) particle_species: u32,
+) -> SweepVertexOutput {
+    let instance_i = particle_i / sweep.instance_particle_n;
+    let tile = vec2(f32(instance_i % sweep.column_n), f32(instance_i / sweep.column_n));
+    let draw_radius = max(params.particle_radius, sweep.pixel_size);
+    let corner = quad_corner(vertex_index) * (draw_radius + sweep.pixel_size);
+    let tile_pos = vec2(sweep.gap) + (particle_pos + corner) * (1.0 - 2.0 * sweep.gap);
+    let texture_pos = (tile + tile_pos) / vec2(f32(sweep.column_n), f32(sweep.row_n));
+    return SweepVertexOutput(
+        vec4(texture_pos * 2.0 - vec2(1.0, 1.0), 0.0, 1.0),
+        corner,
+        particle_species,
+    );
+}
+
+// main_fs in shader.wgsl, but only circles colored by specie
+@fragment
+fn sweep_fs(in: SweepVertexOutput) -> @location(0) vec4<f32> {
+    let draw_radius = max(params.particle_radius, sweep.pixel_size);
+    let fade = min(params.particle_radius2 / (draw_radius * draw_radius), 1.0);
+    let coverage = clamp(-(length(in.corner) - draw_radius) / sweep.pixel_size + 0.5, 0.0, 1.0);
+    if coverage <= 0.0 {
+        discard;
+    }
+    let color = specie_colors[in.particle_species];
+    return vec4(color.rgb, color.a * coverage * fade);
+}
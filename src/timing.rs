@@ -0,0 +1,231 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use eframe::wgpu;
+
+// indices into the query set
+const COMPUTE_START: u32 = 0;
+const COMPUTE_END: u32 = 1;
+const RENDER_START: u32 = 2;
+const RENDER_END: u32 = 3;
+const QUERY_N: u32 = 4;
+
+/// how long the passes of one frame took on the gpu
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct GpuTimes {
+    /// all the substeps, including sorting
+    pub(crate) compute_ms: f32,
+    pub(crate) render_ms: f32,
+}
+
+/// times the compute and render passes with timestamp queries
+/// the results are read back without blocking, so they arrive a few frames late and some frames are skipped
+pub(crate) struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    // nanoseconds per timestamp tick
+    period: f32,
+    // whether resolve() copied into readback_buffer this frame, so start_readback() should map it
+    copied: bool,
+    // true from mapping readback_buffer until it's unmapped, since it can't be copied to while mapped
+    readback_busy: bool,
+    readback_ready: Arc<AtomicBool>,
+}
+impl GpuTimer {
+    /// None if the device wasn't made with wgpu::Features::TIMESTAMP_QUERY
+    pub(crate) fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+        let size = QUERY_N as u64 * size_of::<u64>() as u64;
+        Some(Self {
+            query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("timestamp_query_set"),
+                ty: wgpu::QueryType::Timestamp,
+                count: QUERY_N,
+            }),
+            resolve_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("timestamp_resolve_buffer"),
+                size,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            readback_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("timestamp_readback_buffer"),
+                size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            period: queue.get_timestamp_period(),
+            copied: false,
+            readback_busy: false,
+            readback_ready: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// records an empty compute pass that writes the timestamp for the start of the compute work,
+    /// so the solvers don't each need to know about the timer
+    pub(crate) fn mark_compute_start(&self, command_encoder: &mut wgpu::CommandEncoder) {
+        self.mark(command_encoder, Some(COMPUTE_START), None);
+    }
+
+    pub(crate) fn mark_compute_end(&self, command_encoder: &mut wgpu::CommandEncoder) {
+        self.mark(command_encoder, None, Some(COMPUTE_END));
+    }
+
+    fn mark(
+        &self,
+        command_encoder: &mut wgpu::CommandEncoder,
+        beginning_of_pass_write_index: Option<u32>,
+        end_of_pass_write_index: Option<u32>,
+    ) {
+        command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("timestamp_compute_pass"),
+            timestamp_writes: Some(wgpu::ComputePassTimestampWrites {
+                query_set: &self.query_set,
+                beginning_of_pass_write_index,
+                end_of_pass_write_index,
+            }),
+        });
+    }
+
+    pub(crate) fn render_timestamp_writes(&self) -> wgpu::RenderPassTimestampWrites<'_> {
+        wgpu::RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(RENDER_START),
+            end_of_pass_write_index: Some(RENDER_END),
+        }
+    }
+
+    /// call after all the passes are recorded
+    pub(crate) fn resolve(&mut self, command_encoder: &mut wgpu::CommandEncoder) {
+        command_encoder.resolve_query_set(&self.query_set, 0..QUERY_N, &self.resolve_buffer, 0);
+        self.copied = !self.readback_busy;
+        if self.copied {
+            command_encoder.copy_buffer_to_buffer(
+                &self.resolve_buffer,
+                0,
+                &self.readback_buffer,
+                0,
+                self.resolve_buffer.size(),
+            );
+        }
+    }
+
+    /// call after submitting the command buffer that resolve() was recorded into
+    pub(crate) fn start_readback(&mut self) {
+        if !self.copied {
+            return;
+        }
+        self.copied = false;
+        self.readback_busy = true;
+        let readback_ready = self.readback_ready.clone();
+        self.readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                result.unwrap();
+                readback_ready.store(true, Ordering::Release);
+            });
+    }
+
+    /// the times of the last frame that finished reading back, if one has since the last call
+    pub(crate) fn take_times(&mut self, device: &wgpu::Device) -> Option<GpuTimes> {
+        device.poll(wgpu::Maintain::Poll);
+        if !self.readback_ready.swap(false, Ordering::Acquire) {
+            return None;
+        }
+        let timestamps: Vec<u64> =
+            bytemuck::cast_slice(&self.readback_buffer.slice(..).get_mapped_range()).to_vec();
+        self.readback_buffer.unmap();
+        self.readback_busy = false;
+
+        let ms = |start: u32, end: u32| {
+            // timestamps can go backwards on some gpus, which would wrap around
+            timestamps[end as usize].saturating_sub(timestamps[start as usize]) as f32 * self.period
+                / 1e6
+        };
+        Some(GpuTimes {
+            compute_ms: ms(COMPUTE_START, COMPUTE_END),
+            render_ms: ms(RENDER_START, RENDER_END),
+        })
+    }
+}
+
+/// smoothed timings for the performance overlay
+/// uses the gpu times when there are any, and falls back to the time between frames on the cpu
+pub(crate) struct PerfStats {
+    // exponential moving averages
+    frame_ms: f32,
+    gpu_times: Option<GpuTimes>,
+}
+impl PerfStats {
+    // how much of each new sample goes into the averages
+    const SMOOTHING: f32 = 0.05;
+
+    pub(crate) fn new() -> Self {
+        Self {
+            frame_ms: 0.0,
+            gpu_times: None,
+        }
+    }
+
+    pub(crate) fn update(&mut self, frame_ms: f32, gpu_times: Option<GpuTimes>) {
+        let lerp = |old: f32, new: f32| old + (new - old) * Self::SMOOTHING;
+        self.frame_ms = if self.frame_ms == 0.0 {
+            frame_ms
+        } else {
+            lerp(self.frame_ms, frame_ms)
+        };
+        if let Some(new) = gpu_times {
+            self.gpu_times = Some(match self.gpu_times {
+                Some(old) => GpuTimes {
+                    compute_ms: lerp(old.compute_ms, new.compute_ms),
+                    render_ms: lerp(old.render_ms, new.render_ms),
+                },
+                None => new,
+            });
+        }
+    }
+
+    /// the lines of the overlay
+    pub(crate) fn text(&self, substep_n: usize, particle_n: usize) -> String {
+        let (source, compute_ms_per_substep) = match self.gpu_times {
+            Some(gpu_times) => ("gpu timestamps", gpu_times.compute_ms / substep_n as f32),
+            // an upper bound, since the frame also includes rendering and egui
+            None => ("cpu frame time", self.frame_ms / substep_n as f32),
+        };
+        // None for a time of 0, like before the first frame is timed, instead of inf
+        let per_second = |count: f32, ms: f32| (ms > 0.0).then(|| count * 1000.0 / ms);
+        // what the app actually gets, since every frame does substep_n ticks
+        let ticks_per_second = per_second(substep_n as f32, self.frame_ms);
+        // what the compute shaders alone could do if nothing else took time, which is the same with the cpu fallback
+        let max_ticks_per_second = per_second(1.0, compute_ms_per_substep);
+        // every solver is counted as if it did all the pairs, so they can be compared to the exact one
+        let pairs_per_second = max_ticks_per_second.map(|max_ticks_per_second| {
+            max_ticks_per_second * particle_n as f32 * particle_n as f32
+        });
+        let [ticks_per_second, max_ticks_per_second, pairs_per_second] = [
+            ticks_per_second.map(|rate| format!("{rate:.0}")),
+            max_ticks_per_second.map(|rate| format!("{rate:.0}")),
+            pairs_per_second.map(|rate| format!("{rate:.3e}")),
+        ]
+        .map(|rate| rate.unwrap_or_else(|| "n/a".to_owned()));
+        let render_ms = match self.gpu_times {
+            Some(gpu_times) => format!("{:.3} ms", gpu_times.render_ms),
+            None => "n/a".to_owned(),
+        };
+        format!(
+            "{source}\n\
+            frame: {:.2} ms\n\
+            compute: {compute_ms_per_substep:.3} ms/substep\n\
+            render: {render_ms}\n\
+            ticks/s: {ticks_per_second}\n\
+            max ticks/s (compute only): {max_ticks_per_second}\n\
+            max pairs/s (compute only): {pairs_per_second}",
+            self.frame_ms,
+        )
+    }
+}
@@ -0,0 +1,374 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{Dimension, SimData, SimSettings};
+
+/// how a trajectory is written, all of them starting with the metadata as json
+/// every sample has the tick, the sim time and each particle's position and velocity in stable id order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TrajectoryFormat {
+    /// one file, with the metadata as a `# ` comment on the first line, then a row per particle per sample
+    Csv,
+    /// a directory of numpy arrays: poses.npy and vels.npy of shape (sample_n, particle_n, dimension),
+    /// ticks.npy and times.npy of shape (sample_n,), species.npy of shape (particle_n,), and metadata.json
+    Npy,
+    /// one file of tagged chunks, so readers can skip the ones they don't know, all little endian:
+    /// b"PLTRAJ01", then the u64 length of the metadata and the metadata,
+    /// then chunks of a 4 byte tag, the u64 length of the rest of the chunk and the rest of the chunk,
+    /// which is a u32 per particle for b"SPEC", written once at the start,
+    /// or the u64 tick, the f64 time, then f32 poses and f32 vels, `dimension` per particle, for b"STEP"
+    Chunked,
+}
+impl TrajectoryFormat {
+    pub(crate) const ALL: [Self; 3] = [Self::Csv, Self::Npy, Self::Chunked];
+}
+
+#[derive(serde::Serialize)]
+struct Metadata<'a> {
+    sim_settings: &'a SimSettings,
+    // 2 or 3, the components per position and velocity
+    dimension: usize,
+    // rounded up to a multiple of substep_n, since samples can only be taken between frames
+    tick_interval: u64,
+}
+
+enum Sink {
+    Csv(BufWriter<File>),
+    Npy(Box<NpyFiles>),
+    Chunked(BufWriter<File>),
+}
+
+struct NpyFiles {
+    poses: NpyWriter,
+    vels: NpyWriter,
+    ticks: NpyWriter,
+    times: NpyWriter,
+}
+
+/// writes GfxData::read_sim_data() every tick_interval ticks
+/// the files are finished when it's dropped too, so quitting the app in the middle still leaves readable npy files
+pub(crate) struct TrajectoryWriter {
+    pub(crate) path: PathBuf,
+    pub(crate) sample_n: usize,
+    pub(crate) tick_interval: u64,
+    last_tick: Option<u64>,
+    dimension: usize,
+    sink: Sink,
+    finished: bool,
+}
+impl TrajectoryWriter {
+    /// starts a file or directory like trajectory_1700000000.csv in the working directory
+    /// `species` are in stable id order, like the rest of the samples
+    /// the frames do substep_n ticks at a time, so tick_interval is rounded up to a multiple of it
+    pub(crate) fn start(
+        format: TrajectoryFormat,
+        tick_interval: u64,
+        sim_settings: &SimSettings,
+        species: &[u32],
+    ) -> std::io::Result<Self> {
+        let substep_n = sim_settings.substep_n.max(1) as u64;
+        let tick_interval = tick_interval.max(1).div_ceil(substep_n) * substep_n;
+        let dimension = match sim_settings.dimension {
+            Dimension::Two => 2,
+            Dimension::Three => 3,
+        };
+        let metadata = serde_json::to_string(&Metadata {
+            sim_settings,
+            dimension,
+            tick_interval,
+        })?;
+        let name = crate::export::timestamped_name("trajectory");
+        let (path, sink) = match format {
+            TrajectoryFormat::Csv => {
+                let path = PathBuf::from(format!("{name}.csv"));
+                let mut file = BufWriter::new(File::create(&path)?);
+                writeln!(file, "# {metadata}")?;
+                let components = ["x", "y", "z"][..dimension].join(",");
+                let vel_components = ["vx", "vy", "vz"][..dimension].join(",");
+                writeln!(file, "tick,time,id,specie,{components},{vel_components}")?;
+                (path, Sink::Csv(file))
+            }
+            TrajectoryFormat::Npy => {
+                let path = PathBuf::from(name);
+                std::fs::create_dir(&path)?;
+                std::fs::write(path.join("metadata.json"), &metadata)?;
+                let particle_n = species.len();
+                let mut species_npy = NpyWriter::new(&path.join("species.npy"), "<u4", &[])?;
+                species_npy.write_rows(bytemuck::cast_slice(species), particle_n)?;
+                species_npy.finish()?;
+                let sink = Sink::Npy(Box::new(NpyFiles {
+                    poses: NpyWriter::new(
+                        &path.join("poses.npy"),
+                        "<f4",
+                        &[particle_n, dimension],
+                    )?,
+                    vels: NpyWriter::new(&path.join("vels.npy"), "<f4", &[particle_n, dimension])?,
+                    ticks: NpyWriter::new(&path.join("ticks.npy"), "<u8", &[])?,
+                    times: NpyWriter::new(&path.join("times.npy"), "<f8", &[])?,
+                }));
+                (path, sink)
+            }
+            TrajectoryFormat::Chunked => {
+                let path = PathBuf::from(format!("{name}.bin"));
+                let mut file = BufWriter::new(File::create(&path)?);
+                file.write_all(b"PLTRAJ01")?;
+                file.write_all(&(metadata.len() as u64).to_le_bytes())?;
+                file.write_all(metadata.as_bytes())?;
+                write_chunk(&mut file, b"SPEC", &[bytemuck::cast_slice(species)])?;
+                (path, Sink::Chunked(file))
+            }
+        };
+        Ok(Self {
+            path,
+            sample_n: 0,
+            tick_interval,
+            last_tick: None,
+            dimension,
+            sink,
+            finished: false,
+        })
+    }
+
+    /// if it's been tick_interval ticks since the last sample
+    /// every sample has its own tick anyway, in case substep_n changed since the start
+    pub(crate) fn wants_sample(&self, tick: u64) -> bool {
+        self.last_tick
+            .is_none_or(|last_tick| tick >= last_tick + self.tick_interval)
+    }
+
+    pub(crate) fn sample(
+        &mut self,
+        tick: u64,
+        time: f64,
+        sim_data: &SimData<[f32; 3]>,
+    ) -> std::io::Result<()> {
+        let dimension = self.dimension;
+        let flatten = |values: &[[f32; 3]]| {
+            values
+                .iter()
+                .flat_map(|value| value[..dimension].iter().copied())
+                .collect::<Vec<f32>>()
+        };
+        match &mut self.sink {
+            Sink::Csv(file) => {
+                for (id, ((pos, vel), specie)) in sim_data
+                    .poses
+                    .iter()
+                    .zip(&sim_data.vels)
+                    .zip(&sim_data.species)
+                    .enumerate()
+                {
+                    write!(file, "{tick},{time},{id},{specie}")?;
+                    for value in pos[..dimension].iter().chain(&vel[..dimension]) {
+                        write!(file, ",{value}")?;
+                    }
+                    writeln!(file)?;
+                }
+            }
+            Sink::Npy(npy_files) => {
+                let NpyFiles {
+                    poses,
+                    vels,
+                    ticks,
+                    times,
+                } = &mut **npy_files;
+                poses.write_rows(bytemuck::cast_slice(&flatten(&sim_data.poses)), 1)?;
+                vels.write_rows(bytemuck::cast_slice(&flatten(&sim_data.vels)), 1)?;
+                ticks.write_rows(&tick.to_le_bytes(), 1)?;
+                times.write_rows(&time.to_le_bytes(), 1)?;
+            }
+            Sink::Chunked(file) => {
+                write_chunk(
+                    file,
+                    b"STEP",
+                    &[
+                        &tick.to_le_bytes(),
+                        &time.to_le_bytes(),
+                        bytemuck::cast_slice(&flatten(&sim_data.poses)),
+                        bytemuck::cast_slice(&flatten(&sim_data.vels)),
+                    ],
+                )?;
+            }
+        }
+        self.last_tick = Some(tick);
+        self.sample_n += 1;
+        Ok(())
+    }
+
+    /// flushes the files, and fills in the sample count in the npy headers
+    pub(crate) fn finish(mut self) -> std::io::Result<()> {
+        self.finish_files()
+    }
+
+    fn finish_files(&mut self) -> std::io::Result<()> {
+        self.finished = true;
+        match &mut self.sink {
+            Sink::Csv(file) | Sink::Chunked(file) => file.flush(),
+            Sink::Npy(npy_files) => {
+                let NpyFiles {
+                    poses,
+                    vels,
+                    ticks,
+                    times,
+                } = &mut **npy_files;
+                poses.finish()?;
+                vels.finish()?;
+                ticks.finish()?;
+                times.finish()
+            }
+        }
+    }
+}
+impl Drop for TrajectoryWriter {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.finish_files();
+        }
+    }
+}
+
+fn write_chunk(file: &mut impl Write, tag: &[u8; 4], parts: &[&[u8]]) -> std::io::Result<()> {
+    let len = parts.iter().map(|part| part.len() as u64).sum::<u64>();
+    file.write_all(tag)?;
+    file.write_all(&len.to_le_bytes())?;
+    for part in parts {
+        file.write_all(part)?;
+    }
+    Ok(())
+}
+
+// the npy header is rewritten with the final shape at the end, so it has a fixed size
+// it has to be a multiple of 64 bytes, and this is enough for any shape
+const NPY_HEADER_LEN: usize = 128;
+
+/// a .npy file that's appended to along its first axis, see
+/// https://numpy.org/doc/stable/reference/generated/numpy.lib.format.html
+struct NpyWriter {
+    file: BufWriter<File>,
+    // like "<f4"
+    descr: &'static str,
+    // the shape after the first axis
+    row_shape: Vec<usize>,
+    row_n: usize,
+}
+impl NpyWriter {
+    fn new(path: &Path, descr: &'static str, row_shape: &[usize]) -> std::io::Result<Self> {
+        let mut npy = Self {
+            file: BufWriter::new(File::create(path)?),
+            descr,
+            row_shape: row_shape.to_vec(),
+            row_n: 0,
+        };
+        npy.write_header()?;
+        Ok(npy)
+    }
+
+    fn write_header(&mut self) -> std::io::Result<()> {
+        let shape = std::iter::once(self.row_n)
+            .chain(self.row_shape.iter().copied())
+            .map(|len| format!("{len},"))
+            .collect::<String>();
+        let dict = format!(
+            "{{'descr': '{}', 'fortran_order': False, 'shape': ({shape}), }}",
+            self.descr
+        );
+        // the magic string, the version, the u16 length of the rest, then the dict padded with spaces to a newline
+        let mut header = b"\x93NUMPY\x01\x00".to_vec();
+        header.extend_from_slice(&(NPY_HEADER_LEN as u16 - 10).to_le_bytes());
+        header.extend_from_slice(dict.as_bytes());
+        assert!(
+            header.len() < NPY_HEADER_LEN,
+            "the npy header doesn't fit in NPY_HEADER_LEN"
+        );
+        header.resize(NPY_HEADER_LEN - 1, b' ');
+        header.push(b'\n');
+        self.file.write_all(&header)
+    }
+
+    /// `bytes` are `row_n` rows of row_shape
+    fn write_rows(&mut self, bytes: &[u8], row_n: usize) -> std::io::Result<()> {
+        self.row_n += row_n;
+        self.file.write_all(bytes)
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.file.rewind()?;
+        self.write_header()?;
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_chunk, NpyWriter, NPY_HEADER_LEN};
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("gpu_particle_life_{}_{name}", std::process::id()))
+    }
+
+    #[test]
+    fn test_npy_round_trip() {
+        let path = temp_path("round_trip.npy");
+        let rows = [[1.0_f32, 2.0], [3.0, 4.0], [5.0, 6.0]];
+        let mut npy = NpyWriter::new(&path, "<f4", &[2]).unwrap();
+        npy.write_rows(bytemuck::cast_slice(&rows[..1]), 1).unwrap();
+        npy.write_rows(bytemuck::cast_slice(&rows[1..]), 2).unwrap();
+        npy.finish().unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        assert_eq!(10 + header_len, NPY_HEADER_LEN);
+        assert!(
+            NPY_HEADER_LEN.is_multiple_of(64),
+            "the data should be 64 byte aligned"
+        );
+        let header = std::str::from_utf8(&bytes[10..NPY_HEADER_LEN]).unwrap();
+        assert!(header.ends_with('\n'));
+        assert_eq!(
+            header.trim_end(),
+            "{'descr': '<f4', 'fortran_order': False, 'shape': (3,2,), }"
+        );
+        assert_eq!(
+            &bytes[NPY_HEADER_LEN..],
+            bytemuck::cast_slice::<_, u8>(&rows)
+        );
+    }
+
+    #[test]
+    fn test_npy_header_fits_big_shapes() {
+        let path = temp_path("big_shape.npy");
+        let mut npy = NpyWriter::new(&path, "<f4", &[usize::MAX, 3]).unwrap();
+        npy.row_n = usize::MAX;
+        npy.finish().unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(bytes.len(), NPY_HEADER_LEN);
+    }
+
+    #[test]
+    fn test_chunk_layout() {
+        let mut bytes = Vec::new();
+        write_chunk(
+            &mut bytes,
+            b"STEP",
+            &[&7_u64.to_le_bytes(), &0.5_f64.to_le_bytes(), &[1, 2, 3]],
+        )
+        .unwrap();
+        write_chunk(&mut bytes, b"SPEC", &[]).unwrap();
+
+        assert_eq!(&bytes[..4], b"STEP");
+        assert_eq!(u64::from_le_bytes(bytes[4..12].try_into().unwrap()), 19);
+        assert_eq!(u64::from_le_bytes(bytes[12..20].try_into().unwrap()), 7);
+        assert_eq!(f64::from_le_bytes(bytes[20..28].try_into().unwrap()), 0.5);
+        assert_eq!(&bytes[28..31], &[1, 2, 3]);
+        // an empty chunk is still a tag and a length
+        assert_eq!(&bytes[31..35], b"SPEC");
+        assert_eq!(u64::from_le_bytes(bytes[35..43].try_into().unwrap()), 0);
+        assert_eq!(bytes.len(), 43);
+    }
+}